@@ -0,0 +1,106 @@
+use hello_wgpu::export::{self, PlyFormat};
+use hello_wgpu::mesh_format::{self, Compression, EncodeOptions};
+use hello_wgpu::model::ModelData;
+use hello_wgpu::{import, processing, Error};
+use log::LevelFilter;
+use std::path::{Path, PathBuf};
+
+const USAGE: &str =
+    "usage: model-convert [--u16 | --u32] [--deflate] [--ascii] [--weld <tolerance>]
+                     [--flat-normals | --smooth-normals] [--optimize] <input> <output>
+
+Converts .model (JSON), .obj, .gltf, .glb and .mesh files into the binary .mesh format, or
+exports them to .obj, .ply or .stl. --u16, --u32 and --deflate apply to .mesh output, --ascii
+to .ply output.
+
+--weld merges vertices that differ by at most <tolerance> and drops degenerate triangles,
+the normal options replace the normals of the model and --optimize reorders it for the vertex
+cache and less overdraw.";
+
+enum Normals {
+    Keep,
+    Flat,
+    Smooth,
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+fn save(
+    path: &Path,
+    model: &ModelData,
+    options: &EncodeOptions,
+    ply_format: PlyFormat,
+) -> hello_wgpu::Result<()> {
+    match extension(path).as_deref() {
+        Some("mesh") => mesh_format::save(path, model, options),
+        Some("obj") => export::save_obj(path, model),
+        Some("ply") => export::save_ply(path, model, ply_format),
+        Some("stl") => export::save_stl(path, model),
+        _ => Err(Error::InvalidMesh(format!(
+            "{:?} has no recognized output extension",
+            path
+        ))),
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    env_logger::builder().filter_level(LevelFilter::Info).init();
+    let mut options = EncodeOptions::default();
+    let mut ply_format = PlyFormat::BinaryLittleEndian;
+    let mut weld_tolerance = None;
+    let mut normals = Normals::Keep;
+    let mut optimize = false;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--u16" => options.index_format = Some(wgpu::IndexFormat::Uint16),
+            "--u32" => options.index_format = Some(wgpu::IndexFormat::Uint32),
+            "--deflate" => options.compression = Compression::Deflate,
+            "--ascii" => ply_format = PlyFormat::Ascii,
+            "--weld" => match args.next().map(|tolerance| tolerance.parse::<f32>()) {
+                Some(Ok(tolerance)) => weld_tolerance = Some(tolerance),
+                _ => anyhow::bail!("--weld needs a tolerance\n\n{}", USAGE),
+            },
+            "--flat-normals" => normals = Normals::Flat,
+            "--smooth-normals" => normals = Normals::Smooth,
+            "--optimize" => optimize = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ if arg.starts_with("--") => anyhow::bail!("unknown option {}\n\n{}", arg, USAGE),
+            _ => paths.push(arg.into()),
+        }
+    }
+    let (input, output) = match paths.as_slice() {
+        [input, output] => (input, output),
+        _ => anyhow::bail!(USAGE),
+    };
+
+    let mut model = import::load(input)?;
+    if let Some(tolerance) = weld_tolerance {
+        model = processing::weld(&model, tolerance)?;
+    }
+    match normals {
+        Normals::Keep => {}
+        Normals::Flat => model = processing::flat_normals(&model)?,
+        Normals::Smooth => model = processing::smooth_normals(&model)?,
+    }
+    if optimize {
+        model = processing::optimize(&model)?;
+    }
+    save(output, &model, &options, ply_format)?;
+    println!(
+        "{:?} -> {:?}: {} vertices, {} triangles",
+        input,
+        output,
+        model.positions().len(),
+        model.indices().len() / 3
+    );
+    Ok(())
+}
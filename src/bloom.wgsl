@@ -0,0 +1,68 @@
+// Blurs the bright parts of the HDR scene through a chain of ever smaller textures and adds them
+// back onto the scene.
+
+struct BloomUniform {
+  // threshold, knee, intensity
+  params: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> bloom: BloomUniform;
+
+// The blurred bright parts, at half the size of the scene.
+@group(2) @binding(0)
+var t_bloom: texture_2d<f32>;
+
+@group(2) @binding(1)
+var s_bloom: sampler;
+
+// Averages the 4x4 input texels around `uv` with four bilinear samples.
+fn box_4x4(uv: vec2<f32>) -> vec3<f32> {
+  let texel = input_texel();
+  let a = textureSample(t_input, s_input, uv + texel * vec2<f32>(-1.0, -1.0)).rgb;
+  let b = textureSample(t_input, s_input, uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+  let c = textureSample(t_input, s_input, uv + texel * vec2<f32>(-1.0, 1.0)).rgb;
+  let d = textureSample(t_input, s_input, uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+  return (a + b + c + d) * 0.25;
+}
+
+// Keeps what is brighter than the threshold, fading in quadratically over the knee below it.
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = box_4x4(in.uv);
+  let threshold = bloom.params.x;
+  let knee = bloom.params.y;
+  let brightness = max(color.r, max(color.g, color.b));
+  var soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+  soft = soft * soft / (4.0 * knee + 1e-4);
+  let contribution = max(soft, brightness - threshold) / max(brightness, 1e-4);
+  return vec4<f32>(color * contribution, 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+  return vec4<f32>(box_4x4(in.uv), 1.0);
+}
+
+// A 3x3 tent filter over the smaller level, added onto the larger one.
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = input_texel();
+  var sum = textureSample(t_input, s_input, in.uv).rgb * 4.0;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(-1.0, 0.0)).rgb * 2.0;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(1.0, 0.0)).rgb * 2.0;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(0.0, -1.0)).rgb * 2.0;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(0.0, 1.0)).rgb * 2.0;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(-1.0, -1.0)).rgb;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(1.0, -1.0)).rgb;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(-1.0, 1.0)).rgb;
+  sum = sum + textureSample(t_input, s_input, in.uv + texel * vec2<f32>(1.0, 1.0)).rgb;
+  return vec4<f32>(sum / 16.0, 1.0);
+}
+
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+  let scene = textureSample(t_input, s_input, in.uv);
+  let glow = textureSample(t_bloom, s_bloom, in.uv).rgb;
+  return vec4<f32>(scene.rgb + glow * bloom.params.z, scene.a);
+}
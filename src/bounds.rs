@@ -0,0 +1,161 @@
+//! Bounding volumes and the view frustum they are culled against.
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix4, MetricSpace, Point3, Vector4};
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box containing `points`, or an empty box at the origin if there are none.
+    pub fn from_points(points: &[[f32; 3]]) -> Self {
+        if points.is_empty() {
+            return Self {
+                min: Point3::origin(),
+                max: Point3::origin(),
+            };
+        }
+        let mut min = Point3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = Point3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for &point in points {
+            min = min.zip(Point3::from(point), f32::min);
+            max = max.zip(Point3::from(point), f32::max);
+        }
+        Self { min, max }
+    }
+
+    pub fn center(&self) -> Point3<f32> {
+        self.min.midpoint(self.max)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// A sphere containing `points`, centered on their bounding box.
+    pub fn from_points(points: &[[f32; 3]]) -> Self {
+        let center = Aabb::from_points(points).center();
+        let radius = points
+            .iter()
+            .map(|&point| Point3::from(point).distance(center))
+            .fold(0.0, f32::max);
+        Self { center, radius }
+    }
+}
+
+/// The six planes bounding what a camera sees, with normals pointing inwards.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far, each as `(normal, distance)` with a unit normal.
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a view projection matrix mapping depth to `[0, 1]`, as wgpu does.
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2].map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0.0 {
+                plane / length
+            } else {
+                plane
+            }
+        });
+        Self { planes }
+    }
+
+    fn distance(plane: Vector4<f32>, point: Point3<f32>) -> f32 {
+        plane.truncate().dot(point.to_vec()) + plane.w
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|&plane| Self::distance(plane, sphere.center) >= -sphere.radius)
+    }
+
+    /// Whether any part of `aabb` may be inside the frustum. Boxes near the frustum's corners
+    /// can be reported as intersecting when they are just outside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|&plane| {
+            // The corner furthest along the plane's normal.
+            let corner = Point3::from([0, 1, 2].map(|axis| {
+                if plane[axis] >= 0.0 {
+                    aabb.max[axis]
+                } else {
+                    aabb.min[axis]
+                }
+            }));
+            Self::distance(plane, corner) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    #[test]
+    fn bounds_contain_points() {
+        let points = [[1.0, -2.0, 0.5], [-1.0, 3.0, 0.0], [0.0, 0.0, -4.0]];
+        let aabb = Aabb::from_points(&points);
+        assert_eq!(aabb.min, Point3::new(-1.0, -2.0, -4.0));
+        assert_eq!(aabb.max, Point3::new(1.0, 3.0, 0.5));
+        assert_eq!(aabb.center(), Point3::new(0.0, 0.5, -1.75));
+
+        let sphere = BoundingSphere::from_points(&points);
+        assert_eq!(sphere.center, aabb.center());
+        for &point in &points {
+            assert!(Point3::from(point).distance(sphere.center) <= sphere.radius);
+        }
+        assert_eq!(Aabb::from_points(&[]).max, Point3::origin());
+    }
+
+    #[test]
+    fn culls_against_camera_frustum() {
+        let camera = Camera::new(800, 600);
+        let frustum = camera.frustum();
+        let sphere_at = |distance: f32, side: f32, radius: f32| {
+            let right = camera
+                .forward()
+                .cross(cgmath::Vector3::unit_y())
+                .normalize();
+            BoundingSphere {
+                center: camera.eye() + camera.forward() * distance + right * side,
+                radius,
+            }
+        };
+        let aabb_of = |sphere: BoundingSphere| {
+            let extent = cgmath::Vector3::new(sphere.radius, sphere.radius, sphere.radius);
+            Aabb {
+                min: sphere.center - extent,
+                max: sphere.center + extent,
+            }
+        };
+
+        let visible = [sphere_at(5.0, 0.0, 0.5), sphere_at(5.0, 2.5, 0.5)];
+        let hidden = [
+            sphere_at(-5.0, 0.0, 0.5),
+            sphere_at(5.0, 10.0, 0.5),
+            sphere_at(0.05, 0.0, 0.01),
+            sphere_at(200.0, 0.0, 1.0),
+        ];
+        for sphere in visible {
+            assert!(frustum.intersects_sphere(&sphere), "{:?}", sphere);
+            assert!(frustum.intersects_aabb(&aabb_of(sphere)), "{:?}", sphere);
+        }
+        for sphere in hidden {
+            assert!(!frustum.intersects_sphere(&sphere), "{:?}", sphere);
+            assert!(!frustum.intersects_aabb(&aabb_of(sphere)), "{:?}", sphere);
+        }
+    }
+}
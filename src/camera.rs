@@ -1,3 +1,5 @@
+use crate::bounds::{BoundingSphere, Frustum};
+use crate::picking::Ray;
 use cgmath::*;
 
 pub struct Camera {
@@ -11,18 +13,38 @@ pub struct Camera {
 }
 
 impl Camera {
+    fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+        OPENGL_TO_WGPU_MATRIX * proj
+    }
+
     fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        self.build_projection_matrix() * view
+    }
+
+    /// The matrix taking world positions to clip space.
+    pub fn view_projection(&self) -> cgmath::Matrix4<f32> {
+        self.build_view_projection_matrix()
+    }
+
+    /// The rotation of the view, taking world directions to view space.
+    pub fn view_rotation(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_to_rh(Point3::origin(), self.target - self.eye, self.up)
+    }
+
+    /// The view projection without the translation of the view, which keeps things infinitely
+    /// far away like the sky at the same place on screen while the camera moves.
+    pub fn rotation_view_projection(&self) -> cgmath::Matrix4<f32> {
+        self.build_projection_matrix() * self.view_rotation()
     }
 
-    pub fn new(config: &wgpu::SurfaceConfiguration) -> Self {
+    pub fn new(width: u32, height: u32) -> Self {
         Self {
             eye: (0.0, 1.0, 2.0).into(),
             target: (0.0, 0.0, 0.0).into(),
             up: cgmath::Vector3::unit_y(),
-            aspect: config.width as f32 / config.height as f32,
+            aspect: width as f32 / height as f32,
             fovy: 45.0,
             znear: 0.1,
             zfar: 100.0,
@@ -32,7 +54,80 @@ impl Camera {
     pub fn to_uniform(&self) -> CameraUniform {
         CameraUniform {
             view_proj: self.build_view_projection_matrix().into(),
+            position: self.eye.to_homogeneous().into(),
+        }
+    }
+
+    /// The planes bounding everything the camera sees.
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.build_view_projection_matrix())
+    }
+
+    /// The ray from the camera through the pixel at `x`, `y` of a `width` by `height` viewport.
+    pub fn cursor_ray(&self, x: f64, y: f64, width: u32, height: u32) -> Ray {
+        let ndc_x = (2.0 * x / width as f64 - 1.0) as f32;
+        let ndc_y = (1.0 - 2.0 * y / height as f64) as f32;
+        let inverse = self
+            .build_view_projection_matrix()
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let unproject = |depth: f32| {
+            let point = inverse * Vector4::new(ndc_x, ndc_y, depth, 1.0);
+            Point3::from_homogeneous(point)
+        };
+        let near = unproject(0.0);
+        Ray::new(near, unproject(1.0) - near)
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.eye
+    }
+
+    /// The unit vector the camera looks along.
+    pub fn forward(&self) -> Vector3<f32> {
+        (self.target - self.eye).normalize()
+    }
+
+    /// Distance of the near plane.
+    pub fn znear(&self) -> f32 {
+        self.znear
+    }
+
+    /// Distance of the far plane.
+    pub fn zfar(&self) -> f32 {
+        self.zfar
+    }
+
+    /// The corners of the part of the view frustum between the distances `near` and `far` along
+    /// [`Camera::forward`], the four near ones first.
+    pub fn frustum_slice_corners(&self, near: f32, far: f32) -> [Point3<f32>; 8] {
+        let forward = self.forward();
+        let right = forward.cross(self.up).normalize();
+        let up = right.cross(forward);
+        let tan_half_fovy = (Deg(self.fovy) / 2.0).tan();
+        let mut corners = [self.eye; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let distance = if i < 4 { near } else { far };
+            let half_height = distance * tan_half_fovy;
+            let half_width = half_height * self.aspect;
+            let x = if i & 1 == 0 { -half_width } else { half_width };
+            let y = if i & 2 == 0 {
+                -half_height
+            } else {
+                half_height
+            };
+            *corner = self.eye + forward * distance + right * x + up * y;
         }
+        corners
+    }
+
+    /// The fraction of the viewport height covered by `sphere`, as seen through the projection.
+    pub fn screen_size(&self, sphere: &BoundingSphere) -> f32 {
+        let distance = self.eye.distance(sphere.center);
+        if distance <= sphere.radius {
+            return f32::INFINITY;
+        }
+        sphere.radius / (distance * (cgmath::Deg(self.fovy) / 2.0).tan())
     }
 
     pub fn pan<T: Clone + Into<cgmath::Vector3<f32>>>(&mut self, mov: T) {
@@ -43,8 +138,8 @@ impl Camera {
         let upwards = cgmath::Vector3::unit_y();
         let sideways = forwards.cross(upwards);
         let offset = forwards * -vmov.z + sideways * vmov.x + upwards * vmov.y;
-        self.eye += offset.clone().into();
-        self.target += offset.clone().into();
+        self.eye += offset;
+        self.target += offset;
     }
 
     pub fn rotate_h(&mut self, angle: f32) {
@@ -69,10 +164,27 @@ impl Camera {
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     view_proj: [[f32; 4]; 4],
+    /// The eye position, with a `w` of 1.
+    position: [f32; 4],
+}
+
+/// The view projection of a light, which is the camera of the pass rendering its shadow map.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightSpaceUniform {
+    view_proj: [[f32; 4]; 4],
+}
+
+impl LightSpaceUniform {
+    pub fn new(view_proj: Matrix4<f32>) -> Self {
+        Self {
+            view_proj: view_proj.into(),
+        }
+    }
 }
 
 #[rustfmt::skip]
-const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+pub(crate) const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
     0.0, 0.0, 0.5, 0.0,
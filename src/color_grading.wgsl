@@ -0,0 +1,34 @@
+// Replaces colors by looking them up in a 3D table.
+
+struct GradingUniform {
+  // intensity, size of the table
+  params: vec4<f32>,
+  // 1 if the input is linear, while the table maps sRGB colors
+  mode: vec4<u32>
+};
+
+@group(1) @binding(0)
+var<uniform> grading: GradingUniform;
+
+@group(1) @binding(1)
+var t_lut: texture_3d<f32>;
+
+@group(1) @binding(2)
+var s_lut: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = textureSample(t_input, s_input, in.uv);
+  var lookup = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+  if (grading.mode.x != 0u) {
+    lookup = linear_to_srgb(lookup);
+  }
+  // Between the centers of the first and last texels.
+  let size = grading.params.y;
+  let coordinates = lookup * (size - 1.0) / size + 0.5 / size;
+  var graded = textureSampleLevel(t_lut, s_lut, coordinates, 0.0).rgb;
+  if (grading.mode.x != 0u) {
+    graded = srgb_to_linear(graded);
+  }
+  return vec4<f32>(mix(color.rgb, graded, grading.params.x), color.a);
+}
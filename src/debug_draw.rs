@@ -0,0 +1,409 @@
+//! Lines and shapes queued by any part of the app for one frame, drawn over the scene.
+
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::camera::Camera;
+use crate::error::Result;
+use crate::picking::Ray;
+use crate::renderer::create_shader_module;
+use cgmath::{Point3, Vector3};
+use wgpu::include_wgsl;
+
+/// Segments of the circles of [`DebugDraw::sphere`].
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Vertices the buffer of [`DebugDrawPass`] holds at first.
+const INITIAL_CAPACITY: usize = 1024;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [f32; 3],
+}
+
+impl LineVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+            ],
+        }
+    }
+}
+
+/// The edges of a box with `corners` ordered by the bits of their index, bit 0 choosing between
+/// two opposite faces, bit 1 between two others and bit 2 between the last two.
+const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// Lines queued for the current frame, see [`Renderer::debug_draw_mut`]. Colors are linear RGB
+/// and the lines are drawn over everything else.
+///
+/// [`Renderer::debug_draw_mut`]: crate::Renderer::debug_draw_mut
+#[derive(Clone, Debug, Default)]
+pub struct DebugDraw {
+    vertices: Vec<LineVertex>,
+}
+
+impl DebugDraw {
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: [f32; 3]) {
+        self.vertices.push(LineVertex {
+            position: from.into(),
+            color,
+        });
+        self.vertices.push(LineVertex {
+            position: to.into(),
+            color,
+        });
+    }
+
+    /// `ray` from its origin up to `length`.
+    pub fn ray(&mut self, ray: &Ray, length: f32, color: [f32; 3]) {
+        self.line(ray.origin, ray.origin + ray.direction * length, color);
+    }
+
+    fn box_corners(&mut self, corners: &[Point3<f32>; 8], color: [f32; 3]) {
+        for &(from, to) in &BOX_EDGES {
+            self.line(corners[from], corners[to], color);
+        }
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 3]) {
+        let mut corners = [aabb.min; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            for axis in 0..3 {
+                if i & (1 << axis) != 0 {
+                    corner[axis] = aabb.max[axis];
+                }
+            }
+        }
+        self.box_corners(&corners, color);
+    }
+
+    /// A circle around `center` in the plane spanned by the unit vectors `u` and `v`.
+    fn circle(
+        &mut self,
+        center: Point3<f32>,
+        radius: f32,
+        (u, v): (Vector3<f32>, Vector3<f32>),
+        color: [f32; 3],
+    ) {
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// `sphere` as its circles around the three axes.
+    pub fn sphere(&mut self, sphere: &BoundingSphere, color: [f32; 3]) {
+        let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+        for plane in [(x, y), (y, z), (z, x)] {
+            self.circle(sphere.center, sphere.radius, plane, color);
+        }
+    }
+
+    /// The axes at `origin`, `size` long: X red, Y green and Z blue.
+    pub fn axes(&mut self, origin: Point3<f32>, size: f32) {
+        self.line(origin, origin + Vector3::unit_x() * size, [1.0, 0.0, 0.0]);
+        self.line(origin, origin + Vector3::unit_y() * size, [0.0, 1.0, 0.0]);
+        self.line(origin, origin + Vector3::unit_z() * size, [0.0, 0.0, 1.0]);
+    }
+
+    /// The edges of everything `camera` sees, up to its far plane.
+    pub fn frustum(&mut self, camera: &Camera, color: [f32; 3]) {
+        self.box_corners(
+            &camera.frustum_slice_corners(camera.znear(), camera.zfar()),
+            color,
+        );
+    }
+
+    /// A grid on the XZ plane around `center`, `size` wide with `divisions` cells per side.
+    pub fn grid(&mut self, center: Point3<f32>, size: f32, divisions: u32, color: [f32; 3]) {
+        let divisions = divisions.max(1);
+        let half = size / 2.0;
+        for i in 0..=divisions {
+            let offset = i as f32 / divisions as f32 * size - half;
+            self.line(
+                center + Vector3::new(offset, 0.0, -half),
+                center + Vector3::new(offset, 0.0, half),
+                color,
+            );
+            self.line(
+                center + Vector3::new(-half, 0.0, offset),
+                center + Vector3::new(half, 0.0, offset),
+                color,
+            );
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    /// Removes everything queued, which the renderer does after every frame.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
+
+/// Draws a [`DebugDraw`] over the frame from a vertex buffer that grows as needed.
+pub(crate) struct DebugDrawPass {
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    /// Vertices `vertex_buffer` holds.
+    capacity: usize,
+    /// Vertices written by the last [`DebugDrawPass::prepare`].
+    len: u32,
+    /// Whether the frame stores colors as they are, so that they have to be encoded as sRGB.
+    encode_srgb: bool,
+}
+
+impl DebugDrawPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, include_wgsl!("debug_draw.wgsl"))?;
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Debug Draw Pipeline Layout"),
+            bind_group_layouts: &[camera_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Debug Draw Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[LineVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Ok(Self {
+            pipeline,
+            vertex_buffer: Self::create_vertex_buffer(device, INITIAL_CAPACITY),
+            capacity: INITIAL_CAPACITY,
+            len: 0,
+            encode_srgb: !format.describe().srgb,
+        })
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug Draw Buffer"),
+            size: (capacity * std::mem::size_of::<LineVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Writes the lines of `debug_draw`, growing the buffer if they don't fit.
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, debug_draw: &DebugDraw) {
+        let vertices = &debug_draw.vertices;
+        self.len = vertices.len() as u32;
+        if vertices.is_empty() {
+            return;
+        }
+        if vertices.len() > self.capacity {
+            self.capacity = vertices.len().next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+        }
+        let encode = |c: f32| match c <= 0.0031308 {
+            true => c * 12.92,
+            false => 1.055 * c.powf(1.0 / 2.4) - 0.055,
+        };
+        let encoded: Vec<LineVertex>;
+        let vertices = match self.encode_srgb {
+            true => {
+                encoded = vertices
+                    .iter()
+                    .map(|vertex| LineVertex {
+                        color: vertex.color.map(|c| encode(c.max(0.0))),
+                        ..*vertex
+                    })
+                    .collect();
+                &encoded
+            }
+            false => vertices,
+        };
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(vertices));
+    }
+
+    /// Draws the lines of the last [`DebugDrawPass::prepare`] onto `target`.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        camera_bind_group: &wgpu::BindGroup,
+    ) {
+        if self.len == 0 {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Debug Draw Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.len, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::InnerSpace;
+
+    const RED: [f32; 3] = [1.0, 0.0, 0.0];
+
+    fn positions(debug_draw: &DebugDraw) -> Vec<Point3<f32>> {
+        debug_draw
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position.into())
+            .collect()
+    }
+
+    #[test]
+    fn batches_lines_as_vertex_pairs() {
+        let mut debug_draw = DebugDraw::default();
+        assert!(debug_draw.is_empty());
+        debug_draw.line(Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 2.0, 3.0), RED);
+        debug_draw.axes(Point3::new(1.0, 1.0, 1.0), 2.0);
+        assert_eq!(debug_draw.vertices.len(), 8);
+        assert_eq!(debug_draw.vertices[1].position, [1.0, 2.0, 3.0]);
+        assert_eq!(debug_draw.vertices[1].color, RED);
+        // The Y axis, in green.
+        assert_eq!(debug_draw.vertices[4].position, [1.0, 1.0, 1.0]);
+        assert_eq!(debug_draw.vertices[5].position, [1.0, 3.0, 1.0]);
+        assert_eq!(debug_draw.vertices[5].color, [0.0, 1.0, 0.0]);
+
+        debug_draw.grid(Point3::new(0.0, 0.0, 0.0), 4.0, 4, RED);
+        assert_eq!(debug_draw.vertices.len(), 8 + 5 * 2 * 2);
+        debug_draw.sphere(
+            &BoundingSphere {
+                center: Point3::new(1.0, 0.0, 0.0),
+                radius: 2.0,
+            },
+            RED,
+        );
+        let sphere = &positions(&debug_draw)[28..];
+        assert_eq!(sphere.len(), 3 * CIRCLE_SEGMENTS * 2);
+        assert!(sphere
+            .iter()
+            .all(|&p| ((p - Point3::new(1.0, 0.0, 0.0)).magnitude() - 2.0).abs() < 1e-5));
+    }
+
+    #[test]
+    fn draws_the_edges_of_boxes() {
+        let mut debug_draw = DebugDraw::default();
+        let aabb = Aabb {
+            min: Point3::new(-1.0, 0.0, 2.0),
+            max: Point3::new(1.0, 3.0, 6.0),
+        };
+        debug_draw.aabb(&aabb, RED);
+        let positions = positions(&debug_draw);
+        assert_eq!(positions.len(), 24);
+        let mut per_axis = [0; 3];
+        for edge in positions.chunks_exact(2) {
+            // Every edge runs along one axis, from a corner to a corner.
+            let delta = edge[1] - edge[0];
+            let axes: Vec<usize> = (0..3).filter(|&axis| delta[axis] != 0.0).collect();
+            assert_eq!(axes.len(), 1);
+            assert_eq!(delta[axes[0]], aabb.max[axes[0]] - aabb.min[axes[0]]);
+            per_axis[axes[0]] += 1;
+            for p in edge {
+                for axis in 0..3 {
+                    assert!(p[axis] == aabb.min[axis] || p[axis] == aabb.max[axis]);
+                }
+            }
+        }
+        assert_eq!(per_axis, [4, 4, 4]);
+    }
+
+    #[test]
+    fn draws_the_edges_of_frusta() {
+        let mut debug_draw = DebugDraw::default();
+        let camera = Camera::new(800, 600);
+        debug_draw.frustum(&camera, RED);
+        let positions = positions(&debug_draw);
+        assert_eq!(positions.len(), 24);
+        let corners = camera.frustum_slice_corners(camera.znear(), camera.zfar());
+        for corner in &corners {
+            let uses = positions
+                .iter()
+                .filter(|&&p| (p - corner).magnitude() < 1e-4)
+                .count();
+            assert_eq!(uses, 3);
+        }
+        // Four edges run from the near plane to the far plane, the others lie in either.
+        let depth = |p: Point3<f32>| (p - camera.eye()).dot(camera.forward());
+        let lengthwise = positions
+            .chunks_exact(2)
+            .filter(|edge| (depth(edge[1]) - depth(edge[0])).abs() > 1e-3)
+            .count();
+        assert_eq!(lengthwise, 4);
+    }
+
+    #[test]
+    fn clears_between_frames() {
+        let mut debug_draw = DebugDraw::default();
+        debug_draw.axes(Point3::new(0.0, 0.0, 0.0), 1.0);
+        debug_draw.clear();
+        assert!(debug_draw.is_empty());
+        debug_draw.line(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 0.0, 1.0), RED);
+        assert_eq!(debug_draw.vertices.len(), 2);
+        assert_eq!(debug_draw.vertices[0].color, RED);
+    }
+}
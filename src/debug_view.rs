@@ -0,0 +1,295 @@
+//! Views of the geometry of models for debugging meshes, drawn instead of their shading.
+
+use crate::camera::Camera;
+use crate::error::Result;
+use crate::model::{Model, Vertex};
+use crate::renderer::create_shader_module;
+use std::collections::HashMap;
+use wgpu::include_wgsl;
+
+/// Length of the lines of [`DebugView::Normals`] in world units.
+const NORMAL_LENGTH: f32 = 0.1;
+
+/// What the models are drawn as, instead of their shading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugView {
+    /// The edges of the triangles.
+    Wireframe,
+    /// Normals as colors, with a line along the normal of every vertex.
+    Normals,
+    /// A checkerboard over the texture coordinates, tinted by them.
+    UvChecker,
+    /// The fractional part of the world position as colors, which repeats every unit.
+    WorldPosition,
+    /// The distance from the camera, logarithmically from black at the near plane to white at
+    /// the far plane.
+    Depth,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 5] = [
+        DebugView::Wireframe,
+        DebugView::Normals,
+        DebugView::UvChecker,
+        DebugView::WorldPosition,
+        DebugView::Depth,
+    ];
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct DebugUniform {
+    /// Near plane, far plane, length of normal lines.
+    params: [f32; 4],
+}
+
+/// How the wireframe is drawn, depending on the features of the device.
+enum Wireframe {
+    /// Rasterizing only the edges with `PolygonMode::Line`.
+    Lines(wgpu::RenderPipeline),
+    /// Drawing whole triangles but keeping only the pixels near their edges, for models with
+    /// 16 and 32 bit indices.
+    Barycentric {
+        u16: wgpu::RenderPipeline,
+        u32: wgpu::RenderPipeline,
+    },
+}
+
+/// Draws models in a [`DebugView`] from their vertex and index buffers.
+pub(crate) struct DebugPass {
+    normals: wgpu::RenderPipeline,
+    normal_lines: wgpu::RenderPipeline,
+    uv_checker: wgpu::RenderPipeline,
+    world_position: wgpu::RenderPipeline,
+    depth: wgpu::RenderPipeline,
+    wireframe: Wireframe,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    mesh_bind_group_layout: wgpu::BindGroupLayout,
+    /// Binds the buffers of the models of the current frame for [`Wireframe::Barycentric`].
+    mesh_bind_groups: HashMap<*const Model, wgpu::BindGroup>,
+}
+
+impl DebugPass {
+    /// Creates the pipelines for the main pass, testing depth and marking the stencil with
+    /// `depth_stencil`.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        depth_stencil: wgpu::DepthStencilState,
+        camera_layout: &wgpu::BindGroupLayout,
+        uniform_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, include_wgsl!("debug_view.wgsl"))?;
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug View Buffer"),
+            size: std::mem::size_of::<DebugUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: uniform_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("debug_view_bind_group"),
+        });
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let mesh_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[storage_entry(0), storage_entry(1)],
+                label: Some("debug_mesh_bind_group_layout"),
+            });
+
+        let vertex_layout = [Vertex::desc()];
+        let instance_layout = [wgpu::VertexBufferLayout {
+            step_mode: wgpu::VertexStepMode::Instance,
+            ..Vertex::desc()
+        }];
+        let layouts = [camera_layout, uniform_layout];
+        let mesh_layouts = [camera_layout, uniform_layout, &mesh_bind_group_layout];
+        let pipeline = |vertex_entry,
+                        fragment_entry,
+                        buffers: &[wgpu::VertexBufferLayout],
+                        bind_group_layouts: &[&wgpu::BindGroupLayout],
+                        primitive: wgpu::PrimitiveState| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Debug View Pipeline Layout"),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(fragment_entry),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: vertex_entry,
+                    buffers,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: fragment_entry,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive,
+                depth_stencil: Some(depth_stencil.clone()),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            })
+        };
+        let surface = |fragment_entry| {
+            pipeline(
+                "vs_main",
+                fragment_entry,
+                &vertex_layout,
+                &layouts,
+                wgpu::PrimitiveState {
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+            )
+        };
+        // Both sides, so that the edges behind are visible too.
+        let wireframe = if device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+        {
+            Wireframe::Lines(pipeline(
+                "vs_main",
+                "fs_wireframe",
+                &vertex_layout,
+                &layouts,
+                wgpu::PrimitiveState {
+                    polygon_mode: wgpu::PolygonMode::Line,
+                    ..Default::default()
+                },
+            ))
+        } else {
+            let barycentric = |vertex_entry| {
+                pipeline(
+                    vertex_entry,
+                    "fs_barycentric",
+                    &[],
+                    &mesh_layouts,
+                    wgpu::PrimitiveState::default(),
+                )
+            };
+            Wireframe::Barycentric {
+                u16: barycentric("vs_barycentric_u16"),
+                u32: barycentric("vs_barycentric_u32"),
+            }
+        };
+        Ok(Self {
+            normals: surface("fs_normals"),
+            normal_lines: pipeline(
+                "vs_normal_line",
+                "fs_normal_line",
+                &instance_layout,
+                &layouts,
+                wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::LineList,
+                    ..Default::default()
+                },
+            ),
+            uv_checker: surface("fs_uv_checker"),
+            world_position: surface("fs_world_position"),
+            depth: surface("fs_depth"),
+            wireframe,
+            uniform_buffer,
+            uniform_bind_group,
+            mesh_bind_group_layout,
+            mesh_bind_groups: HashMap::new(),
+        })
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let uniform = DebugUniform {
+            params: [camera.znear(), camera.zfar(), NORMAL_LENGTH, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Prepares drawing `models` in `view`, which must be called every frame before
+    /// [`DebugPass::draw`].
+    pub fn prepare(&mut self, device: &wgpu::Device, view: DebugView, models: &[&Model]) {
+        self.mesh_bind_groups.clear();
+        if view != DebugView::Wireframe || matches!(self.wireframe, Wireframe::Lines(_)) {
+            return;
+        }
+        for &model in models {
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.mesh_bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: model.vertex_buffer().as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: model.index_buffer().as_entire_binding(),
+                    },
+                ],
+                label: Some("debug_mesh_bind_group"),
+            });
+            self.mesh_bind_groups.insert(model, bind_group);
+        }
+    }
+
+    /// Draws `model` in `view`, with the camera bound by `camera_bind_group`.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        view: DebugView,
+        model: &'a Model,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.uniform_bind_group, &[]);
+        let pipeline = match (view, &self.wireframe) {
+            (DebugView::Wireframe, Wireframe::Barycentric { u16, u32 }) => {
+                let bind_group = match self.mesh_bind_groups.get(&(model as *const Model)) {
+                    Some(bind_group) => bind_group,
+                    None => return,
+                };
+                render_pass.set_pipeline(match model.index_format() {
+                    wgpu::IndexFormat::Uint16 => u16,
+                    wgpu::IndexFormat::Uint32 => u32,
+                });
+                render_pass.set_bind_group(2, bind_group, &[]);
+                render_pass.draw(0..model.num_vertices(), 0..1);
+                return;
+            }
+            (DebugView::Wireframe, Wireframe::Lines(lines)) => lines,
+            (DebugView::Normals, _) => &self.normals,
+            (DebugView::UvChecker, _) => &self.uv_checker,
+            (DebugView::WorldPosition, _) => &self.world_position,
+            (DebugView::Depth, _) => &self.depth,
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_vertex_buffer(0, model.vertex_buffer().slice(..));
+        render_pass.set_index_buffer(model.index_buffer().slice(..), model.index_format());
+        render_pass.draw_indexed(0..model.num_vertices(), 0, 0..1);
+        if view == DebugView::Normals {
+            render_pass.set_pipeline(&self.normal_lines);
+            render_pass.draw(0..2, 0..model.vertex_count());
+        }
+    }
+}
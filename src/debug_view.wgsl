@@ -0,0 +1,154 @@
+// Views of the geometry of models for debugging meshes.
+
+struct Vertex {
+  @location(0) position: vec3<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) normal: vec3<f32>
+};
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>,
+  position: vec4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct DebugUniform {
+  // near plane, far plane, length of normal lines
+  params: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> debug: DebugUniform;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) world_position: vec3<f32>,
+  @location(1) uv: vec2<f32>,
+  @location(2) normal: vec3<f32>
+};
+
+@vertex
+fn vs_main(vertex: Vertex) -> VertexOutput {
+  var out: VertexOutput;
+  out.clip_position = camera.view_proj * vec4<f32>(vertex.position, 1.0);
+  out.world_position = vertex.position;
+  out.uv = vertex.uv;
+  out.normal = vertex.normal;
+  return out;
+}
+
+@fragment
+fn fs_normals(in: VertexOutput) -> @location(0) vec4<f32> {
+  // Models without normals have zero ones.
+  if (dot(in.normal, in.normal) == 0.0) {
+    return vec4<f32>(0.5, 0.5, 0.5, 1.0);
+  }
+  return vec4<f32>(normalize(in.normal) * 0.5 + 0.5, 1.0);
+}
+
+@fragment
+fn fs_uv_checker(in: VertexOutput) -> @location(0) vec4<f32> {
+  let cells = floor(in.uv * 8.0);
+  let parity = fract((cells.x + cells.y) * 0.5) * 2.0;
+  let checker = mix(0.2, 0.9, parity);
+  // Tinted by the coordinates, which shows their direction.
+  let tint = mix(vec3<f32>(1.0), vec3<f32>(in.uv, 1.0 - in.uv.x), 0.5);
+  return vec4<f32>(checker * tint, 1.0);
+}
+
+@fragment
+fn fs_world_position(in: VertexOutput) -> @location(0) vec4<f32> {
+  return vec4<f32>(fract(in.world_position), 1.0);
+}
+
+@fragment
+fn fs_depth(in: VertexOutput) -> @location(0) vec4<f32> {
+  let near = debug.params.x;
+  let far = debug.params.y;
+  let depth = near * far / (far - in.clip_position.z * (far - near));
+  // Logarithmic, so that near and far objects can both be told apart.
+  let shade = clamp(log(depth / near) / log(far / near), 0.0, 1.0);
+  return vec4<f32>(vec3<f32>(shade), 1.0);
+}
+
+let WIREFRAME_COLOR: vec3<f32> = vec3<f32>(0.1, 1.0, 0.3);
+
+@fragment
+fn fs_wireframe(in: VertexOutput) -> @location(0) vec4<f32> {
+  return vec4<f32>(WIREFRAME_COLOR, 1.0);
+}
+
+// Without `PolygonMode::Line`, triangles are drawn whole and only the pixels near their edges
+// are kept, found from the barycentric coordinates of the pixels. The corners of the triangles
+// are read from the vertex and index buffers of the model, since indexed vertices are shared
+// between triangles and can't have barycentric coordinates of their own.
+
+@group(2) @binding(0)
+var<storage, read> vertices: array<f32>;
+
+@group(2) @binding(1)
+var<storage, read> indices: array<u32>;
+
+struct BarycentricOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) barycentric: vec3<f32>
+};
+
+fn barycentric_corner(index: u32, vertex_index: u32) -> BarycentricOutput {
+  // A vertex is a position, UV coordinates and a normal.
+  let base = index * 8u;
+  let position = vec3<f32>(vertices[base], vertices[base + 1u], vertices[base + 2u]);
+  let corner = vertex_index % 3u;
+  var out: BarycentricOutput;
+  out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+  out.barycentric = vec3<f32>(
+    select(0.0, 1.0, corner == 0u),
+    select(0.0, 1.0, corner == 1u),
+    select(0.0, 1.0, corner == 2u)
+  );
+  return out;
+}
+
+@vertex
+fn vs_barycentric_u16(@builtin(vertex_index) vertex_index: u32) -> BarycentricOutput {
+  // Two indices per word, the first one in the low half.
+  let word = indices[vertex_index / 2u];
+  let index = (word >> (16u * (vertex_index % 2u))) & 0xffffu;
+  return barycentric_corner(index, vertex_index);
+}
+
+@vertex
+fn vs_barycentric_u32(@builtin(vertex_index) vertex_index: u32) -> BarycentricOutput {
+  return barycentric_corner(indices[vertex_index], vertex_index);
+}
+
+@fragment
+fn fs_barycentric(in: BarycentricOutput) -> @location(0) vec4<f32> {
+  let width = fwidth(in.barycentric);
+  let inside = smoothstep(vec3<f32>(0.0), width * 1.5, in.barycentric);
+  let coverage = 1.0 - min(min(inside.x, inside.y), inside.z);
+  if (coverage <= 0.0) {
+    discard;
+  }
+  return vec4<f32>(WIREFRAME_COLOR, coverage);
+}
+
+struct LineOutput {
+  @builtin(position) clip_position: vec4<f32>
+};
+
+// One instance per vertex, from its position to the end of its normal.
+@vertex
+fn vs_normal_line(@builtin(vertex_index) end: u32, vertex: Vertex) -> LineOutput {
+  let position = vertex.position + vertex.normal * debug.params.z * f32(end);
+  var out: LineOutput;
+  out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+  return out;
+}
+
+@fragment
+fn fs_normal_line(in: LineOutput) -> @location(0) vec4<f32> {
+  return vec4<f32>(1.0, 0.8, 0.1, 1.0);
+}
@@ -0,0 +1,524 @@
+//! Image based lighting: environment cubemaps converted from equirectangular HDR images and
+//! convolved into the diffuse and specular light they cast.
+
+use crate::error::{Error, Result};
+use crate::renderer::create_shader_module;
+use half::f16;
+use image::codecs::hdr::HdrDecoder;
+use std::fs::File;
+use std::io::BufReader;
+use std::num::NonZeroU32;
+use std::path::Path;
+use wgpu::include_wgsl;
+use wgpu::util::DeviceExt;
+
+/// The format of every baked texture, which can be both filtered and rendered into.
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const BRDF_LUT_SIZE: u32 = 128;
+const BRDF_LUT_SAMPLES: u32 = 512;
+
+/// A high dynamic range image in linear RGB.
+pub struct HdrImage {
+    width: u32,
+    height: u32,
+    /// Row by row, from the top.
+    pixels: Vec<[f32; 3]>,
+}
+
+impl HdrImage {
+    pub fn new(width: u32, height: u32, pixels: Vec<[f32; 3]>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Loads a Radiance `.hdr` file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let decode_error = |source| Error::ImageDecode {
+            name: path.display().to_string(),
+            source,
+        };
+        let file = File::open(path).map_err(|err| Error::io(path, err))?;
+        let decoder = HdrDecoder::new(BufReader::new(file)).map_err(decode_error)?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(decode_error)?;
+        Ok(Self::new(
+            metadata.width,
+            metadata.height,
+            pixels.into_iter().map(|pixel| pixel.0).collect(),
+        ))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[[f32; 3]] {
+        &self.pixels
+    }
+
+    /// The image at half the size, each pixel averaging up to four of this one.
+    fn halved(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0; 3];
+                let mut count = 0.0;
+                for source_y in (2 * y)..(2 * y + 2).min(self.height) {
+                    for source_x in (2 * x)..(2 * x + 2).min(self.width) {
+                        let pixel = self.pixels[(source_y * self.width + source_x) as usize];
+                        for (sum, channel) in sum.iter_mut().zip(pixel) {
+                            *sum += channel;
+                        }
+                        count += 1.0;
+                    }
+                }
+                pixels.push(sum.map(|channel| channel / count));
+            }
+        }
+        Self::new(width, height, pixels)
+    }
+}
+
+pub struct EnvironmentOptions {
+    /// Size of the faces of the environment cubemap.
+    pub cube_size: u32,
+    /// Size of the faces of the irradiance cubemap. Irradiance varies slowly, so this can be
+    /// small.
+    pub irradiance_size: u32,
+    /// Size of the faces of the sharpest level of the prefiltered specular cubemap.
+    pub specular_size: u32,
+    /// Mip levels of the prefiltered specular cubemap, with roughness going from 0 at the first
+    /// to 1 at the last.
+    pub specular_levels: u32,
+    /// GGX samples taken per texel of the prefiltered specular cubemap.
+    pub specular_samples: u32,
+}
+
+impl Default for EnvironmentOptions {
+    fn default() -> Self {
+        Self {
+            cube_size: 512,
+            irradiance_size: 32,
+            specular_size: 128,
+            specular_levels: 5,
+            specular_samples: 256,
+        }
+    }
+}
+
+/// The uniform of `environment.wgsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct BakeParams {
+    /// Roughness, source mip level, source face size and sample count.
+    values: [f32; 4],
+    /// The cube face drawn into.
+    face: [u32; 4],
+}
+
+/// Draws the shaders of `environment.wgsl` into textures.
+struct Baker<'a> {
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    shader: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+}
+
+impl<'a> Baker<'a> {
+    fn new(device: &'a wgpu::Device, queue: &'a wgpu::Queue) -> Result<Self> {
+        let shader = create_shader_module(device, include_wgsl!("environment.wgsl"))?;
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(Self {
+            device,
+            queue,
+            shader,
+            sampler,
+        })
+    }
+
+    /// A pipeline drawing with the fragment shader `entry_point`. Its bind group layout is
+    /// derived from the bindings the entry point uses.
+    fn pipeline(&self, entry_point: &str) -> wgpu::RenderPipeline {
+        self.device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: None,
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+    }
+
+    /// Draws `pipeline` into each of the first `layers` layers of mip level `level` of `target`,
+    /// with the bindings `entries` and the uniform made of `values` and the layer. The bindings
+    /// must be exactly those the pipeline uses.
+    fn draw(
+        &self,
+        pipeline: &wgpu::RenderPipeline,
+        entries: &[wgpu::BindGroupEntry],
+        values: [f32; 4],
+        target: &wgpu::Texture,
+        level: u32,
+        layers: u32,
+    ) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Environment Encoder"),
+            });
+        for layer in 0..layers {
+            let params = self
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Environment Params Buffer"),
+                    contents: bytemuck::cast_slice(&[BakeParams {
+                        values,
+                        face: [layer, 0, 0, 0],
+                    }]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+            let mut entries = entries.to_vec();
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: params.as_entire_binding(),
+            });
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &entries,
+                label: Some("environment_bake_bind_group"),
+            });
+            let view = target.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: level,
+                mip_level_count: NonZeroU32::new(1),
+                base_array_layer: layer,
+                array_layer_count: NonZeroU32::new(1),
+                ..Default::default()
+            });
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Environment Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    fn create_texture(
+        &self,
+        label: &str,
+        size: u32,
+        layers: u32,
+        mip_level_count: u32,
+    ) -> wgpu::Texture {
+        self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        })
+    }
+
+    /// Converts the equirectangular `image` into a cubemap with faces of `size` and a full mip
+    /// chain.
+    fn equirect_to_cube(&self, image: &HdrImage, size: u32) -> wgpu::Texture {
+        let max_dimension = self.device.limits().max_texture_dimension_2d;
+        // Images too large for a texture are shrunk first.
+        let mut halved: Option<HdrImage> = None;
+        loop {
+            let current = halved.as_ref().unwrap_or(image);
+            if current.width() <= max_dimension && current.height() <= max_dimension {
+                break;
+            }
+            halved = Some(current.halved());
+        }
+        let image = halved.as_ref().unwrap_or(image);
+        let pixels: Vec<f16> = image
+            .pixels()
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            // Brighter texels would become infinite, which the convolutions spread everywhere.
+            .map(|channel| f16::from_f32(channel.min(f16::MAX.to_f32())))
+            .collect();
+        let equirect = self.device.create_texture_with_data(
+            self.queue,
+            &wgpu::TextureDescriptor {
+                label: Some("equirect_texture"),
+                size: wgpu::Extent3d {
+                    width: image.width(),
+                    height: image.height(),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            bytemuck::cast_slice(&pixels),
+        );
+        let equirect_view = equirect.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let cube_levels = 32 - size.leading_zeros();
+        let cubemap = self.create_texture("environment_cubemap", size, 6, cube_levels);
+        let sampler = wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+        };
+        self.draw(
+            &self.pipeline("equirect_to_cube"),
+            &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&equirect_view),
+                },
+                sampler.clone(),
+            ],
+            [0.0; 4],
+            &cubemap,
+            0,
+            6,
+        );
+        let downsample = self.pipeline("downsample");
+        for level in 1..cube_levels {
+            let source = Self::cube_view(&cubemap, level - 1, Some(1));
+            self.draw(
+                &downsample,
+                &[
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&source),
+                    },
+                    sampler.clone(),
+                ],
+                [0.0; 4],
+                &cubemap,
+                level,
+                6,
+            );
+        }
+        cubemap
+    }
+
+    /// A view of `texture` as a cube, from mip level `level` on.
+    fn cube_view(texture: &wgpu::Texture, level: u32, count: Option<u32>) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            base_mip_level: level,
+            mip_level_count: count.and_then(NonZeroU32::new),
+            ..Default::default()
+        })
+    }
+}
+
+/// An environment cubemap with the irradiance and prefiltered specular cubemaps derived from it.
+pub struct Environment {
+    cubemap_texture: wgpu::Texture,
+    cubemap: wgpu::TextureView,
+    irradiance: wgpu::TextureView,
+    prefiltered: wgpu::TextureView,
+    specular_levels: u32,
+}
+
+impl Environment {
+    /// Converts the equirectangular `image` into a cubemap and convolves it, on the GPU.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &HdrImage,
+        options: &EnvironmentOptions,
+    ) -> Result<Self> {
+        let baker = Baker::new(device, queue)?;
+
+        let cube_size = options
+            .cube_size
+            .clamp(1, device.limits().max_texture_dimension_2d);
+        let cubemap = baker.equirect_to_cube(image, cube_size);
+        let cubemap_view = Baker::cube_view(&cubemap, 0, None);
+        let sampler = wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::Sampler(&baker.sampler),
+        };
+        let source = wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&cubemap_view),
+        };
+
+        let irradiance_size = options.irradiance_size.clamp(1, cube_size);
+        let irradiance = baker.create_texture("irradiance_cubemap", irradiance_size, 6, 1);
+        // The level with about as many texels as there are samples across a face.
+        let irradiance_source_level = (cube_size as f32 / 16.0).log2().max(0.0);
+        baker.draw(
+            &baker.pipeline("irradiance"),
+            &[source.clone(), sampler.clone()],
+            [0.0, irradiance_source_level, cube_size as f32, 0.0],
+            &irradiance,
+            0,
+            6,
+        );
+
+        let specular_size = options.specular_size.clamp(1, cube_size);
+        let specular_levels = options
+            .specular_levels
+            .clamp(1, 32 - specular_size.leading_zeros());
+        let prefiltered =
+            baker.create_texture("prefiltered_cubemap", specular_size, 6, specular_levels);
+        let prefilter = baker.pipeline("prefilter");
+        for level in 0..specular_levels {
+            let roughness = level as f32 / (specular_levels - 1).max(1) as f32;
+            // The level of the environment as large as this one, for mirror reflections.
+            let source_level = (cube_size as f32 / (specular_size >> level).max(1) as f32).log2();
+            baker.draw(
+                &prefilter,
+                &[source.clone(), sampler.clone()],
+                [
+                    roughness,
+                    source_level,
+                    cube_size as f32,
+                    options.specular_samples.max(1) as f32,
+                ],
+                &prefiltered,
+                level,
+                6,
+            );
+        }
+
+        Ok(Self {
+            cubemap_texture: cubemap,
+            cubemap: cubemap_view,
+            irradiance: Baker::cube_view(&irradiance, 0, None),
+            prefiltered: Baker::cube_view(&prefiltered, 0, None),
+            specular_levels,
+        })
+    }
+
+    /// Loads an equirectangular `.hdr` file, see [`Environment::new`].
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        options: &EnvironmentOptions,
+    ) -> Result<Self> {
+        Self::new(device, queue, &HdrImage::load(path)?, options)
+    }
+
+    /// An environment that casts no light.
+    pub(crate) fn black(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self> {
+        let options = EnvironmentOptions {
+            cube_size: 1,
+            irradiance_size: 1,
+            specular_size: 1,
+            specular_levels: 1,
+            specular_samples: 1,
+        };
+        Self::new(
+            device,
+            queue,
+            &HdrImage::new(1, 1, vec![[0.0; 3]]),
+            &options,
+        )
+    }
+
+    /// The environment itself, with a full mip chain.
+    pub fn cubemap(&self) -> &wgpu::TextureView {
+        &self.cubemap
+    }
+
+    pub(crate) fn cubemap_texture(&self) -> &wgpu::Texture {
+        &self.cubemap_texture
+    }
+
+    /// Irradiance divided by pi, by the normal direction.
+    pub fn irradiance(&self) -> &wgpu::TextureView {
+        &self.irradiance
+    }
+
+    /// Light reflected specularly, by the reflection direction, with roughness increasing evenly
+    /// over the mip levels.
+    pub fn prefiltered(&self) -> &wgpu::TextureView {
+        &self.prefiltered
+    }
+
+    /// The number of mip levels of [`Environment::prefiltered`].
+    pub fn specular_levels(&self) -> u32 {
+        self.specular_levels
+    }
+}
+
+/// Converts the equirectangular `image` into a cubemap with faces of `size`, with a full mip chain.
+pub(crate) fn equirect_to_cube(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image: &HdrImage,
+    size: u32,
+) -> Result<wgpu::Texture> {
+    let size = size.clamp(1, device.limits().max_texture_dimension_2d);
+    Ok(Baker::new(device, queue)?.equirect_to_cube(image, size))
+}
+
+/// Computes the lookup table of the split sum approximation: the scale and bias to the
+/// reflectance at normal incidence that give the specular albedo, by the cosine of the view angle
+/// along U and the roughness along V.
+pub(crate) fn brdf_lut(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<wgpu::TextureView> {
+    let baker = Baker::new(device, queue)?;
+    let lut = baker.create_texture("brdf_lut", BRDF_LUT_SIZE, 1, 1);
+    baker.draw(
+        &baker.pipeline("brdf_lut"),
+        &[],
+        [0.0, 0.0, 0.0, BRDF_LUT_SAMPLES as f32],
+        &lut,
+        0,
+        1,
+    );
+    Ok(lut.create_view(&wgpu::TextureViewDescriptor::default()))
+}
@@ -0,0 +1,200 @@
+// Shaders turning an equirectangular image into the cubemaps used for image based lighting, by
+// drawing a triangle covering each face. Each fragment entry point uses its own subset of the
+// bindings.
+
+@group(0) @binding(0)
+var equirect: texture_2d<f32>;
+
+@group(0) @binding(1)
+var source: texture_cube<f32>;
+
+@group(0) @binding(2)
+var s_linear: sampler;
+
+struct Params {
+  // roughness, source mip level, source face size, sample count
+  values: vec4<f32>,
+  // the cube face drawn into
+  face: vec4<u32>
+};
+
+@group(0) @binding(3)
+var<uniform> params: Params;
+
+let PI: f32 = 3.14159265359;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  // 0, 0 at the top left corner of the target
+  @location(0) uv: vec2<f32>
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+  let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+  var out: VertexOutput;
+  out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+  out.uv = uv;
+  return out;
+}
+
+// The direction through `uv` of cube face `face`, following the cubemap conventions of wgpu.
+fn cube_direction(uv: vec2<f32>, face: u32) -> vec3<f32> {
+  let st = uv * 2.0 - 1.0;
+  var direction: vec3<f32>;
+  if (face == 0u) {
+    direction = vec3<f32>(1.0, -st.y, -st.x);
+  } else if (face == 1u) {
+    direction = vec3<f32>(-1.0, -st.y, st.x);
+  } else if (face == 2u) {
+    direction = vec3<f32>(st.x, 1.0, st.y);
+  } else if (face == 3u) {
+    direction = vec3<f32>(st.x, -1.0, -st.y);
+  } else if (face == 4u) {
+    direction = vec3<f32>(st.x, -st.y, 1.0);
+  } else {
+    direction = vec3<f32>(-st.x, -st.y, -1.0);
+  }
+  return normalize(direction);
+}
+
+// A frame around `normal`, with `normal` as its third column.
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+  var up = vec3<f32>(0.0, 1.0, 0.0);
+  if (abs(normal.y) > 0.999) {
+    up = vec3<f32>(1.0, 0.0, 0.0);
+  }
+  let tangent = normalize(cross(up, normal));
+  return mat3x3<f32>(tangent, cross(normal, tangent), normal);
+}
+
+// The `i`th of `count` points of the Hammersley sequence, evenly covering the unit square.
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+  var bits = i;
+  bits = (bits << 16u) | (bits >> 16u);
+  bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+  bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+  bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+  bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+  return vec2<f32>(f32(i) / f32(count), f32(bits) * 2.3283064365386963e-10);
+}
+
+// A half vector around `normal`, distributed like the GGX normal distribution.
+fn importance_sample_ggx(xi: vec2<f32>, normal: vec3<f32>, alpha: f32) -> vec3<f32> {
+  let phi = 2.0 * PI * xi.x;
+  let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y));
+  let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+  let h = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+  return normalize(tangent_frame(normal) * h);
+}
+
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+  let alpha2 = alpha * alpha;
+  let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+  return alpha2 / (PI * d * d);
+}
+
+@fragment
+fn equirect_to_cube(in: VertexOutput) -> @location(0) vec4<f32> {
+  let direction = cube_direction(in.uv, params.face.x);
+  let uv = vec2<f32>(
+    0.5 + atan2(direction.x, -direction.z) / (2.0 * PI),
+    acos(clamp(direction.y, -1.0, 1.0)) / PI
+  );
+  return vec4<f32>(textureSampleLevel(equirect, s_linear, uv, 0.0).rgb, 1.0);
+}
+
+// Halves the previous mip level, which is bound as `source`.
+@fragment
+fn downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+  // Sampling at the shared corner of four source texels averages them.
+  let direction = cube_direction(in.uv, params.face.x);
+  return vec4<f32>(textureSampleLevel(source, s_linear, direction, 0.0).rgb, 1.0);
+}
+
+// Convolves the environment with a cosine lobe. Stores irradiance divided by pi, so that the
+// diffuse light reflected is the stored value times the albedo.
+@fragment
+fn irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+  let frame = tangent_frame(cube_direction(in.uv, params.face.x));
+  let phi_steps = 64u;
+  let theta_steps = 16u;
+  var sum = vec3<f32>(0.0);
+  for (var i = 0u; i < phi_steps; i = i + 1u) {
+    let phi = 2.0 * PI * (f32(i) + 0.5) / f32(phi_steps);
+    for (var j = 0u; j < theta_steps; j = j + 1u) {
+      let theta = 0.5 * PI * (f32(j) + 0.5) / f32(theta_steps);
+      let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+      let radiance = textureSampleLevel(source, s_linear, frame * local, params.values.y).rgb;
+      sum = sum + radiance * cos(theta) * sin(theta);
+    }
+  }
+  return vec4<f32>(PI * sum / f32(phi_steps * theta_steps), 1.0);
+}
+
+// Convolves the environment with the GGX lobe of a roughness, assuming the view direction equals
+// the normal. Samples lower mip levels where samples are sparse, to avoid noise.
+@fragment
+fn prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+  let normal = cube_direction(in.uv, params.face.x);
+  let roughness = params.values.x;
+  let source_size = params.values.z;
+  if (roughness == 0.0) {
+    return vec4<f32>(textureSampleLevel(source, s_linear, normal, params.values.y).rgb, 1.0);
+  }
+  let alpha = roughness * roughness;
+  let sample_count = u32(params.values.w);
+  let texel_solid_angle = 4.0 * PI / (6.0 * source_size * source_size);
+  var sum = vec3<f32>(0.0);
+  var weight = 0.0;
+  for (var i = 0u; i < sample_count; i = i + 1u) {
+    let half_vector = importance_sample_ggx(hammersley(i, sample_count), normal, alpha);
+    let n_dot_h = max(dot(normal, half_vector), 0.0);
+    let to_light = 2.0 * n_dot_h * half_vector - normal;
+    let n_dot_l = dot(normal, to_light);
+    if (n_dot_l > 0.0) {
+      // With the view direction equal to the normal, v.h equals n.h.
+      let pdf = distribution_ggx(n_dot_h, alpha) / 4.0 + 1e-4;
+      let sample_solid_angle = 1.0 / (f32(sample_count) * pdf);
+      let level = max(0.5 * log2(sample_solid_angle / texel_solid_angle), 0.0);
+      sum = sum + textureSampleLevel(source, s_linear, to_light, level).rgb * n_dot_l;
+      weight = weight + n_dot_l;
+    }
+  }
+  return vec4<f32>(sum / max(weight, 1e-4), 1.0);
+}
+
+fn geometry_schlick_ggx(n_dot_x: f32, k: f32) -> f32 {
+  return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// The scale and bias to the reflectance at normal incidence giving the specular albedo, by the
+// cosine of the view angle in x and the roughness in y.
+@fragment
+fn brdf_lut(in: VertexOutput) -> @location(0) vec4<f32> {
+  let n_dot_v = in.uv.x;
+  let roughness = in.uv.y;
+  let alpha = roughness * roughness;
+  // Smith's k for image based lighting.
+  let k = alpha / 2.0;
+  let normal = vec3<f32>(0.0, 0.0, 1.0);
+  let to_eye = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+  let sample_count = u32(params.values.w);
+  var scale = 0.0;
+  var bias = 0.0;
+  for (var i = 0u; i < sample_count; i = i + 1u) {
+    let half_vector = importance_sample_ggx(hammersley(i, sample_count), normal, alpha);
+    let v_dot_h = max(dot(to_eye, half_vector), 0.0);
+    let to_light = 2.0 * v_dot_h * half_vector - to_eye;
+    let n_dot_l = to_light.z;
+    if (n_dot_l > 0.0) {
+      let n_dot_h = max(half_vector.z, 0.0);
+      let geometry = geometry_schlick_ggx(n_dot_v, k) * geometry_schlick_ggx(n_dot_l, k);
+      let visibility = geometry * v_dot_h / (n_dot_h * n_dot_v);
+      let fresnel = pow(1.0 - v_dot_h, 5.0);
+      scale = scale + (1.0 - fresnel) * visibility;
+      bias = bias + fresnel * visibility;
+    }
+  }
+  return vec4<f32>(vec2<f32>(scale, bias) / f32(sample_count), 0.0, 1.0);
+}
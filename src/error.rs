@@ -0,0 +1,174 @@
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while setting up the renderer or loading its assets.
+#[derive(Debug)]
+pub enum Error {
+    /// No adapter compatible with the surface was found.
+    AdapterRequest,
+    /// The adapter refused to hand out a device.
+    DeviceRequest(wgpu::RequestDeviceError),
+    /// The surface can not be configured as requested.
+    SurfaceConfiguration(String),
+    /// An asset file could not be read.
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// An asset file was read but its contents are malformed.
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        source: serde_json::Error,
+    },
+    /// An OBJ file could not be imported.
+    Obj {
+        path: PathBuf,
+        source: tobj::LoadError,
+    },
+    /// A glTF file could not be imported.
+    Gltf { path: PathBuf, source: gltf::Error },
+    /// Mesh data is structurally unusable.
+    InvalidMesh(String),
+    /// Model data was loaded but is inconsistent.
+    InvalidModel {
+        path: Option<PathBuf>,
+        source: ValidationError,
+    },
+    /// An image could not be decoded.
+    ImageDecode {
+        name: String,
+        source: image::ImageError,
+    },
+    /// A shader module failed validation.
+    ShaderValidation { label: String, description: String },
+}
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn parse(path: impl Into<PathBuf>, source: serde_json::Error) -> Self {
+        Error::Parse {
+            path: path.into(),
+            line: source.line(),
+            column: source.column(),
+            source,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::AdapterRequest => f.write_str("no compatible graphics adapter found"),
+            Error::DeviceRequest(_) => f.write_str("requesting a graphics device failed"),
+            Error::SurfaceConfiguration(reason) => {
+                write!(f, "could not configure surface: {}", reason)
+            }
+            Error::Io { path, .. } => write!(f, "could not read {:?}", path),
+            Error::Parse {
+                path, line, column, ..
+            } => write!(f, "could not parse {:?} at {}:{}", path, line, column),
+            Error::Obj { path, .. } => write!(f, "could not import OBJ {:?}", path),
+            Error::Gltf { path, .. } => write!(f, "could not import glTF {:?}", path),
+            Error::InvalidMesh(reason) => write!(f, "invalid mesh: {}", reason),
+            Error::InvalidModel {
+                path: Some(path), ..
+            } => write!(f, "model {:?} is invalid", path),
+            Error::InvalidModel { path: None, .. } => f.write_str("model is invalid"),
+            Error::ImageDecode { name, .. } => write!(f, "could not decode image {}", name),
+            Error::ShaderValidation { label, description } => {
+                write!(f, "shader {} failed validation: {}", label, description)
+            }
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::DeviceRequest(source) => Some(source),
+            Error::Io { source, .. } => Some(source),
+            Error::Parse { source, .. } => Some(source),
+            Error::Obj { source, .. } => Some(source),
+            Error::Gltf { source, .. } => Some(source),
+            Error::InvalidModel { source, .. } => Some(source),
+            Error::ImageDecode { source, .. } => Some(source),
+            Error::AdapterRequest
+            | Error::SurfaceConfiguration(_)
+            | Error::InvalidMesh(_)
+            | Error::ShaderValidation { .. } => None,
+        }
+    }
+}
+
+/// The first inconsistency [`ModelData::validate`](crate::model::ModelData::validate) found.
+#[derive(Debug)]
+pub enum ValidationError {
+    /// A vertex attribute has a different number of entries than `positions`.
+    AttributeLength {
+        attribute: &'static str,
+        len: usize,
+        expected: usize,
+    },
+    /// The number of indices is not a multiple of three.
+    IndexCount(usize),
+    /// `indices[index]` refers to a vertex that does not exist.
+    IndexOutOfRange {
+        index: usize,
+        value: u32,
+        vertex_count: usize,
+    },
+    /// The triangle made of `indices[3 * triangle..3 * triangle + 3]` has no area.
+    DegenerateTriangle { triangle: usize },
+    /// `attribute[element]` contains NaN or an infinity.
+    NonFinite {
+        attribute: &'static str,
+        element: usize,
+    },
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::AttributeLength {
+                attribute,
+                len,
+                expected,
+            } => write!(
+                f,
+                "{} has {} entries but there are {} positions",
+                attribute, len, expected
+            ),
+            ValidationError::IndexCount(count) => {
+                write!(f, "{} indices do not make up whole triangles", count)
+            }
+            ValidationError::IndexOutOfRange {
+                index,
+                value,
+                vertex_count,
+            } => write!(
+                f,
+                "indices[{}] = {} is out of range for {} vertices",
+                index, value, vertex_count
+            ),
+            ValidationError::DegenerateTriangle { triangle } => {
+                write!(f, "triangle {} is degenerate", triangle)
+            }
+            ValidationError::NonFinite { attribute, element } => {
+                write!(f, "{}[{}] is not finite", attribute, element)
+            }
+        }
+    }
+}
+
+impl StdError for ValidationError {}
@@ -0,0 +1,265 @@
+use crate::error::{Error, Result};
+use crate::model::ModelData;
+use cgmath::{InnerSpace, Vector3};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlyFormat {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+/// Fails with [`std::io::ErrorKind::InvalidInput`] if `model` does not pass
+/// [`ModelData::validate`], which the writers rely on.
+fn check(model: &ModelData) -> std::io::Result<()> {
+    model
+        .validate()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+}
+
+/// Writes `model` as a Wavefront OBJ with one object, including texture coordinates and normals
+/// if it has any.
+pub fn write_obj<W: Write>(writer: W, model: &ModelData) -> std::io::Result<()> {
+    check(model)?;
+    obj(writer, model)
+}
+
+fn obj<W: Write>(mut writer: W, model: &ModelData) -> std::io::Result<()> {
+    for [x, y, z] in model.positions() {
+        writeln!(writer, "v {} {} {}", x, y, z)?;
+    }
+    // OBJ puts the origin of texture space at the bottom, we put it at the top.
+    for [u, v] in model.uvs() {
+        writeln!(writer, "vt {} {}", u, 1.0 - v)?;
+    }
+    for [x, y, z] in model.normals() {
+        writeln!(writer, "vn {} {} {}", x, y, z)?;
+    }
+    let has_uvs = !model.uvs().is_empty();
+    let has_normals = !model.normals().is_empty();
+    for triangle in model.indices().chunks_exact(3) {
+        write!(writer, "f")?;
+        for index in triangle {
+            match (has_uvs, has_normals) {
+                (true, true) => write!(writer, " {0}/{0}/{0}", index + 1)?,
+                (true, false) => write!(writer, " {0}/{0}", index + 1)?,
+                (false, true) => write!(writer, " {0}//{0}", index + 1)?,
+                (false, false) => write!(writer, " {}", index + 1)?,
+            }
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+/// Writes `model` as a PLY with `s`, `t` texture coordinates and, if it has any, normals.
+pub fn write_ply<W: Write>(writer: W, model: &ModelData, format: PlyFormat) -> std::io::Result<()> {
+    check(model)?;
+    ply(writer, model, format)
+}
+
+fn ply<W: Write>(mut writer: W, model: &ModelData, format: PlyFormat) -> std::io::Result<()> {
+    let has_normals = !model.normals().is_empty();
+    let vertices = model.vertices();
+    writeln!(writer, "ply")?;
+    match format {
+        PlyFormat::Ascii => writeln!(writer, "format ascii 1.0")?,
+        PlyFormat::BinaryLittleEndian => writeln!(writer, "format binary_little_endian 1.0")?,
+    }
+    writeln!(writer, "element vertex {}", vertices.len())?;
+    for property in &["x", "y", "z", "s", "t"] {
+        writeln!(writer, "property float {}", property)?;
+    }
+    if has_normals {
+        for property in &["nx", "ny", "nz"] {
+            writeln!(writer, "property float {}", property)?;
+        }
+    }
+    writeln!(writer, "element face {}", model.indices().len() / 3)?;
+    writeln!(writer, "property list uchar uint vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    for vertex in &vertices {
+        let mut values = vec![];
+        values.extend_from_slice(&vertex.position());
+        values.extend_from_slice(&vertex.uv());
+        if has_normals {
+            values.extend_from_slice(&vertex.normal());
+        }
+        match format {
+            PlyFormat::Ascii => {
+                let line: Vec<String> = values.iter().map(f32::to_string).collect();
+                writeln!(writer, "{}", line.join(" "))?;
+            }
+            PlyFormat::BinaryLittleEndian => {
+                for value in values {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    for triangle in model.indices().chunks_exact(3) {
+        match format {
+            PlyFormat::Ascii => {
+                writeln!(writer, "3 {} {} {}", triangle[0], triangle[1], triangle[2])?
+            }
+            PlyFormat::BinaryLittleEndian => {
+                writer.write_all(&[3])?;
+                for index in triangle {
+                    writer.write_all(&index.to_le_bytes())?;
+                }
+            }
+        }
+    }
+    writer.flush()
+}
+
+/// Writes the triangles of `model` as a binary STL, with facet normals computed from the
+/// triangle winding.
+pub fn write_stl<W: Write>(writer: W, model: &ModelData) -> std::io::Result<()> {
+    check(model)?;
+    stl(writer, model)
+}
+
+fn stl<W: Write>(mut writer: W, model: &ModelData) -> std::io::Result<()> {
+    let mut header = [0u8; 80];
+    let title = b"hello-wgpu export";
+    header[..title.len()].copy_from_slice(title);
+    writer.write_all(&header)?;
+    writer.write_all(&(model.indices().len() as u32 / 3).to_le_bytes())?;
+    let positions = model.positions();
+    for triangle in model.indices().chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(positions[triangle[i] as usize]));
+        let normal = (b - a).cross(c - a);
+        let normal = if normal.magnitude2() > 0.0 {
+            normal.normalize()
+        } else {
+            normal
+        };
+        for vector in &[normal, a, b, c] {
+            for component in &[vector.x, vector.y, vector.z] {
+                writer.write_all(&component.to_le_bytes())?;
+            }
+        }
+        writer.write_all(&0u16.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Validates `model` before creating the file at `path` and writing it there with `write`.
+fn save(
+    path: &Path,
+    model: &ModelData,
+    write: impl FnOnce(BufWriter<File>) -> std::io::Result<()>,
+) -> Result<()> {
+    model
+        .validate()
+        .map_err(|source| Error::InvalidModel { path: None, source })?;
+    File::create(path)
+        .and_then(|file| write(BufWriter::new(file)))
+        .map_err(|err| Error::io(path, err))
+}
+
+pub fn save_obj(path: &Path, model: &ModelData) -> Result<()> {
+    save(path, model, |writer| obj(writer, model))
+}
+
+pub fn save_ply(path: &Path, model: &ModelData, format: PlyFormat) -> Result<()> {
+    save(path, model, |writer| ply(writer, model, format))
+}
+
+pub fn save_stl(path: &Path, model: &ModelData) -> Result<()> {
+    save(path, model, |writer| stl(writer, model))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    fn triangle() -> ModelData {
+        ModelData::new(
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            vec![[0.0, 0.0], [1.0, 0.0], [0.0, 0.25]],
+            vec![0, 1, 2],
+        )
+    }
+
+    fn obj_text(model: &ModelData) -> String {
+        let mut bytes = Vec::new();
+        write_obj(&mut bytes, model).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn obj_faces_match_attributes() {
+        let text = obj_text(&triangle());
+        assert!(text.contains("vt 0 0.75\n"));
+        assert!(!text.contains("vn "));
+        assert!(text.ends_with("f 1/1 2/2 3/3\n"));
+
+        let text = obj_text(&triangle().with_normals(vec![[0.0, 0.0, 1.0]; 3]));
+        assert!(text.contains("vn 0 0 1\n"));
+        assert!(text.ends_with("f 1/1/1 2/2/2 3/3/3\n"));
+    }
+
+    #[test]
+    fn ply_header_matches_body() {
+        let model = triangle().with_normals(vec![[0.0, 0.0, 1.0]; 3]);
+        let mut bytes = Vec::new();
+        write_ply(&mut bytes, &model, PlyFormat::Ascii).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let (header, body) = text.split_once("end_header\n").unwrap();
+        assert!(header.contains("element vertex 3\n"));
+        assert!(header.contains("property float nz\n"));
+        assert!(header.contains("element face 1\n"));
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1].split(' ').count(), 8);
+        assert_eq!(lines[3], "3 0 1 2");
+
+        let mut bytes = Vec::new();
+        write_ply(&mut bytes, &model, PlyFormat::BinaryLittleEndian).unwrap();
+        let end = b"end_header\n";
+        let body = bytes.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        assert_eq!(bytes.len() - body, 3 * 8 * 4 + 1 + 3 * 4);
+    }
+
+    #[test]
+    fn stl_has_facet_normals() {
+        let mut bytes = Vec::new();
+        write_stl(&mut bytes, &triangle()).unwrap();
+        assert_eq!(bytes.len(), 84 + 50);
+        assert_eq!(&bytes[80..84], &1u32.to_le_bytes());
+        let normal: Vec<f32> = bytes[84..96]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(normal, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn rejects_invalid_models() {
+        let valid = triangle();
+        let model = ModelData::new(
+            valid.positions().to_vec(),
+            valid.uvs().to_vec(),
+            vec![0, 1, 3],
+        );
+        for result in [
+            write_obj(Vec::new(), &model),
+            write_ply(Vec::new(), &model, PlyFormat::Ascii),
+            write_stl(Vec::new(), &model),
+        ] {
+            assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        }
+        let path = std::env::temp_dir().join("hello-wgpu-invalid-export.obj");
+        assert!(matches!(
+            save_obj(&path, &model),
+            Err(Error::InvalidModel { path: None, .. })
+        ));
+        assert!(!path.exists());
+    }
+}
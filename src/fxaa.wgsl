@@ -0,0 +1,43 @@
+// Fast approximate anti-aliasing: blurs along the edges found by luminance contrast.
+
+struct FxaaUniform {
+  // largest blur in texels, how much the blur shrinks with luminance, smallest shrinking
+  params: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> fxaa: FxaaUniform;
+
+// Luminance with a rough gamma, since edges are judged by how they look.
+fn fxaa_luma(color: vec3<f32>) -> f32 {
+  return sqrt(luminance(color));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let texel = input_texel();
+  let center = textureSample(t_input, s_input, in.uv);
+  let nw = fxaa_luma(textureSample(t_input, s_input, in.uv + texel * vec2<f32>(-1.0, -1.0)).rgb);
+  let ne = fxaa_luma(textureSample(t_input, s_input, in.uv + texel * vec2<f32>(1.0, -1.0)).rgb);
+  let sw = fxaa_luma(textureSample(t_input, s_input, in.uv + texel * vec2<f32>(-1.0, 1.0)).rgb);
+  let se = fxaa_luma(textureSample(t_input, s_input, in.uv + texel * vec2<f32>(1.0, 1.0)).rgb);
+  let m = fxaa_luma(center.rgb);
+  let luma_min = min(m, min(min(nw, ne), min(sw, se)));
+  let luma_max = max(m, max(max(nw, ne), max(sw, se)));
+
+  // Perpendicular to the luminance gradient, which runs along the edge.
+  var direction = vec2<f32>(-((nw + ne) - (sw + se)), (nw + sw) - (ne + se));
+  let reduce = max((nw + ne + sw + se) * 0.25 * fxaa.params.y, fxaa.params.z);
+  let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+  let span = vec2<f32>(fxaa.params.x);
+  direction = clamp(direction * scale, -span, span) * texel;
+
+  let near = 0.5 * (textureSample(t_input, s_input, in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+    + textureSample(t_input, s_input, in.uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+  let far = near * 0.5 + 0.25 * (textureSample(t_input, s_input, in.uv - direction * 0.5).rgb
+    + textureSample(t_input, s_input, in.uv + direction * 0.5).rgb);
+  // The wider blur overshoots where it crosses another edge.
+  let far_luma = fxaa_luma(far);
+  let color = select(far, near, far_luma < luma_min || far_luma > luma_max);
+  return vec4<f32>(color, center.a);
+}
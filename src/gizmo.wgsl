@@ -0,0 +1,38 @@
+// Draws the world axes as seen from the camera, without perspective, into a corner viewport.
+
+struct GizmoUniform {
+  // rotation of the view
+  view: mat4x4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> gizmo: GizmoUniform;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) color: vec3<f32>
+};
+
+// Two vertices per line, one line for each direction of each axis, the negative ones dimmer.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+  let segment = index / 2u;
+  let axis = segment % 3u;
+  var direction = vec3<f32>(0.0);
+  direction[axis] = 1.0;
+  var color = direction;
+  if (segment >= 3u) {
+    direction = -direction;
+    color = color * 0.3;
+  }
+  let view = gizmo.view * vec4<f32>(direction * f32(index % 2u), 0.0);
+  var out: VertexOutput;
+  out.clip_position = vec4<f32>(view.xy * 0.8, 0.5, 1.0);
+  out.color = color;
+  return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  return vec4<f32>(in.color, 1.0);
+}
@@ -0,0 +1,16 @@
+// Removes the color of the image.
+
+struct GrayscaleUniform {
+  // amount
+  params: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> grayscale: GrayscaleUniform;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = textureSample(t_input, s_input, in.uv);
+  let gray = vec3<f32>(luminance(color.rgb));
+  return vec4<f32>(mix(color.rgb, gray, grayscale.params.x), color.a);
+}
@@ -0,0 +1,261 @@
+//! Spatial references: a grid on the ground plane out to the horizon and a gizmo in the corner
+//! of the frame showing the orientation of the camera.
+
+use crate::camera::Camera;
+use crate::error::Result;
+use crate::renderer::{self, create_shader_module};
+use cgmath::{Matrix4, SquareMatrix};
+use wgpu::include_wgsl;
+
+/// Width and height of the orientation gizmo in pixels.
+const GIZMO_SIZE: u32 = 96;
+/// Distance of the orientation gizmo from the corner of the frame in pixels.
+const GIZMO_MARGIN: u32 = 12;
+
+/// A grid of lines on the XZ plane, anti-aliased and fading with the distance from the camera.
+/// The X axis is drawn in red and the Z axis in blue.
+#[derive(Clone, Copy, Debug)]
+pub struct Grid {
+    /// The distance between lines in world units, with a stronger line every ten cells.
+    pub cell_size: f32,
+    /// The distance from the camera at which the grid has faded out.
+    pub fade_distance: f32,
+    /// Linear RGB.
+    pub color: [f32; 3],
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            cell_size: 1.0,
+            fade_distance: 50.0,
+            color: [0.5, 0.5, 0.5],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct GridUniform {
+    view_proj: [[f32; 4]; 4],
+    inverse_view_proj: [[f32; 4]; 4],
+    /// With a `w` of 1.
+    eye: [f32; 4],
+    color: [f32; 4],
+    /// Cell size, fade distance.
+    params: [f32; 4],
+}
+
+/// Draws a [`Grid`] in the main pass, after the scene and the sky since it is transparent.
+pub(crate) struct GridPass {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+}
+
+impl GridPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, include_wgsl!("grid.wgsl"))?;
+        let (uniform_buffer, uniform_bind_group) = uniform(
+            device,
+            "Grid Buffer",
+            std::mem::size_of::<GridUniform>(),
+            uniform_bind_group_layout,
+        );
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Grid Pipeline Layout"),
+            bind_group_layouts: &[uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Grid Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Hidden by the scene, but not hiding anything drawn after it.
+            depth_stencil: Some(renderer::depth_stencil_state(
+                false,
+                wgpu::CompareFunction::Less,
+                wgpu::StencilState::default(),
+            )),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+        Ok(Self {
+            pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+        })
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, grid: &Grid) {
+        let view_proj = camera.view_projection();
+        let inverse_view_proj = view_proj.invert().unwrap_or_else(Matrix4::identity);
+        let [r, g, b] = grid.color;
+        let uniform = GridUniform {
+            view_proj: view_proj.into(),
+            inverse_view_proj: inverse_view_proj.into(),
+            eye: camera.eye().to_homogeneous().into(),
+            color: [r, g, b, 1.0],
+            params: [grid.cell_size, grid.fade_distance, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct GizmoUniform {
+    view: [[f32; 4]; 4],
+}
+
+/// Draws the world axes as the camera sees them into the bottom left corner of the frame: X red,
+/// Y green and Z blue, with their negative directions dimmer.
+pub(crate) struct GizmoPass {
+    pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+}
+
+impl GizmoPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, include_wgsl!("gizmo.wgsl"))?;
+        let (uniform_buffer, uniform_bind_group) = uniform(
+            device,
+            "Gizmo Buffer",
+            std::mem::size_of::<GizmoUniform>(),
+            uniform_bind_group_layout,
+        );
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gizmo Pipeline Layout"),
+            bind_group_layouts: &[uniform_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Gizmo Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Ok(Self {
+            pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+        })
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let uniform = GizmoUniform {
+            view: camera.view_rotation().into(),
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Draws the gizmo onto `target`, which is `width` by `height` pixels.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::TextureView,
+        (width, height): (u32, u32),
+    ) {
+        let size = GIZMO_SIZE.min(width).min(height);
+        let margin = GIZMO_MARGIN.min(width - size).min(height - size);
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Gizmo Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_viewport(
+            margin as f32,
+            (height - size - margin) as f32,
+            size as f32,
+            size as f32,
+            0.0,
+            1.0,
+        );
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..12, 0..1);
+    }
+}
+
+/// A uniform buffer of `size` bytes and its bind group.
+fn uniform(
+    device: &wgpu::Device,
+    label: &str,
+    size: usize,
+    layout: &wgpu::BindGroupLayout,
+) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+        label: Some(label),
+    });
+    (buffer, bind_group)
+}
@@ -0,0 +1,85 @@
+// Draws a grid on the XZ plane out to the horizon, intersecting the plane with the view ray of
+// every pixel of a triangle covering the screen.
+
+struct GridUniform {
+  view_proj: mat4x4<f32>,
+  inverse_view_proj: mat4x4<f32>,
+  // with a `w` of 1
+  eye: vec4<f32>,
+  // linear RGB of the lines
+  color: vec4<f32>,
+  // cell size, fade distance
+  params: vec4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> grid: GridUniform;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) ndc: vec2<f32>
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+  let ndc = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+  var out: VertexOutput;
+  out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+  out.ndc = ndc;
+  return out;
+}
+
+fn unproject(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+  let position = grid.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+  return position.xyz / position.w;
+}
+
+// How much a pixel at `coord` in cells is covered by the lines between cells, one pixel wide,
+// where a pixel spans `width` cells. The lines fade out where the cells shrink to a few pixels,
+// which would only alias.
+fn line_coverage(coord: vec2<f32>, width: vec2<f32>) -> f32 {
+  let distance = abs(fract(coord - 0.5) - 0.5) / width;
+  let coverage = 1.0 - min(min(distance.x, distance.y), 1.0);
+  return coverage * (1.0 - smoothstep(0.1, 0.5, max(width.x, width.y)));
+}
+
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+  // Of the point on the plane, so that the scene hides the grid.
+  @builtin(frag_depth) depth: f32
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  let near = unproject(in.ndc, 0.0);
+  let far = unproject(in.ndc, 1.0);
+  let t = near.y / (near.y - far.y);
+  let position = near + (far - near) * t;
+
+  // Derivatives are taken before discarding, while all pixels are still running.
+  let coord = position.xz / grid.params.x;
+  let width = fwidth(coord);
+  let minor = line_coverage(coord, width);
+  let major = line_coverage(coord / 10.0, width / 10.0);
+  let axis = 1.0 - min(abs(coord) / width, vec2<f32>(1.0));
+
+  var color = grid.color.rgb;
+  var alpha = max(minor * 0.4, major);
+  // The X axis, where z is 0, in red and the Z axis in blue.
+  color = mix(color, vec3<f32>(1.0, 0.1, 0.1), axis.y);
+  color = mix(color, vec3<f32>(0.1, 0.2, 1.0), axis.x * (1.0 - axis.y));
+  alpha = max(alpha, max(axis.x, axis.y));
+  let distance = length(position - grid.eye.xyz);
+  let fade_distance = grid.params.y;
+  alpha = alpha * (1.0 - smoothstep(fade_distance * 0.25, fade_distance, distance));
+
+  // Only in front of the camera and up to the far plane.
+  if (t <= 0.0 || t > 1.0 || alpha <= 0.0) {
+    discard;
+  }
+  let clip = grid.view_proj * vec4<f32>(position, 1.0);
+  var out: FragmentOutput;
+  out.color = vec4<f32>(color, alpha);
+  out.depth = clip.z / clip.w;
+  return out;
+}
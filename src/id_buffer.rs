@@ -0,0 +1,337 @@
+//! Picking by rendering object IDs into an integer texture and reading back single pixels.
+
+use crate::material::Material;
+use crate::model::Model;
+use crate::renderer::{self, Renderer};
+use crate::texture::Texture;
+use std::sync::{Arc, Mutex};
+
+pub(crate) const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+
+/// The ID of pixels not covered by any object.
+pub(crate) const NO_OBJECT: u32 = 0;
+
+type MapResult = Arc<Mutex<Option<std::result::Result<(), wgpu::BufferAsyncError>>>>;
+
+enum State {
+    Idle,
+    /// A pick at this pixel is to be rendered with the next frame.
+    Requested {
+        x: u32,
+        y: u32,
+    },
+    /// The pixel is being copied into the readback buffer and mapped.
+    Rendered {
+        x: u32,
+        y: u32,
+    },
+    Mapping {
+        x: u32,
+        y: u32,
+        result: MapResult,
+    },
+}
+
+/// An object drawn into the ID buffer. The base color alpha of `material` decides which pixels
+/// the object covers.
+pub(crate) struct IdDraw<'a> {
+    pub id: u32,
+    pub model: &'a Model,
+    pub material: &'a Material,
+}
+
+pub(crate) struct IdBuffer {
+    pipeline: wgpu::RenderPipeline,
+    object_bind_group_layout: wgpu::BindGroupLayout,
+    /// Every ID from 0 up, each at its own dynamic offset.
+    objects: wgpu::Buffer,
+    object_bind_group: wgpu::BindGroup,
+    object_capacity: u32,
+    object_stride: u32,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    depth_texture: Texture,
+    readback: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    state: State,
+}
+
+impl IdBuffer {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        shader: &wgpu::ShaderModule,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        let object_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<u32>() as u64),
+                    },
+                    count: None,
+                }],
+                label: Some("object_bind_group_layout"),
+            });
+        let pipeline = Renderer::make_pipeline(
+            device,
+            shader,
+            ID_FORMAT,
+            &[
+                material_bind_group_layout,
+                camera_bind_group_layout,
+                &object_bind_group_layout,
+            ],
+            renderer::depth_stencil_state(
+                true,
+                wgpu::CompareFunction::Less,
+                wgpu::StencilState::default(),
+            ),
+            1,
+        );
+        let object_stride = device.limits().min_uniform_buffer_offset_alignment;
+        let object_capacity = 64;
+        let (objects, object_bind_group) = Self::create_objects(
+            device,
+            queue,
+            &object_bind_group_layout,
+            object_capacity,
+            object_stride,
+        );
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("ID Readback Buffer"),
+            size: std::mem::size_of::<u32>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let (texture, view) = Self::create_texture(device, width, height);
+        Self {
+            pipeline,
+            object_bind_group_layout,
+            objects,
+            object_bind_group,
+            object_capacity,
+            object_stride,
+            texture,
+            view,
+            depth_texture: Texture::create_depth_texture(
+                device,
+                width,
+                height,
+                1,
+                "id_depth_texture",
+            ),
+            readback,
+            width,
+            height,
+            state: State::Idle,
+        }
+    }
+
+    fn create_objects(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        capacity: u32,
+        stride: u32,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let objects = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Object ID Buffer"),
+            size: capacity as u64 * stride as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut contents = vec![0u8; capacity as usize * stride as usize];
+        for id in 0..capacity {
+            let offset = (id * stride) as usize;
+            contents[offset..offset + 4].copy_from_slice(&id.to_le_bytes());
+        }
+        queue.write_buffer(&objects, 0, &contents);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &objects,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<u32>() as u64),
+                }),
+            }],
+            label: Some("object_bind_group"),
+        });
+        (objects, bind_group)
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("id_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ID_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (texture, view) = Self::create_texture(device, width, height);
+        self.texture = texture;
+        self.view = view;
+        self.depth_texture =
+            Texture::create_depth_texture(device, width, height, 1, "id_depth_texture");
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Asks for the object at pixel `x`, `y` to be found with the next frame. Replaces an earlier
+    /// request that hasn't been rendered yet, and is ignored while a readback is in progress.
+    pub fn request(&mut self, x: u32, y: u32) {
+        if let State::Idle | State::Requested { .. } = self.state {
+            self.state = State::Requested {
+                x: x.min(self.width - 1),
+                y: y.min(self.height - 1),
+            };
+        }
+    }
+
+    /// Draws `draws` into the ID buffer and copies the requested pixel for reading back, if a
+    /// pick has been requested.
+    pub fn encode<'a>(
+        &'a mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_bind_group: &'a wgpu::BindGroup,
+        draws: impl IntoIterator<Item = IdDraw<'a>>,
+    ) {
+        let (x, y) = match self.state {
+            State::Requested { x, y } => (x, y),
+            _ => return,
+        };
+        let draws: Vec<_> = draws.into_iter().collect();
+        let max_id = draws.iter().map(|draw| draw.id).max().unwrap_or(NO_OBJECT);
+        if max_id >= self.object_capacity {
+            self.object_capacity = (max_id + 1).next_power_of_two();
+            let (objects, object_bind_group) = Self::create_objects(
+                device,
+                queue,
+                &self.object_bind_group_layout,
+                self.object_capacity,
+                self.object_stride,
+            );
+            self.objects = objects;
+            self.object_bind_group = object_bind_group;
+        }
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("ID Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.depth_texture.view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(1, camera_bind_group, &[]);
+            for draw in draws {
+                let model = draw.model;
+                render_pass.set_bind_group(0, draw.material.bind_group(), &[]);
+                render_pass.set_bind_group(
+                    2,
+                    &self.object_bind_group,
+                    &[draw.id * self.object_stride],
+                );
+                render_pass.set_vertex_buffer(0, model.vertex_buffer().slice(..));
+                render_pass.set_index_buffer(model.index_buffer().slice(..), model.index_format());
+                render_pass.draw_indexed(0..model.num_vertices(), 0, 0..1);
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x, y, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.state = State::Rendered { x, y };
+    }
+
+    /// Starts mapping the readback buffer once the frame that filled it has been submitted.
+    pub fn submitted(&mut self) {
+        if let State::Rendered { x, y } = self.state {
+            let result = MapResult::default();
+            let callback_result = result.clone();
+            self.readback
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |mapped| {
+                    *callback_result.lock().unwrap() = Some(mapped);
+                });
+            self.state = State::Mapping { x, y, result };
+        }
+    }
+
+    /// The pixel and object ID of a finished pick, without waiting for the GPU.
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<(u32, u32, u32)> {
+        let (x, y, result) = match &self.state {
+            State::Mapping { x, y, result } => (*x, *y, result.clone()),
+            _ => return None,
+        };
+        device.poll(wgpu::Maintain::Poll);
+        let mapped = result.lock().unwrap().take()?;
+        self.state = State::Idle;
+        if let Err(err) = mapped {
+            log::warn!("Failed to read back the ID buffer: {}", err);
+            return None;
+        }
+        let id = {
+            let bytes = self.readback.slice(..).get_mapped_range();
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        self.readback.unmap();
+        Some((x, y, id))
+    }
+}
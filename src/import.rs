@@ -0,0 +1,303 @@
+use crate::error::{Error, Result};
+use crate::mesh_format;
+use crate::model::ModelData;
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+use log::*;
+use std::path::Path;
+
+/// Accumulates the meshes of a multi-mesh file into a single [`ModelData`].
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// Appends a mesh. `uvs` and `normals` may be empty; meshes without normals get zero ones,
+    /// which are shaded flat.
+    fn append(
+        &mut self,
+        positions: Vec<[f32; 3]>,
+        uvs: Vec<[f32; 2]>,
+        normals: Vec<[f32; 3]>,
+        indices: Vec<u32>,
+    ) {
+        let base = self.positions.len() as u32;
+        let vertex_count = positions.len();
+        self.positions.extend(positions);
+        self.uvs.extend(uvs);
+        self.uvs.resize(self.positions.len(), [0.0, 0.0]);
+        self.normals.extend(normals);
+        self.normals.resize(self.positions.len(), [0.0; 3]);
+        if indices.is_empty() {
+            self.indices.extend(base..base + vertex_count as u32);
+        } else {
+            self.indices
+                .extend(indices.into_iter().map(|index| base + index));
+        }
+    }
+
+    fn build(self) -> ModelData {
+        let model = ModelData::new(self.positions, self.uvs, self.indices);
+        if self.normals.iter().all(|&normal| normal == [0.0; 3]) {
+            model
+        } else {
+            model.with_normals(self.normals)
+        }
+    }
+}
+
+/// Loads a `.model` (JSON), `.obj`, `.gltf`, `.glb` or `.mesh` file, going by its extension.
+pub fn load(path: &Path) -> Result<ModelData> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("model") => ModelData::load(path),
+        Some("obj") => load_obj(path),
+        Some("gltf") | Some("glb") => load_gltf(path),
+        Some("mesh") => mesh_format::load(path),
+        _ => Err(Error::InvalidMesh(format!(
+            "{:?} has no recognized extension",
+            path
+        ))),
+    }
+}
+
+/// Loads all objects of a Wavefront OBJ file as one model, triangulating polygons.
+pub fn load_obj(path: &Path) -> Result<ModelData> {
+    let (models, _) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|source| Error::Obj {
+        path: path.into(),
+        source,
+    })?;
+    let mut builder = MeshBuilder::default();
+    for model in models {
+        let mesh = model.mesh;
+        let positions = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2]])
+            .collect();
+        // OBJ puts the origin of texture space at the bottom, we put it at the top.
+        let uvs = mesh
+            .texcoords
+            .chunks_exact(2)
+            .map(|uv| [uv[0], 1.0 - uv[1]])
+            .collect();
+        let normals = mesh
+            .normals
+            .chunks_exact(3)
+            .map(|n| [n[0], n[1], n[2]])
+            .collect();
+        builder.append(positions, uvs, normals, mesh.indices);
+    }
+    builder.build().validated_structure(path)
+}
+
+/// The contents of a `data:[<media type>];base64,<data>` URI.
+fn decode_data_uri(uri: &str) -> Option<Vec<u8>> {
+    let (header, data) = uri.strip_prefix("data:")?.split_once(',')?;
+    if !header.ends_with(";base64") {
+        return None;
+    }
+    base64::decode(data).ok()
+}
+
+fn load_gltf_buffers(path: &Path, gltf: &gltf::Gltf) -> Result<Vec<Vec<u8>>> {
+    gltf.buffers()
+        .map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Bin => gltf.blob.clone().ok_or_else(|| {
+                Error::InvalidMesh(format!(
+                    "buffer {} refers to a missing BIN chunk",
+                    buffer.index()
+                ))
+            }),
+            gltf::buffer::Source::Uri(uri) if uri.starts_with("data:") => decode_data_uri(uri)
+                .ok_or_else(|| {
+                    Error::InvalidMesh(format!(
+                        "buffer {} has a data URI that is not valid base64",
+                        buffer.index()
+                    ))
+                }),
+            gltf::buffer::Source::Uri(uri) => {
+                let buffer_path = path.parent().unwrap_or_else(|| Path::new("")).join(uri);
+                std::fs::read(&buffer_path).map_err(|err| Error::io(buffer_path, err))
+            }
+        })
+        .collect()
+}
+
+fn append_gltf_node(
+    builder: &mut MeshBuilder,
+    buffers: &[Vec<u8>],
+    node: gltf::Node,
+    parent_transform: Matrix4<f32>,
+) {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+    // Normals are transformed by the inverse transpose, which keeps them perpendicular to the
+    // surface under non-uniform scaling.
+    let linear = Matrix3::from_cols(
+        transform.x.truncate(),
+        transform.y.truncate(),
+        transform.z.truncate(),
+    );
+    let normal_matrix = linear
+        .invert()
+        .map(|inverse| inverse.transpose())
+        .unwrap_or(linear);
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                warn!(
+                    "skipping primitive {} of mesh {}: unsupported mode {:?}",
+                    primitive.index(),
+                    mesh.index(),
+                    primitive.mode()
+                );
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions = match reader.read_positions() {
+                Some(positions) => positions
+                    .map(|p| transform.transform_point(Point3::from(p)).into())
+                    .collect(),
+                None => continue,
+            };
+            let uvs = reader
+                .read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().collect())
+                .unwrap_or_default();
+            let normals = reader
+                .read_normals()
+                .map(|normals| {
+                    normals
+                        .map(|n| {
+                            let n = normal_matrix * Vector3::from(n);
+                            if n.magnitude2() > 0.0 {
+                                n.normalize().into()
+                            } else {
+                                n.into()
+                            }
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let indices = reader
+                .read_indices()
+                .map(|indices| indices.into_u32().collect())
+                .unwrap_or_default();
+            builder.append(positions, uvs, normals, indices);
+        }
+    }
+    for child in node.children() {
+        append_gltf_node(builder, buffers, child, transform);
+    }
+}
+
+/// Loads the default scene of a glTF (`.gltf` or `.glb`) file as one model, with node
+/// transforms applied to the vertex positions and normals.
+pub fn load_gltf(path: &Path) -> Result<ModelData> {
+    let gltf = gltf::Gltf::open(path).map_err(|source| Error::Gltf {
+        path: path.into(),
+        source,
+    })?;
+    let buffers = load_gltf_buffers(path, &gltf)?;
+    let scene = gltf
+        .default_scene()
+        .or_else(|| gltf.scenes().next())
+        .ok_or_else(|| Error::InvalidMesh(format!("{:?} contains no scene", path)))?;
+    let mut builder = MeshBuilder::default();
+    for node in scene.nodes() {
+        append_gltf_node(&mut builder, &buffers, node, Matrix4::from_scale(1.0));
+    }
+    builder.build().validated_structure(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing;
+
+    /// Writes `contents` to a file named `name` in the temporary directory.
+    fn write_temp(name: &str, contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("hello-wgpu-{}", name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn keeps_degenerate_triangles_for_welding() {
+        // A quad split along its diagonal, followed by a triangle with no area.
+        let path = write_temp(
+            "messy.obj",
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 2 2 0\nvn 0 0 1\n\
+             f 1//1 2//1 3//1\nf 1//1 3//1 4//1\nf 1//1 3//1 5//1\n",
+        );
+        let model = load_obj(&path).unwrap();
+        assert_eq!(model.indices().len(), 9);
+        assert_eq!(model.normals().len(), model.positions().len());
+        assert!(model
+            .normals()
+            .iter()
+            .all(|&normal| normal == [0.0, 0.0, 1.0]));
+        assert!(model.validate().is_err());
+
+        let welded = processing::weld(&model, 1e-5).unwrap();
+        assert!(welded.validate().is_ok());
+        assert_eq!(welded.indices().len(), 6);
+        assert_eq!(welded.positions().len(), 4);
+        assert_eq!(welded.normals().len(), 4);
+    }
+
+    #[test]
+    fn rejects_broken_structure() {
+        let path = write_temp("broken.obj", "v 0 0 0\nv 1 0 0\nf 1 2 3\n");
+        assert!(load_obj(&path).is_err());
+    }
+
+    #[test]
+    fn transforms_gltf_normals() {
+        let floats: [f32; 18] = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, //
+            0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0,
+        ];
+        let data = base64::encode(bytemuck::cast_slice::<f32, u8>(&floats));
+        let gltf = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [{{"mesh": 0, "rotation": [0.7071068, 0, 0, 0.7071068], "scale": [2, 1, 1]}}],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1}}}}]}}],
+                "buffers": [{{"byteLength": 72, "uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [{{"buffer": 0, "byteLength": 72}}],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3,
+                      "type": "VEC3"}}
+                ]
+            }}"#,
+            data
+        );
+        let model = load_gltf(&write_temp("rotated.gltf", &gltf)).unwrap();
+        assert!(model.uvs().iter().all(|&uv| uv == [0.0, 0.0]));
+        // Rotated a quarter turn around x, so +z becomes -y.
+        for normal in model.normals() {
+            let expected = Vector3::new(0.0, -1.0, 0.0);
+            assert!((Vector3::from(*normal) - expected).magnitude() < 1e-5);
+        }
+        assert!((model.positions()[1][0] - 2.0).abs() < 1e-5);
+    }
+}
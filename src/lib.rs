@@ -0,0 +1,28 @@
+pub mod bounds;
+pub mod camera;
+pub mod debug_draw;
+pub mod debug_view;
+pub mod environment;
+pub mod error;
+pub mod export;
+pub mod grid;
+mod id_buffer;
+pub mod import;
+pub mod light;
+pub mod lod;
+pub mod material;
+pub mod mesh_format;
+pub mod model;
+pub mod picking;
+pub mod post;
+pub mod primitives;
+pub mod processing;
+mod renderer;
+pub mod shadow;
+pub mod skybox;
+pub mod terrain;
+pub mod texture;
+pub mod tonemap;
+
+pub use error::{Error, Result};
+pub use renderer::{CullingStats, GpuPick, Pickable, Renderer, Shading};
@@ -0,0 +1,338 @@
+//! Lights in physical units, for the physically based shading model.
+
+use cgmath::{InnerSpace, Point3, Rad, Vector3};
+use log::*;
+
+/// Most point lights that are drawn at once.
+pub const MAX_POINT_LIGHTS: usize = 8;
+
+/// Most spot lights that are drawn at once.
+pub const MAX_SPOT_LIGHTS: usize = 4;
+
+/// The luminance in nits that the scene is rendered with as 1, which EV100 15 exposes to white.
+/// Lights are scaled into these units so that bright highlights fit into the half floats of the
+/// HDR target, and colors that aren't lit, like the sky, the grid and unlit models, show as given
+/// at that exposure.
+pub const SCENE_WHITE: f32 = 1.2 * 32768.0;
+
+/// Light from infinitely far away, such as the sun.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    /// The direction the light travels in.
+    pub direction: Vector3<f32>,
+    /// Linear RGB, scaled by `illuminance`.
+    pub color: [f32; 3],
+    /// In lux, on surfaces facing the light. Direct sunlight is about 100 000 lx.
+    pub illuminance: f32,
+    /// Whether objects block the light, see [`ShadowOptions`](crate::shadow::ShadowOptions).
+    pub casts_shadows: bool,
+}
+
+/// Light shining equally in all directions from a point, such as a light bulb.
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+    /// Linear RGB, scaled by `intensity`.
+    pub color: [f32; 3],
+    /// In candela. A 100 W incandescent bulb is about 140 cd.
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely, which bounds its influence.
+    pub range: f32,
+}
+
+/// Light shining from a point in a cone, such as a flashlight.
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    /// The direction of the axis of the cone.
+    pub direction: Vector3<f32>,
+    /// Linear RGB, scaled by `intensity`.
+    pub color: [f32; 3],
+    /// In candela, inside of the inner cone.
+    pub intensity: f32,
+    /// Distance at which the light has faded out completely, which bounds its influence.
+    pub range: f32,
+    /// Angle between the axis and the edge of the fully lit inner cone.
+    pub inner_angle: Rad<f32>,
+    /// Angle between the axis and the edge of the cone, where the light has faded out.
+    pub outer_angle: Rad<f32>,
+    /// Whether the light is blocked by objects.
+    pub casts_shadows: bool,
+}
+
+/// The lights of a scene and the exposure turning the light reaching the camera into colors.
+#[derive(Clone, Debug)]
+pub struct Lights {
+    pub directional: Option<DirectionalLight>,
+    /// Only the first [`MAX_POINT_LIGHTS`] are drawn.
+    pub points: Vec<PointLight>,
+    /// Only the first [`MAX_SPOT_LIGHTS`] are drawn.
+    pub spots: Vec<SpotLight>,
+    /// Illuminance in lux of light coming equally from all directions, per RGB channel. Stands
+    /// in for the light of the sky and light bouncing off surroundings.
+    pub ambient: [f32; 3],
+    /// Luminance in nits of a value of 1 in the environment map, see
+    /// [`Renderer::set_environment`](crate::Renderer::set_environment). The environment lights
+    /// the scene in addition to `ambient`.
+    pub environment_intensity: f32,
+    /// Exposure value at ISO 100, like a camera setting. 15 suits scenes in direct sunlight,
+    /// lower values brighten the image. Applies to everything drawn, when the scene is tonemapped.
+    pub ev100: f32,
+}
+
+impl Default for Lights {
+    /// A sunny day.
+    fn default() -> Self {
+        Self {
+            directional: Some(DirectionalLight {
+                direction: Vector3::new(-0.4, -1.0, -0.6),
+                color: [1.0, 0.96, 0.9],
+                illuminance: 100_000.0,
+                casts_shadows: true,
+            }),
+            points: Vec::new(),
+            spots: Vec::new(),
+            ambient: [15_000.0, 17_000.0, 20_000.0],
+            environment_intensity: 10_000.0,
+            ev100: 15.0,
+        }
+    }
+}
+
+impl Lights {
+    /// The factor from luminance in nits to display values, for a camera at `ev100` with the
+    /// usual saturation based sensitivity.
+    pub fn exposure(&self) -> f32 {
+        1.0 / (1.2 * 2.0f32.powf(self.ev100))
+    }
+
+    /// The factor from the colors the scene is rendered with to display values, 1 at EV100 15,
+    /// see [`SCENE_WHITE`].
+    pub fn scene_exposure(&self) -> f32 {
+        self.exposure() * SCENE_WHITE
+    }
+
+    /// The lights in the units of [`SCENE_WHITE`].
+    pub fn to_uniform(&self) -> LightsUniform {
+        let scale = |[r, g, b]: [f32; 3], intensity: f32| {
+            let factor = intensity / SCENE_WHITE;
+            [r * factor, g * factor, b * factor]
+        };
+        let mut uniform = LightsUniform {
+            directional_direction: [0.0; 4],
+            directional_color: [0.0; 4],
+            ambient: [0.0; 4],
+            environment: [self.environment_intensity / SCENE_WHITE, 0.0, 0.0, 0.0],
+            counts: [0; 4],
+            points: [PointLightUniform {
+                position: [0.0; 4],
+                color: [0.0; 4],
+            }; MAX_POINT_LIGHTS],
+            spots: [SpotLightUniform {
+                position: [0.0; 4],
+                direction: [0.0; 4],
+                color: [0.0; 4],
+            }; MAX_SPOT_LIGHTS],
+        };
+        if let Some(light) = &self.directional {
+            let direction = light.direction.normalize();
+            uniform.directional_direction = [direction.x, direction.y, direction.z, 0.0];
+            let [r, g, b] = scale(light.color, light.illuminance);
+            uniform.directional_color = [r, g, b, 0.0];
+        }
+        let [r, g, b] = scale(self.ambient, 1.0);
+        uniform.ambient = [r, g, b, 0.0];
+        if self.points.len() > MAX_POINT_LIGHTS {
+            warn!(
+                "{} point lights, ignoring all but the first {}",
+                self.points.len(),
+                MAX_POINT_LIGHTS
+            );
+        }
+        for (uniform, light) in uniform.points.iter_mut().zip(&self.points) {
+            let position = light.position;
+            uniform.position = [position.x, position.y, position.z, light.range];
+            let [r, g, b] = scale(light.color, light.intensity);
+            uniform.color = [r, g, b, 0.0];
+        }
+        uniform.counts[0] = self.points.len().min(MAX_POINT_LIGHTS) as u32;
+        if self.spots.len() > MAX_SPOT_LIGHTS {
+            warn!(
+                "{} spot lights, ignoring all but the first {}",
+                self.spots.len(),
+                MAX_SPOT_LIGHTS
+            );
+        }
+        for (uniform, light) in uniform.spots.iter_mut().zip(&self.spots) {
+            let position = light.position;
+            uniform.position = [position.x, position.y, position.z, light.range];
+            let direction = light.direction.normalize();
+            let outer = light.outer_angle.0.cos();
+            uniform.direction = [direction.x, direction.y, direction.z, outer];
+            let [r, g, b] = scale(light.color, light.intensity);
+            // Kept apart from the outer cosine, so that the cones fade even if they're equal.
+            uniform.color = [r, g, b, light.inner_angle.0.cos().max(outer + 1e-4)];
+        }
+        uniform.counts[1] = self.spots.len().min(MAX_SPOT_LIGHTS) as u32;
+        uniform
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightUniform {
+    /// The position and the range.
+    position: [f32; 4],
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SpotLightUniform {
+    /// The position and the range.
+    position: [f32; 4],
+    /// The direction and the cosine of the outer angle.
+    direction: [f32; 4],
+    /// The color and the cosine of the inner angle.
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    directional_direction: [f32; 4],
+    /// Color times illuminance, black without a directional light. Like all intensities, in the
+    /// units of [`SCENE_WHITE`].
+    directional_color: [f32; 4],
+    /// The ambient illuminance.
+    ambient: [f32; 4],
+    /// The environment intensity and the last mip level of the prefiltered specular cubemap.
+    environment: [f32; 4],
+    /// The number of point lights and of spot lights.
+    counts: [u32; 4],
+    points: [PointLightUniform; MAX_POINT_LIGHTS],
+    spots: [SpotLightUniform; MAX_SPOT_LIGHTS],
+}
+
+impl LightsUniform {
+    /// Sets the number of mip levels of the prefiltered specular cubemap of the environment.
+    pub(crate) fn set_specular_levels(&mut self, levels: u32) {
+        self.environment[1] = levels.saturating_sub(1) as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point_light(intensity: f32) -> PointLight {
+        PointLight {
+            position: Point3::new(1.0, 2.0, 3.0),
+            color: [1.0, 0.5, 0.25],
+            intensity,
+            range: 10.0,
+        }
+    }
+
+    /// Whether `uniform`, in the units of [`SCENE_WHITE`], is `nits`.
+    fn is_nits(uniform: &[f32], nits: &[f32]) -> bool {
+        uniform.len() == nits.len()
+            && uniform
+                .iter()
+                .zip(nits)
+                .all(|(value, nits)| (value * SCENE_WHITE - nits).abs() <= 1e-3 * nits.abs())
+    }
+
+    #[test]
+    fn exposes_by_ev100() {
+        let mut lights = Lights {
+            ev100: 0.0,
+            ..Lights::default()
+        };
+        assert!((lights.exposure() - 1.0 / 1.2).abs() < 1e-6);
+        // The scene is rendered so that EV100 15 shows it as given, and each stop halves that.
+        lights.ev100 = 15.0;
+        assert!((lights.scene_exposure() - 1.0).abs() < 1e-6);
+        lights.ev100 = 16.0;
+        assert!((lights.scene_exposure() - 0.5).abs() < 1e-6);
+        lights.ev100 = 13.0;
+        assert!((lights.scene_exposure() - 4.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scales_colors_by_lux_and_candela() {
+        let lights = Lights {
+            directional: Some(DirectionalLight {
+                direction: Vector3::new(0.0, -2.0, 0.0),
+                color: [1.0, 0.5, 0.0],
+                illuminance: 1000.0,
+                casts_shadows: false,
+            }),
+            points: vec![point_light(140.0)],
+            spots: vec![SpotLight {
+                position: Point3::new(0.0, 0.0, 0.0),
+                direction: Vector3::new(0.0, 0.0, -3.0),
+                color: [0.0, 1.0, 0.0],
+                intensity: 50.0,
+                range: 5.0,
+                inner_angle: Rad(0.0),
+                outer_angle: Rad(std::f32::consts::FRAC_PI_2),
+                casts_shadows: false,
+            }],
+            ambient: [1.0, 2.0, 3.0],
+            environment_intensity: 5000.0,
+            ..Lights::default()
+        };
+        let uniform = lights.to_uniform();
+        assert_eq!(uniform.directional_direction, [0.0, -1.0, 0.0, 0.0]);
+        assert!(is_nits(
+            &uniform.directional_color,
+            &[1000.0, 500.0, 0.0, 0.0]
+        ));
+        assert!(is_nits(&uniform.ambient, &[1.0, 2.0, 3.0, 0.0]));
+        assert!(is_nits(&uniform.environment[..1], &[5000.0]));
+        assert_eq!(uniform.points[0].position, [1.0, 2.0, 3.0, 10.0]);
+        assert!(is_nits(&uniform.points[0].color, &[140.0, 70.0, 35.0, 0.0]));
+        assert_eq!(uniform.spots[0].direction[..3], [0.0, 0.0, -1.0]);
+        assert!(uniform.spots[0].direction[3].abs() < 1e-6);
+        assert!(is_nits(&uniform.spots[0].color[..3], &[0.0, 50.0, 0.0]));
+        assert_eq!(uniform.spots[0].color[3], 1.0);
+        assert_eq!(uniform.counts, [1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn keeps_the_inner_cone_apart_from_the_outer() {
+        let lights = Lights {
+            spots: vec![SpotLight {
+                position: Point3::new(0.0, 0.0, 0.0),
+                direction: Vector3::new(0.0, 0.0, -1.0),
+                color: [1.0; 3],
+                intensity: 1.0,
+                range: 1.0,
+                inner_angle: Rad(0.5),
+                outer_angle: Rad(0.5),
+                casts_shadows: false,
+            }],
+            ..Lights::default()
+        };
+        let uniform = lights.to_uniform();
+        assert!(uniform.spots[0].color[3] > uniform.spots[0].direction[3]);
+    }
+
+    #[test]
+    fn limits_the_number_of_lights() {
+        let lights = Lights {
+            directional: None,
+            points: (0..MAX_POINT_LIGHTS + 3)
+                .map(|index| point_light(index as f32))
+                .collect(),
+            ..Lights::default()
+        };
+        let uniform = lights.to_uniform();
+        assert_eq!(uniform.counts[0], MAX_POINT_LIGHTS as u32);
+        assert_eq!(uniform.counts[1], 0);
+        assert_eq!(uniform.directional_color, [0.0; 4]);
+        let last = &uniform.points[MAX_POINT_LIGHTS - 1];
+        assert!(is_nits(&last.color[..1], &[(MAX_POINT_LIGHTS - 1) as f32]));
+    }
+}
@@ -0,0 +1,223 @@
+// Shared by the lit shaders: the camera, the lights with their shadows and the environment, and
+// the BRDF they are reflected with. The shader of each pipeline follows. Intensities are divided by
+// the luminance rendered as 1, so that bright highlights fit into the HDR target.
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>,
+  position: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct PointLight {
+  // position, range
+  position: vec4<f32>,
+  // color times intensity in candela
+  color: vec4<f32>
+};
+
+struct SpotLight {
+  // position, range
+  position: vec4<f32>,
+  // direction, cosine of the outer angle
+  direction: vec4<f32>,
+  // color times intensity in candela, cosine of the inner angle
+  color: vec4<f32>
+};
+
+struct LightsUniform {
+  directional_direction: vec4<f32>,
+  // color times illuminance in lux
+  directional_color: vec4<f32>,
+  // ambient illuminance in lux
+  ambient: vec4<f32>,
+  // luminance in nits of environment map values, last prefiltered specular level
+  environment: vec4<f32>,
+  // point light count, spot light count
+  counts: vec4<u32>,
+  points: array<PointLight, 8>,
+  spots: array<SpotLight, 4>
+};
+
+@group(3) @binding(0)
+var<uniform> lights: LightsUniform;
+
+// Irradiance divided by pi
+@group(3) @binding(1)
+var t_irradiance: texture_cube<f32>;
+
+@group(3) @binding(2)
+var t_prefiltered: texture_cube<f32>;
+
+// Scale and bias to f0 giving the specular albedo, by n.v and roughness
+@group(3) @binding(3)
+var t_brdf_lut: texture_2d<f32>;
+
+@group(3) @binding(4)
+var s_environment: sampler;
+
+// The cascades of the directional light, then a layer per spot light
+@group(3) @binding(5)
+var t_shadow: texture_depth_2d_array;
+
+@group(3) @binding(6)
+var s_shadow: sampler_comparison;
+
+struct ShadowUniform {
+  cascades: array<mat4x4<f32>, 4>,
+  spots: array<mat4x4<f32>, 4>,
+  // far end of each cascade along the camera's forward direction
+  cascade_splits: vec4<f32>,
+  // world size of a texel of each cascade
+  cascade_texel_sizes: vec4<f32>,
+  camera_forward: vec4<f32>,
+  // 1 for each spot light casting shadows
+  spot_shadows: vec4<f32>,
+  // texel size in UV, PCF radius, normal offset, 1 if the directional light casts shadows
+  params: vec4<f32>
+};
+
+@group(3) @binding(7)
+var<uniform> shadows: ShadowUniform;
+
+let PI: f32 = 3.14159265359;
+
+// Normal distribution of GGX (Trowbridge-Reitz).
+fn distribution_ggx(n_dot_h: f32, alpha: f32) -> f32 {
+  let alpha2 = alpha * alpha;
+  let d = n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0;
+  return alpha2 / (PI * d * d);
+}
+
+// Height correlated Smith visibility, the geometry term divided by 4 n.l n.v.
+fn visibility_smith_ggx(n_dot_v: f32, n_dot_l: f32, alpha: f32) -> f32 {
+  let alpha2 = alpha * alpha;
+  let ggx_v = n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - alpha2) + alpha2);
+  let ggx_l = n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - alpha2) + alpha2);
+  return 0.5 / max(ggx_v + ggx_l, 1e-5);
+}
+
+fn fresnel_schlick(v_dot_h: f32, f0: vec3<f32>) -> vec3<f32> {
+  return f0 + (vec3<f32>(1.0) - f0) * pow(1.0 - v_dot_h, 5.0);
+}
+
+struct Surface {
+  normal: vec3<f32>,
+  to_eye: vec3<f32>,
+  diffuse_color: vec3<f32>,
+  // reflectance at normal incidence
+  f0: vec3<f32>,
+  // perceptual roughness, and its square
+  roughness: f32,
+  alpha: f32
+};
+
+// Light reflected towards the eye per unit of illuminance arriving from `to_light`.
+fn brdf(surface: Surface, to_light: vec3<f32>) -> vec3<f32> {
+  let n_dot_l = dot(surface.normal, to_light);
+  if (n_dot_l <= 0.0) {
+    return vec3<f32>(0.0);
+  }
+  let half_vector = normalize(surface.to_eye + to_light);
+  let n_dot_v = max(dot(surface.normal, surface.to_eye), 1e-4);
+  let n_dot_h = max(dot(surface.normal, half_vector), 0.0);
+  let v_dot_h = max(dot(surface.to_eye, half_vector), 0.0);
+  let fresnel = fresnel_schlick(v_dot_h, surface.f0);
+  let specular = fresnel * distribution_ggx(n_dot_h, surface.alpha)
+    * visibility_smith_ggx(n_dot_v, n_dot_l, surface.alpha);
+  // Light reflected specularly doesn't enter the surface to be scattered diffusely.
+  let diffuse = (vec3<f32>(1.0) - fresnel) * surface.diffuse_color / PI;
+  return (diffuse + specular) * n_dot_l;
+}
+
+// The fraction of light reaching `position` past the casters in shadow map `layer`, filtered
+// over a square of texels around it.
+fn sample_shadow(layer: i32, light_space: mat4x4<f32>, position: vec3<f32>) -> f32 {
+  let clip = light_space * vec4<f32>(position, 1.0);
+  let ndc = clip.xyz / clip.w;
+  if (clip.w <= 0.0 || any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0) {
+    return 1.0;
+  }
+  let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+  let step = shadows.params.x * shadows.params.y;
+  var lit = 0.0;
+  for (var x = -1; x <= 1; x = x + 1) {
+    for (var y = -1; y <= 1; y = y + 1) {
+      let offset = vec2<f32>(f32(x), f32(y)) * step;
+      lit = lit + textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, layer, ndc.z);
+    }
+  }
+  return lit / 9.0;
+}
+
+// Shadowing of the directional light, from the cascade covering `position`.
+fn directional_shadow(position: vec3<f32>, normal: vec3<f32>) -> f32 {
+  if (shadows.params.w == 0.0) {
+    return 1.0;
+  }
+  let depth = dot(position - camera.position.xyz, shadows.camera_forward.xyz);
+  for (var i = 0; i < 4; i = i + 1) {
+    if (depth <= shadows.cascade_splits[i]) {
+      let offset = normal * shadows.params.z * shadows.cascade_texel_sizes[i];
+      return sample_shadow(i, shadows.cascades[i], position + offset);
+    }
+  }
+  return 1.0;
+}
+
+// Shadowing of spot light `index`, `distance` away from `position`.
+fn spot_shadow(index: u32, position: vec3<f32>, normal: vec3<f32>, distance: f32) -> f32 {
+  if (shadows.spot_shadows[index] == 0.0) {
+    return 1.0;
+  }
+  // Texels grow with the distance from the light, roughly as for a 90 degree cone.
+  let offset = normal * shadows.params.z * shadows.params.x * 2.0 * distance;
+  return sample_shadow(4 + i32(index), shadows.spots[index], position + offset);
+}
+
+// Luminance, divided like the intensities, reflected towards the eye by `surface` at `position`, from the environment
+// and the lights. Shadow lookups are offset along `geometric_normal`.
+fn shade(surface: Surface, position: vec3<f32>, geometric_normal: vec3<f32>) -> vec3<f32> {
+  // Light from all around, split into a diffuse and a specular part. The ambient illuminance is
+  // that of a uniform sky, which has the same radiance in every direction.
+  let n_dot_v = clamp(dot(surface.normal, surface.to_eye), 0.0, 1.0);
+  let lut_uv = vec2<f32>(n_dot_v, surface.roughness);
+  let lut = textureSampleLevel(t_brdf_lut, s_environment, lut_uv, 0.0).xy;
+  let specular_albedo = surface.f0 * lut.x + lut.y;
+  let reflected = reflect(-surface.to_eye, surface.normal);
+  let max_level = lights.environment.y;
+  let sky = lights.ambient.rgb / PI;
+  let intensity = lights.environment.x;
+  let irradiance = textureSampleLevel(t_irradiance, s_environment, surface.normal, 0.0).rgb;
+  let diffuse_light = sky + irradiance * intensity;
+  let level = surface.roughness * max_level;
+  let prefiltered = textureSampleLevel(t_prefiltered, s_environment, reflected, level).rgb;
+  let specular_light = sky + prefiltered * intensity;
+
+  var luminance = diffuse_light * surface.diffuse_color + specular_light * specular_albedo;
+  luminance = luminance + brdf(surface, -lights.directional_direction.xyz)
+    * lights.directional_color.rgb * directional_shadow(position, geometric_normal);
+  for (var i = 0u; i < lights.counts.x; i = i + 1u) {
+    let light = lights.points[i];
+    let to_light = light.position.xyz - position;
+    let distance2 = max(dot(to_light, to_light), 1e-4);
+    // Inverse square falloff, smoothly windowed to reach zero at the light's range.
+    let window = clamp(1.0 - pow(distance2 / (light.position.w * light.position.w), 2.0), 0.0, 1.0);
+    let illuminance = light.color.rgb / distance2 * window * window;
+    luminance = luminance + brdf(surface, normalize(to_light)) * illuminance;
+  }
+  for (var i = 0u; i < lights.counts.y; i = i + 1u) {
+    let light = lights.spots[i];
+    let to_light = light.position.xyz - position;
+    let distance2 = max(dot(to_light, to_light), 1e-4);
+    let window = clamp(1.0 - pow(distance2 / (light.position.w * light.position.w), 2.0), 0.0, 1.0);
+    let direction = normalize(to_light);
+    // Fades from the inner cone to the outer one.
+    let cone = smoothstep(light.direction.w, light.color.w, dot(-direction, light.direction.xyz));
+    let shadow = spot_shadow(i, position, geometric_normal, sqrt(distance2));
+    let illuminance = light.color.rgb / distance2 * window * window * cone * shadow;
+    luminance = luminance + brdf(surface, direction) * illuminance;
+  }
+  return luminance;
+}
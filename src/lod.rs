@@ -0,0 +1,185 @@
+//! Levels of detail: progressively simplified versions of a model, chosen by how large the model
+//! appears on screen.
+
+use crate::camera::Camera;
+use crate::error::Result;
+use crate::material::Material;
+use crate::model::{Model, ModelData};
+use crate::processing;
+use log::*;
+use std::sync::Arc;
+
+pub struct LodOptions {
+    /// Most levels to generate, including the full detail one.
+    pub max_levels: usize,
+    /// Fraction of the triangles of one level kept in the next.
+    pub reduction: f32,
+    /// Screen size, as a fraction of the viewport height, below which the first simplified level
+    /// is drawn.
+    pub screen_size: f32,
+    /// How far simplification may move the surface of a level away from the full detail one, as
+    /// a fraction of the radius of the model's bounding sphere.
+    pub max_error: f32,
+    /// Fewest triangles a simplified level may have.
+    pub min_triangles: usize,
+}
+
+impl Default for LodOptions {
+    fn default() -> Self {
+        Self {
+            max_levels: 4,
+            reduction: 0.5,
+            screen_size: 0.5,
+            max_error: 0.01,
+            min_triangles: 32,
+        }
+    }
+}
+
+impl LodOptions {
+    /// Only the full detail level.
+    pub fn none() -> Self {
+        Self {
+            max_levels: 1,
+            ..Self::default()
+        }
+    }
+}
+
+/// Simplifies `model_data` into a chain of levels, starting with `model_data` itself. Generation
+/// stops early once a level would have fewer than `min_triangles` triangles or simplification
+/// within `max_error` no longer gets close to the triangle count asked for.
+pub fn generate_lods(model_data: &ModelData, options: &LodOptions) -> Result<Vec<ModelData>> {
+    let reduction = options.reduction.clamp(0.0, 1.0);
+    let mut levels = vec![model_data.clone()];
+    while levels.len() < options.max_levels {
+        let previous = levels.last().unwrap().indices().len() / 3;
+        let target = (previous as f32 * reduction) as usize;
+        if target < options.min_triangles.max(1) {
+            break;
+        }
+        // Each level is simplified from the full detail one, so that errors don't add up.
+        let level = processing::simplify(model_data, target, options.max_error)?;
+        let triangles = level.indices().len() / 3;
+        if triangles < options.min_triangles.max(1)
+            || triangles as f32 > previous as f32 * (1.0 + reduction) / 2.0
+        {
+            break;
+        }
+        levels.push(level);
+    }
+    Ok(levels)
+}
+
+/// The GPU side of a level of detail chain.
+pub struct LodChain {
+    levels: Vec<Model>,
+    /// For each level after the first, the screen size below which it is drawn.
+    screen_sizes: Vec<f32>,
+}
+
+impl LodChain {
+    pub fn new(
+        device: &wgpu::Device,
+        model_data: &ModelData,
+        options: &LodOptions,
+    ) -> Result<Self> {
+        let lods = generate_lods(model_data, options)?;
+        debug!(
+            "generated {} levels of detail with {:?} triangles",
+            lods.len(),
+            lods.iter()
+                .map(|level| level.indices().len() / 3)
+                .collect::<Vec<_>>()
+        );
+        let levels = lods
+            .iter()
+            .map(|level| Model::new(device, level))
+            .collect::<Result<Vec<_>>>()?;
+        // Each level keeps `reduction` of the triangles on `reduction` of the screen area, so
+        // the triangle density on screen stays about the same.
+        let step = options.reduction.clamp(0.0, 1.0).sqrt();
+        let screen_sizes = (0..levels.len() - 1)
+            .map(|i| options.screen_size * step.powi(i as i32))
+            .collect();
+        Ok(Self {
+            levels,
+            screen_sizes,
+        })
+    }
+
+    pub fn levels(&self) -> &[Model] {
+        &self.levels
+    }
+
+    /// Sets the material every level is drawn with.
+    pub fn set_material(&mut self, material: Option<Arc<Material>>) {
+        for level in &mut self.levels {
+            level.set_material(material.clone());
+        }
+    }
+
+    /// The index of the level to draw when seen through `camera`.
+    pub fn select(&self, camera: &Camera) -> usize {
+        let screen_size = camera.screen_size(self.levels[0].bounding_sphere());
+        self.screen_sizes
+            .iter()
+            .take_while(|&&threshold| screen_size < threshold)
+            .count()
+    }
+
+    /// The level to draw when seen through `camera`.
+    pub fn level(&self, camera: &Camera) -> &Model {
+        &self.levels[self.select(camera)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+    fn triangle_counts(levels: &[ModelData]) -> Vec<usize> {
+        levels
+            .iter()
+            .map(|level| level.indices().len() / 3)
+            .collect()
+    }
+
+    #[test]
+    fn rectangles_keep_one_level() {
+        let rectangle = primitives::plane(2.0, 1.0, 1);
+        let levels = generate_lods(&rectangle, &LodOptions::default()).unwrap();
+        assert_eq!(triangle_counts(&levels), [2]);
+    }
+
+    #[test]
+    fn none_keeps_full_detail() {
+        let sphere = primitives::uv_sphere(1.0, 32, 16);
+        let levels = generate_lods(&sphere, &LodOptions::none()).unwrap();
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].indices(), sphere.indices());
+    }
+
+    #[test]
+    fn levels_get_coarser() {
+        let options = LodOptions {
+            max_error: 0.1,
+            ..LodOptions::default()
+        };
+        let sphere = primitives::uv_sphere(1.0, 32, 16).with_material("sphere.material");
+        let levels = generate_lods(&sphere, &options).unwrap();
+        assert!(levels.len() > 1);
+        let counts = triangle_counts(&levels);
+        assert!(
+            counts.windows(2).all(|pair| pair[1] < pair[0]),
+            "{:?}",
+            counts
+        );
+        assert!(counts.iter().all(|&count| count >= options.min_triangles));
+        for level in &levels {
+            assert!(level.validate().is_ok());
+            assert_eq!(level.material(), sphere.material());
+        }
+    }
+}
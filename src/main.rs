@@ -1,85 +1,41 @@
-use crate::texture::Texture;
-use anyhow::{Context, Result};
-use image::ImageFormat::Png;
+use cgmath::{EuclideanSpace, MetricSpace, Point3};
+use hello_wgpu::bounds::{Aabb, BoundingSphere};
+use hello_wgpu::debug_draw::DebugDraw;
+use hello_wgpu::debug_view::DebugView;
+use hello_wgpu::environment::EnvironmentOptions;
+use hello_wgpu::grid::Grid;
+use hello_wgpu::picking::Ray;
+use hello_wgpu::post::{Bloom, ColorGrading, Effect, Fxaa, Grayscale, Lut, PostEffect, Vignette};
+use hello_wgpu::skybox::{Background, SkyGradient, Skybox};
+use hello_wgpu::terrain::{Heightmap, TerrainOptions};
+use hello_wgpu::tonemap::{Tonemap, Tonemapping};
+use hello_wgpu::{import, CullingStats, Renderer, Shading};
 use log::LevelFilter;
-use model::{Model, ModelData, Vertex};
-use std::error::Error;
-use std::fmt::{Display, Formatter};
-use std::fs::File;
-use std::path::Path;
-use wgpu::include_wgsl;
-use wgpu::util::DeviceExt;
+use std::convert::TryInto;
+use std::path::PathBuf;
+use std::sync::Arc;
 use winit::{
+    dpi::PhysicalPosition,
     event::*,
     event_loop::{ControlFlow, EventLoop},
-    window::Window,
     window::WindowBuilder,
 };
 
-mod camera;
-mod model;
-mod texture;
+const USAGE: &str = "usage: hello-wgpu [--model <model>] [--heightmap <image>] \
+                     [--environment <hdr image>] [--skybox <+x> <-x> <+y> <-y> <+z> <-z>] \
+                     [--msaa <1|2|4>] [--lut <image>]";
 
-#[derive(Debug)]
-struct GraphicsError(&'static str);
+/// World units the terrain spans along its longer side.
+const TERRAIN_EXTENT: f32 = 10.0;
 
-impl Display for GraphicsError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.0)
-    }
-}
-
-impl Error for GraphicsError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        None
-    }
-
-    fn description(&self) -> &str {
-        self.0
-    }
-
-    fn cause(&self) -> Option<&dyn Error> {
-        None
-    }
-}
-
-struct Flip<T> {
-    alternatives: [T; 2],
-    state: bool,
-}
+/// How far the overlay draws picking rays that miss.
+const PICK_RAY_LENGTH: f32 = 100.0;
 
-impl<T> Flip<T> {
-    pub fn new(first: T, second: T) -> Flip<T> {
-        Flip {
-            alternatives: [first, second],
-            state: false,
-        }
-    }
+/// Size of the cube faces of environment maps, which are also shown as the sky.
+const ENVIRONMENT_SIZE: u32 = 1024;
 
-    pub fn flip(&mut self) {
-        self.state = !self.state;
-    }
-
-    pub fn get(&self) -> &T {
-        &self.alternatives[if self.state { 1 } else { 0 }]
-    }
-}
-
-struct State {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    size: winit::dpi::PhysicalSize<u32>,
-    background_color: wgpu::Color,
-    render_pipelines: Flip<wgpu::RenderPipeline>,
-    model: Model,
-    diffuse_bind_group: wgpu::BindGroup,
-    diffuse_texture: Texture,
-    camera: camera::Camera,
-    camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
-}
+/// Texels per side of the color grading table used without `--lut`.
+const LUT_SIZE: u32 = 16;
 
 fn interpolate_color(from: wgpu::Color, to: wgpu::Color, factor: f64) -> wgpu::Color {
     wgpu::Color {
@@ -90,380 +46,459 @@ fn interpolate_color(from: wgpu::Color, to: wgpu::Color, factor: f64) -> wgpu::C
     }
 }
 
-impl State {
-    fn make_pipeline(
-        device: &wgpu::Device,
-        shader: &wgpu::ShaderModule,
-        config: &wgpu::SurfaceConfiguration,
-        bind_group_layouts: &[&wgpu::BindGroupLayout],
-    ) -> wgpu::RenderPipeline {
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts,
-                push_constant_ranges: &[],
-            });
-
-        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        })
+/// The background after `background`: the clear color, then a gradient, then `skybox`.
+fn next_background(background: &Background, skybox: Option<&Arc<Skybox>>) -> Background {
+    match (background, skybox) {
+        (Background::Color, _) => Background::Gradient(SkyGradient::default()),
+        (Background::Gradient(_), Some(skybox)) => Background::Skybox(skybox.clone()),
+        _ => Background::Color,
     }
+}
 
-    pub async fn new(window: &Window) -> Result<Self> {
-        let size = window.inner_size();
-        let instance = wgpu::Instance::new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(window) };
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .ok_or(GraphicsError("Creating adapter failed"))?;
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: Some("Device"),
-                },
-                None,
-            )
-            .await?;
-        let preferred_format = *surface.get_supported_formats(&adapter).first().ok_or(
-            GraphicsError("No supported formats"),
-        )?;
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: preferred_format,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-        };
-        surface.configure(&device, &config);
-
-        let image = image::load(
-            std::io::BufReader::new(
-                File::open("assets/tree.png").context("failed to open assets/tree.png")?,
-            ),
-            Png,
-        )
-        .context("failed to read tree as PNG")?;
-        let diffuse_texture =
-            texture::Texture::from_image(&device, &queue, &image, Some("tree.png"))?;
-
-        let texture_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                ],
-                label: Some("texture_bind_group_layout"),
-            });
-
-        let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &texture_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(diffuse_texture.view()),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(diffuse_texture.sampler()),
-                },
-            ],
-            label: Some("diffuse_bind_group"),
-        });
-
-        let background_color = wgpu::Color {
-            r: 1.0,
-            g: 1.0,
-            b: 1.0,
-            a: 1.0,
-        };
-
-        let shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
+/// The debug view after `view`: each of [`DebugView::ALL`], then shading again.
+fn next_debug_view(view: Option<DebugView>) -> Option<DebugView> {
+    let next = match view {
+        Some(view) => {
+            DebugView::ALL
+                .iter()
+                .position(|&other| other == view)
+                .unwrap()
+                + 1
+        }
+        None => 0,
+    };
+    DebugView::ALL.get(next).copied()
+}
 
-        let camera = camera::Camera::new(&config);
+/// Lines drawn over the scene while `enabled`: the bounds of the model, the world axes and the
+/// last picking ray.
+struct Overlay {
+    enabled: bool,
+    model_bounds: (Aabb, BoundingSphere),
+    /// The ray of the last pick, and where it hit.
+    pick: Option<(Ray, Option<Point3<f32>>)>,
+}
 
-        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Camera Buffer"),
-            contents: bytemuck::cast_slice(&[camera.to_uniform()]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+impl Overlay {
+    fn draw(&self, debug_draw: &mut DebugDraw) {
+        if !self.enabled {
+            return;
+        }
+        let (aabb, sphere) = &self.model_bounds;
+        debug_draw.aabb(aabb, [1.0, 1.0, 0.0]);
+        debug_draw.sphere(sphere, [0.0, 1.0, 1.0]);
+        debug_draw.axes(Point3::origin(), 1.0);
+        if let Some((ray, hit)) = &self.pick {
+            let length = hit.map_or(PICK_RAY_LENGTH, |hit| hit.distance(ray.origin));
+            debug_draw.ray(ray, length, [1.0, 0.0, 1.0]);
+            if let Some(hit) = hit {
+                debug_draw.axes(*hit, 0.1);
+            }
+        }
+    }
+}
 
-        let camera_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }],
-                label: Some("camera_bind_group_layout"),
-            });
+/// Changes the tonemapping of `renderer` with `change`.
+fn adjust_tonemapping(renderer: &mut Renderer, change: impl FnOnce(&mut Tonemapping)) {
+    let mut tonemapping = *renderer.tonemapping();
+    change(&mut tonemapping);
+    log::info!(
+        "{:?} tonemapping, gamma {:.1}",
+        tonemapping.operator,
+        tonemapping.gamma
+    );
+    renderer.set_tonemapping(tonemapping);
+}
 
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: camera_buffer.as_entire_binding(),
-            }],
-            label: Some("camera_bind_group"),
-        });
+/// Changes the exposure of `renderer` by `stops`, where positive values darken the image.
+fn adjust_exposure(renderer: &mut Renderer, stops: f32) {
+    let lights = renderer.lights_mut();
+    lights.ev100 += stops;
+    log::info!("EV100 {:.1}", lights.ev100);
+}
 
-        let render_pipeline = Self::make_pipeline(
-            &device,
-            &shader,
-            &config,
-            &[&texture_bind_group_layout, &camera_bind_group_layout],
-        );
-        let shader_alter = device.create_shader_module(include_wgsl!("shader_alter.wgsl"));
-        let render_pipeline_alter = Self::make_pipeline(
-            &device,
-            &shader_alter,
-            &config,
-            &[&texture_bind_group_layout],
+/// Turns the post-processing effect at `index` on or off.
+fn toggle_post_effect(renderer: &mut Renderer, index: usize) {
+    if let Some(post_effect) = renderer.post_effects_mut().get_mut(index) {
+        post_effect.enabled = !post_effect.enabled;
+        log::info!(
+            "{} {}",
+            post_effect.effect.name(),
+            if post_effect.enabled { "on" } else { "off" }
         );
-
-        let render_pipelines = Flip::new(render_pipeline, render_pipeline_alter);
-
-        let model_data = ModelData::load(Path::new("assets/rectangle.model"))?;
-        let model = Model::new(&device, &model_data)?;
-
-        Ok(Self {
-            surface,
-            device,
-            queue,
-            config,
-            size,
-            background_color,
-            render_pipelines,
-            model,
-            diffuse_bind_group,
-            diffuse_texture,
-            camera,
-            camera_buffer,
-            camera_bind_group,
-        })
     }
+}
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        println!("Resizing to {:?}", new_size);
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+fn input(
+    renderer: &mut Renderer,
+    cursor_position: &mut PhysicalPosition<f64>,
+    skybox: Option<&Arc<Skybox>>,
+    overlay: &mut Overlay,
+    event: &WindowEvent,
+) -> bool {
+    match event {
+        WindowEvent::CursorMoved { position, .. } => {
+            *cursor_position = *position;
+            let left_color = wgpu::Color {
+                r: 1.0,
+                g: 0.0,
+                b: 0.2,
+                a: 1.0,
+            };
+            let right_color = wgpu::Color {
+                r: 0.0,
+                g: 1.0,
+                b: 0.2,
+                a: 1.0,
+            };
+            let (width, _) = renderer.size();
+            renderer.set_background_color(interpolate_color(
+                left_color,
+                right_color,
+                position.x / width as f64,
+            ));
+            true
         }
-    }
-
-    fn input(&mut self, event: &WindowEvent) -> bool {
-        match event {
-            WindowEvent::CursorMoved { position, .. } => {
-                let left_color = wgpu::Color {
-                    r: 1.0,
-                    g: 0.0,
-                    b: 0.2,
-                    a: 1.0,
-                };
-                let right_color = wgpu::Color {
-                    r: 0.0,
-                    g: 1.0,
-                    b: 0.2,
-                    a: 1.0,
-                };
-                self.background_color =
-                    interpolate_color(left_color, right_color, position.x / self.size.width as f64);
-                true
+        WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+            ..
+        } => {
+            let hit = renderer.pick(cursor_position.x, cursor_position.y);
+            match hit {
+                Some((picked, hit)) => log::info!(
+                    "Picked {:?}, triangle {} at uv {:?}",
+                    picked,
+                    hit.triangle,
+                    hit.uv
+                ),
+                None => log::info!("Picked nothing"),
             }
-            WindowEvent::KeyboardInput { input, .. } if input.state == ElementState::Pressed => {
-                input.virtual_keycode.map_or(false, |vkey| match vkey {
-                    VirtualKeyCode::Space => {
-                        self.render_pipelines.flip();
-                        true
-                    }
-                    VirtualKeyCode::W => {
-                        self.camera.pan((0.0, 0.0, -0.01));
-                        true
-                    }
-                    VirtualKeyCode::S => {
-                        self.camera.pan((0.0, 0.0, 0.01));
-                        true
-                    }
-                    VirtualKeyCode::A => {
-                        self.camera.pan((-0.01, 0.0, 0.0));
-                        true
-                    }
-                    VirtualKeyCode::D => {
-                        self.camera.pan((0.01, 0.0, 0.0));
-                        true
-                    }
-                    VirtualKeyCode::PageUp => {
-                        self.camera.pan((0.0, 0.01, 0.0));
-                        true
-                    }
-                    VirtualKeyCode::PageDown => {
-                        self.camera.pan((0.0, -0.01, 0.0));
-                        true
-                    }
-                    VirtualKeyCode::Q => {
-                        self.camera.rotate_h(-0.04);
-                        true
-                    }
-                    VirtualKeyCode::E => {
-                        self.camera.rotate_h(0.04);
-                        true
-                    }
-                    VirtualKeyCode::Up => {
-                        self.camera.rotate_v(0.04);
-                        true
-                    }
-                    VirtualKeyCode::Down => {
-                        self.camera.rotate_v(-0.04);
-                        true
+            let (width, height) = renderer.size();
+            let ray =
+                renderer
+                    .camera()
+                    .cursor_ray(cursor_position.x, cursor_position.y, width, height);
+            overlay.pick = Some((ray, hit.map(|(_, hit)| hit.position)));
+            renderer.select(hit.map(|(picked, _)| picked));
+            true
+        }
+        WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Right,
+            ..
+        } => {
+            renderer.request_gpu_pick(cursor_position.x, cursor_position.y);
+            true
+        }
+        WindowEvent::KeyboardInput { input, .. } if input.state == ElementState::Pressed => {
+            input.virtual_keycode.is_some_and(|vkey| match vkey {
+                VirtualKeyCode::Space => {
+                    let view = next_debug_view(renderer.debug_view());
+                    log::info!("Debug view {:?}", view);
+                    renderer.set_debug_view(view);
+                    true
+                }
+                VirtualKeyCode::L => {
+                    renderer.set_shading(match renderer.shading() {
+                        Shading::Unlit => Shading::Pbr,
+                        Shading::Pbr => Shading::Unlit,
+                    });
+                    true
+                }
+                VirtualKeyCode::M => {
+                    // Cycles through the supported sample counts, back to 1.
+                    let sample_count = renderer
+                        .supported_sample_counts()
+                        .iter()
+                        .copied()
+                        .find(|&count| count > renderer.sample_count())
+                        .unwrap_or(1);
+                    match renderer.set_sample_count(sample_count) {
+                        Ok(()) => log::info!("{}x MSAA", renderer.sample_count()),
+                        Err(err) => log::error!("{}", err),
                     }
-                    _ => false,
-                })
-            }
-            _ => false,
+                    true
+                }
+                VirtualKeyCode::T => {
+                    adjust_tonemapping(renderer, |tonemapping| {
+                        tonemapping.operator = match tonemapping.operator {
+                            Tonemap::Aces => Tonemap::Reinhard,
+                            Tonemap::Reinhard => Tonemap::None,
+                            Tonemap::None => Tonemap::Aces,
+                        }
+                    });
+                    true
+                }
+                // Exposure in half stops.
+                VirtualKeyCode::Equals => {
+                    adjust_exposure(renderer, -0.5);
+                    true
+                }
+                VirtualKeyCode::Minus => {
+                    adjust_exposure(renderer, 0.5);
+                    true
+                }
+                VirtualKeyCode::RBracket => {
+                    adjust_tonemapping(renderer, |tonemapping| tonemapping.gamma += 0.1);
+                    true
+                }
+                VirtualKeyCode::LBracket => {
+                    adjust_tonemapping(renderer, |tonemapping| {
+                        tonemapping.gamma = (tonemapping.gamma - 0.1).max(0.1)
+                    });
+                    true
+                }
+                VirtualKeyCode::Key1 => {
+                    toggle_post_effect(renderer, 0);
+                    true
+                }
+                VirtualKeyCode::Key2 => {
+                    toggle_post_effect(renderer, 1);
+                    true
+                }
+                VirtualKeyCode::Key3 => {
+                    toggle_post_effect(renderer, 2);
+                    true
+                }
+                VirtualKeyCode::Key4 => {
+                    toggle_post_effect(renderer, 3);
+                    true
+                }
+                VirtualKeyCode::Key5 => {
+                    toggle_post_effect(renderer, 4);
+                    true
+                }
+                VirtualKeyCode::G => {
+                    renderer.set_grid(match renderer.grid() {
+                        Some(_) => None,
+                        None => Some(Grid::default()),
+                    });
+                    true
+                }
+                VirtualKeyCode::O => {
+                    renderer.set_orientation_gizmo(!renderer.shows_orientation_gizmo());
+                    true
+                }
+                VirtualKeyCode::V => {
+                    overlay.enabled = !overlay.enabled;
+                    true
+                }
+                VirtualKeyCode::B => {
+                    renderer.set_background(next_background(renderer.background(), skybox));
+                    true
+                }
+                VirtualKeyCode::W => {
+                    renderer.camera_mut().pan((0.0, 0.0, -0.01));
+                    true
+                }
+                VirtualKeyCode::S => {
+                    renderer.camera_mut().pan((0.0, 0.0, 0.01));
+                    true
+                }
+                VirtualKeyCode::A => {
+                    renderer.camera_mut().pan((-0.01, 0.0, 0.0));
+                    true
+                }
+                VirtualKeyCode::D => {
+                    renderer.camera_mut().pan((0.01, 0.0, 0.0));
+                    true
+                }
+                VirtualKeyCode::PageUp => {
+                    renderer.camera_mut().pan((0.0, 0.01, 0.0));
+                    true
+                }
+                VirtualKeyCode::PageDown => {
+                    renderer.camera_mut().pan((0.0, -0.01, 0.0));
+                    true
+                }
+                VirtualKeyCode::Q => {
+                    renderer.camera_mut().rotate_h(-0.04);
+                    true
+                }
+                VirtualKeyCode::E => {
+                    renderer.camera_mut().rotate_h(0.04);
+                    true
+                }
+                VirtualKeyCode::Up => {
+                    renderer.camera_mut().rotate_v(0.04);
+                    true
+                }
+                VirtualKeyCode::Down => {
+                    renderer.camera_mut().rotate_v(-0.04);
+                    true
+                }
+                _ => false,
+            })
         }
+        _ => false,
     }
+}
 
-    fn update(&mut self) {
-        self.queue.write_buffer(
-            &self.camera_buffer,
-            0,
-            bytemuck::cast_slice(&[self.camera.to_uniform()]),
-        );
-    }
-
-    fn render(&mut self) -> std::result::Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.background_color),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-
-            render_pass.set_pipeline(&self.render_pipelines.get());
-            render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
-            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.model.vertex_buffer().slice(..));
-            render_pass.set_index_buffer(
-                self.model.index_buffer().slice(..),
-                wgpu::IndexFormat::Uint16,
-            );
-            render_pass.draw_indexed(0..self.model.num_vertices(), 0, 0..1);
+fn main() -> anyhow::Result<()> {
+    env_logger::builder().filter_level(LevelFilter::Info).init();
+    let mut model_path = PathBuf::from("assets/rectangle.model");
+    let mut heightmap_path: Option<PathBuf> = None;
+    let mut environment_path: Option<PathBuf> = None;
+    let mut skybox_paths: Option<[PathBuf; 6]> = None;
+    let mut sample_count = 1;
+    let mut lut_path: Option<PathBuf> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--model" => match args.next() {
+                Some(path) => model_path = path.into(),
+                None => anyhow::bail!(USAGE),
+            },
+            "--heightmap" => match args.next() {
+                Some(path) => heightmap_path = Some(path.into()),
+                None => anyhow::bail!(USAGE),
+            },
+            "--environment" => match args.next() {
+                Some(path) => environment_path = Some(path.into()),
+                None => anyhow::bail!(USAGE),
+            },
+            "--skybox" => {
+                let paths: Vec<PathBuf> = args.by_ref().take(6).map(PathBuf::from).collect();
+                match paths.try_into() {
+                    Ok(paths) => skybox_paths = Some(paths),
+                    Err(_) => anyhow::bail!(USAGE),
+                }
+            }
+            "--msaa" => match args.next().and_then(|count| count.parse().ok()) {
+                Some(count) => sample_count = count,
+                None => anyhow::bail!(USAGE),
+            },
+            "--lut" => match args.next() {
+                Some(path) => lut_path = Some(path.into()),
+                None => anyhow::bail!(USAGE),
+            },
+            _ => anyhow::bail!("unknown argument {}\n{}", arg, USAGE),
         }
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-        Ok(())
     }
-}
 
-fn main() -> Result<()> {
-    env_logger::builder().filter_level(LevelFilter::Info).init();
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop)?;
-    let mut state = pollster::block_on(State::new(&window))?;
+    let size = window.inner_size();
+    // The window is moved into the event loop along with the renderer and lives as long as it.
+    let mut renderer =
+        pollster::block_on(unsafe { Renderer::new(&window, size.width, size.height) })?;
+    renderer.set_sample_count(sample_count)?;
+    let model_data = import::load(&model_path)?;
+    renderer.set_model(&model_data)?;
+    let mut overlay = Overlay {
+        enabled: false,
+        model_bounds: (model_data.aabb(), model_data.bounding_sphere()),
+        pick: None,
+    };
+    if let Some(path) = heightmap_path {
+        let heightmap = Heightmap::load(&path)?;
+        let longer_side = heightmap.width().max(heightmap.depth()).max(2);
+        let options = TerrainOptions {
+            horizontal_scale: TERRAIN_EXTENT / (longer_side - 1) as f32,
+            ..Default::default()
+        };
+        renderer.set_terrain(&heightmap, &options, Vec::new())?;
+    }
+    let mut skybox = None;
+    if let Some(path) = environment_path {
+        let options = EnvironmentOptions {
+            cube_size: ENVIRONMENT_SIZE,
+            ..Default::default()
+        };
+        let environment = renderer.load_environment(&path, &options)?;
+        skybox = Some(Skybox::from_environment(&environment));
+        renderer.set_environment(Some(environment));
+        // The environment replaces the uniform ambient light.
+        renderer.lights_mut().ambient = [0.0; 3];
+    }
+    if let Some(paths) = skybox_paths {
+        skybox = Some(Skybox::load_faces(
+            renderer.device(),
+            renderer.queue(),
+            &paths,
+        )?);
+    }
+    let skybox = skybox.map(Arc::new);
+    if let Some(skybox) = &skybox {
+        renderer.set_background(Background::Skybox(skybox.clone()));
+    }
+    let lut = match lut_path {
+        Some(path) => Lut::load(renderer.device(), renderer.queue(), &path)?,
+        // A warm tint with a little more contrast.
+        None => Lut::from_fn(renderer.device(), renderer.queue(), LUT_SIZE, |[r, g, b]| {
+            let contrast = |c: f32| c * c * (3.0 - 2.0 * c);
+            let [r, g, b] = [r, g, b].map(|c| c + (contrast(c) - c) * 0.5);
+            [r * 1.08, g * 1.02, b * 0.9]
+        }),
+    };
+    // Toggled with the number keys, all off at first.
+    let effects = [
+        Effect::Bloom(Bloom::default()),
+        Effect::Fxaa(Fxaa::default()),
+        Effect::Vignette(Vignette::default()),
+        Effect::ColorGrading(ColorGrading::new(Arc::new(lut))),
+        Effect::Grayscale(Grayscale::default()),
+    ];
+    *renderer.post_effects_mut() = IntoIterator::into_iter(effects)
+        .map(|effect| PostEffect {
+            effect,
+            enabled: false,
+        })
+        .collect();
+    let mut culling_stats = CullingStats::default();
+    let mut cursor_position = PhysicalPosition::new(0.0, 0.0);
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
-        } if window_id == window.id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        ..
-                    } => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(new_size) => state.resize(*new_size),
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size)
-                    }
-                    _ => {}
+        } if window_id == window.id()
+            && !input(
+                &mut renderer,
+                &mut cursor_position,
+                skybox.as_ref(),
+                &mut overlay,
+                event,
+            ) =>
+        {
+            match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(new_size) => renderer.resize(new_size.width, new_size.height),
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    renderer.resize(new_inner_size.width, new_inner_size.height)
                 }
+                _ => {}
             }
         }
         Event::RedrawRequested(window_id) if window_id == window.id() => {
-            state.update();
-            match state.render() {
+            if let Some(pick) = renderer.gpu_pick() {
+                log::info!("ID buffer at {}, {}: {:?}", pick.x, pick.y, pick.picked);
+                renderer.select(pick.picked);
+            }
+            overlay.draw(renderer.debug_draw_mut());
+            renderer.update();
+            match renderer.render() {
+                Ok(_) if renderer.culling_stats() != culling_stats => {
+                    culling_stats = renderer.culling_stats();
+                    window.set_title(&format!(
+                        "hello-wgpu - {} drawn, {} culled",
+                        culling_stats.drawn, culling_stats.culled
+                    ));
+                }
                 Ok(_) => {}
-                Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
+                Err(wgpu::SurfaceError::Lost) => {
+                    let (width, height) = renderer.size();
+                    renderer.resize(width, height)
+                }
                 Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
                 Err(e) => eprintln!("{:?}", e),
             }
@@ -0,0 +1,376 @@
+//! Materials: the textures and factors describing how the surface of a model is shaded, following
+//! the metallic-roughness model of glTF.
+
+use crate::error::{Error, Result};
+use crate::texture::Texture;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use wgpu::util::DeviceExt;
+
+/// Base color, normal, metallic-roughness and emissive.
+const TEXTURE_COUNT: usize = 4;
+
+/// How the textures of a material are sampled outside of `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressMode {
+    /// Repeats the texels at the edges, so that transparent edges stay transparent.
+    ClampToEdge,
+    Repeat,
+    MirrorRepeat,
+}
+
+impl From<AddressMode> for wgpu::AddressMode {
+    fn from(address_mode: AddressMode) -> Self {
+        match address_mode {
+            AddressMode::ClampToEdge => wgpu::AddressMode::ClampToEdge,
+            AddressMode::Repeat => wgpu::AddressMode::Repeat,
+            AddressMode::MirrorRepeat => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// The CPU side of a material, as stored in `.material` JSON files.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialData {
+    /// Multiplies the base color texture.
+    pub base_color_factor: [f32; 4],
+    /// Multiplies the blue channel of the metallic-roughness texture.
+    pub metallic_factor: f32,
+    /// Multiplies the green channel of the metallic-roughness texture.
+    pub roughness_factor: f32,
+    /// Multiplies the emissive texture.
+    pub emissive_factor: [f32; 3],
+    /// Scales the X and Y components of the normal map.
+    pub normal_scale: f32,
+    /// Applies to all textures.
+    pub address_mode: AddressMode,
+    /// Paths of the textures, relative to the material file in the file and resolved against its
+    /// directory when loaded.
+    pub base_color_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+    pub metallic_roughness_texture: Option<PathBuf>,
+    pub emissive_texture: Option<PathBuf>,
+}
+
+impl Default for MaterialData {
+    /// A white, rough dielectric without textures.
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0; 4],
+            metallic_factor: 0.0,
+            roughness_factor: 1.0,
+            emissive_factor: [0.0; 3],
+            normal_scale: 1.0,
+            address_mode: AddressMode::ClampToEdge,
+            base_color_texture: None,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
+
+impl MaterialData {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        let mut data: MaterialData =
+            serde_json::from_str(&contents).map_err(|err| Error::parse(path, err))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        let textures = [
+            &mut data.base_color_texture,
+            &mut data.normal_texture,
+            &mut data.metallic_roughness_texture,
+            &mut data.emissive_texture,
+        ];
+        for texture in IntoIterator::into_iter(textures).flatten() {
+            *texture = directory.join(&texture);
+        }
+        Ok(data)
+    }
+
+    fn to_uniform(&self) -> MaterialUniform {
+        let [r, g, b] = self.emissive_factor;
+        MaterialUniform {
+            base_color_factor: self.base_color_factor,
+            emissive_factor: [r, g, b, 0.0],
+            params: [
+                self.metallic_factor,
+                self.roughness_factor,
+                self.normal_scale,
+                0.0,
+            ],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct MaterialUniform {
+    base_color_factor: [f32; 4],
+    emissive_factor: [f32; 4],
+    /// Metallic factor, roughness factor and normal scale.
+    params: [f32; 4],
+}
+
+/// The textures of a material. Missing ones are replaced by textures that leave the factors
+/// unchanged, and a normal map that leaves normals unchanged.
+#[derive(Default)]
+pub struct MaterialTextures {
+    /// In sRGB.
+    pub base_color: Option<Texture>,
+    /// In tangent space, linear.
+    pub normal: Option<Texture>,
+    /// Roughness in green and metalness in blue, linear.
+    pub metallic_roughness: Option<Texture>,
+    /// In sRGB.
+    pub emissive: Option<Texture>,
+}
+
+impl MaterialTextures {
+    /// Loads the textures `data` refers to.
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, data: &MaterialData) -> Result<Self> {
+        let load = |path: &Option<PathBuf>, format| {
+            path.as_deref()
+                .map(|path| Texture::load_with_format(device, queue, path, format))
+                .transpose()
+        };
+        Ok(Self {
+            base_color: load(
+                &data.base_color_texture,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+            )?,
+            normal: load(&data.normal_texture, wgpu::TextureFormat::Rgba8Unorm)?,
+            metallic_roughness: load(
+                &data.metallic_roughness_texture,
+                wgpu::TextureFormat::Rgba8Unorm,
+            )?,
+            emissive: load(&data.emissive_texture, wgpu::TextureFormat::Rgba8UnormSrgb)?,
+        })
+    }
+}
+
+/// The GPU side of a material: its textures, a uniform buffer holding its factors and the bind
+/// group binding both.
+pub struct Material {
+    /// Base color, normal, metallic-roughness and emissive.
+    textures: [Texture; TEXTURE_COUNT],
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Material {
+    /// The layout of material bind groups: the textures in the order of [`MaterialTextures`],
+    /// then a sampler and then the factors.
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries: Vec<_> = (0..TEXTURE_COUNT as u32)
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            })
+            .collect();
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: TEXTURE_COUNT as u32,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        });
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: TEXTURE_COUNT as u32 + 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("material_bind_group_layout"),
+        })
+    }
+
+    /// Creates a material with the factors of `data` and `textures`. The texture paths of `data`
+    /// are ignored, see [`MaterialTextures::load`].
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        data: &MaterialData,
+        textures: MaterialTextures,
+    ) -> Result<Self> {
+        let placeholder = |texture: Option<Texture>, pixel, format| match texture {
+            Some(texture) => Ok(texture),
+            None => {
+                let img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                    1,
+                    1,
+                    image::Rgba(pixel),
+                ));
+                Texture::from_image_with_format(
+                    device,
+                    queue,
+                    &img,
+                    Some("material placeholder texture"),
+                    format,
+                )
+            }
+        };
+        let textures = [
+            placeholder(
+                textures.base_color,
+                [255; 4],
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+            )?,
+            placeholder(
+                textures.normal,
+                [128, 128, 255, 255],
+                wgpu::TextureFormat::Rgba8Unorm,
+            )?,
+            placeholder(
+                textures.metallic_roughness,
+                [255; 4],
+                wgpu::TextureFormat::Rgba8Unorm,
+            )?,
+            placeholder(
+                textures.emissive,
+                [255; 4],
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+            )?,
+        ];
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Material Buffer"),
+            contents: bytemuck::cast_slice(&[data.to_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: data.address_mode.into(),
+            address_mode_v: data.address_mode.into(),
+            address_mode_w: data.address_mode.into(),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let mut entries: Vec<_> = textures
+            .iter()
+            .enumerate()
+            .map(|(binding, texture)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: wgpu::BindingResource::TextureView(texture.view()),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: TEXTURE_COUNT as u32,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: TEXTURE_COUNT as u32 + 1,
+            resource: uniform_buffer.as_entire_binding(),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some("material_bind_group"),
+        });
+
+        Ok(Self {
+            textures,
+            uniform_buffer,
+            bind_group,
+        })
+    }
+
+    /// Loads a `.material` file and the textures it refers to.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        path: &Path,
+    ) -> Result<Self> {
+        let data = MaterialData::load(path)?;
+        let textures = MaterialTextures::load(device, queue, &data)?;
+        Self::new(device, queue, layout, &data, textures)
+    }
+
+    /// Replaces the factors with those of `data`, keeping the textures and how they are sampled.
+    pub fn set_factors(&self, queue: &wgpu::Queue, data: &MaterialData) {
+        queue.write_buffer(
+            &self.uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[data.to_uniform()]),
+        );
+    }
+
+    pub fn base_color(&self) -> &Texture {
+        &self.textures[0]
+    }
+
+    pub fn normal(&self) -> &Texture {
+        &self.textures[1]
+    }
+
+    pub fn metallic_roughness(&self) -> &Texture {
+        &self.textures[2]
+    }
+
+    pub fn emissive(&self) -> &Texture {
+        &self.textures[3]
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, contents: &str) -> Result<MaterialData> {
+        let directory = std::env::temp_dir().join("hello-wgpu-materials");
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        std::fs::write(&path, contents).unwrap();
+        MaterialData::load(&path)
+    }
+
+    #[test]
+    fn resolves_textures_against_the_material_directory() {
+        let data = load(
+            "resolved.material",
+            r#"{"base_color_texture": "tree.png", "normal_texture": "maps/normal.png"}"#,
+        )
+        .unwrap();
+        let directory = std::env::temp_dir().join("hello-wgpu-materials");
+        assert_eq!(data.base_color_texture, Some(directory.join("tree.png")));
+        assert_eq!(data.normal_texture, Some(directory.join("maps/normal.png")));
+        assert_eq!(data.metallic_roughness_texture, None);
+        assert_eq!(data.emissive_texture, None);
+    }
+
+    #[test]
+    fn defaults_missing_fields() {
+        let data = load("empty.material", "{}").unwrap();
+        assert_eq!(data.base_color_factor, [1.0; 4]);
+        assert_eq!(data.roughness_factor, 1.0);
+        assert_eq!(data.address_mode, AddressMode::ClampToEdge);
+        let data = load("repeat.material", r#"{"address_mode": "repeat"}"#).unwrap();
+        assert_eq!(data.address_mode, AddressMode::Repeat);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let result = load("typo.material", r#"{"base_colour_factor": [1, 0, 0, 1]}"#);
+        assert!(matches!(result, Err(Error::Parse { .. })));
+    }
+}
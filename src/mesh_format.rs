@@ -0,0 +1,608 @@
+//! Compact binary mesh files, conventionally named `*.mesh`.
+//!
+//! A file is a [`MeshHeader`] followed by the payload: `vertex_count` vertices laid out exactly
+//! like [`Vertex`], then `index_count` 16 or 32-bit indices, zero-padded to a multiple of four
+//! bytes. The payload may be zlib-compressed. All values are little-endian, so on little-endian
+//! hosts uncompressed files can be cast into vertex and index slices without copying, see
+//! [`MeshView`].
+
+use crate::error::{Error, Result};
+use crate::model::{ModelData, Vertex};
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::path::Path;
+
+pub const MAGIC: [u8; 4] = *b"WGMB";
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Identifies the layout of the vertices in the payload.
+#[repr(u16)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexLayout {
+    /// `[f32; 3]` position followed by `[f32; 2]` texture coordinates.
+    PositionUv = 1,
+    /// Like `PositionUv`, followed by a `[f32; 3]` normal.
+    PositionUvNormal = 2,
+}
+
+impl VertexLayout {
+    /// The layout of [`Vertex`].
+    pub const CURRENT: VertexLayout = VertexLayout::PositionUvNormal;
+
+    fn from_raw(raw: u16) -> Option<Self> {
+        match raw {
+            1 => Some(VertexLayout::PositionUv),
+            2 => Some(VertexLayout::PositionUvNormal),
+            _ => None,
+        }
+    }
+
+    pub const fn stride(self) -> usize {
+        match self {
+            VertexLayout::PositionUv => std::mem::size_of::<[f32; 5]>(),
+            VertexLayout::PositionUvNormal => std::mem::size_of::<[f32; 8]>(),
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None = 0,
+    Deflate = 1,
+}
+
+impl Compression {
+    fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Deflate),
+            _ => None,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MeshHeader {
+    magic: [u8; 4],
+    version: u16,
+    vertex_layout: u16,
+    /// Bytes per index, 2 or 4.
+    index_size: u8,
+    compression: u8,
+    reserved: u16,
+    vertex_count: u32,
+    index_count: u32,
+    /// Size of the uncompressed payload.
+    payload_size: u32,
+    /// Size of the payload as stored in the file.
+    stored_size: u32,
+}
+
+const HEADER_SIZE: usize = std::mem::size_of::<MeshHeader>();
+
+/// Largest payload a file may declare, so that corrupt headers can't ask for huge allocations.
+const MAX_PAYLOAD_SIZE: u32 = 1 << 30;
+
+/// Most bytes zlib can inflate one stored byte into.
+const MAX_DEFLATE_RATIO: u64 = 1032;
+
+const _: () = assert!(std::mem::size_of::<Vertex>() == VertexLayout::CURRENT.stride());
+
+fn padded(size: usize) -> usize {
+    (size + 3) & !3
+}
+
+/// The little-endian values of type `T` in `bytes`, `N` bytes each.
+fn read_le<T, const N: usize>(bytes: &[u8], from_le_bytes: fn([u8; N]) -> T) -> Vec<T> {
+    bytes
+        .chunks_exact(N)
+        .map(|chunk| from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+impl MeshHeader {
+    fn read(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(Error::InvalidMesh(format!(
+                "file is {} bytes long, shorter than the {} byte header",
+                bytes.len(),
+                HEADER_SIZE
+            )));
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+        let header = MeshHeader {
+            magic: [bytes[0], bytes[1], bytes[2], bytes[3]],
+            version: u16_at(4),
+            vertex_layout: u16_at(6),
+            index_size: bytes[8],
+            compression: bytes[9],
+            reserved: u16_at(10),
+            vertex_count: u32_at(12),
+            index_count: u32_at(16),
+            payload_size: u32_at(20),
+            stored_size: u32_at(24),
+        };
+        header.validate()?;
+        if bytes.len() - HEADER_SIZE < header.stored_size as usize {
+            return Err(Error::InvalidMesh(format!(
+                "payload is truncated to {} of {} bytes",
+                bytes.len() - HEADER_SIZE,
+                header.stored_size
+            )));
+        }
+        Ok(header)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.magic != MAGIC {
+            return Err(Error::InvalidMesh(format!("bad magic {:?}", self.magic)));
+        }
+        if self.version != FORMAT_VERSION {
+            return Err(Error::InvalidMesh(format!(
+                "unsupported format version {}",
+                self.version
+            )));
+        }
+        let vertex_layout = self.vertex_layout()?;
+        self.index_format()?;
+        if self.payload_size > MAX_PAYLOAD_SIZE {
+            return Err(Error::InvalidMesh(format!(
+                "payload size {} exceeds the maximum of {}",
+                self.payload_size, MAX_PAYLOAD_SIZE
+            )));
+        }
+        match self.compression()? {
+            Compression::None if self.stored_size != self.payload_size => {
+                return Err(Error::InvalidMesh(format!(
+                    "uncompressed payload is stored in {} instead of {} bytes",
+                    self.stored_size, self.payload_size
+                )));
+            }
+            Compression::Deflate
+                if self.payload_size as u64 > self.stored_size as u64 * MAX_DEFLATE_RATIO =>
+            {
+                return Err(Error::InvalidMesh(format!(
+                    "payload of {} bytes can not be compressed into {} bytes",
+                    self.payload_size, self.stored_size
+                )));
+            }
+            _ => {}
+        }
+        let expected_size = self.vertex_count as usize * vertex_layout.stride()
+            + padded(self.index_count as usize * self.index_size as usize);
+        if self.payload_size as usize != expected_size {
+            return Err(Error::InvalidMesh(format!(
+                "payload size {} does not match {} vertices and {} indices",
+                self.payload_size, self.vertex_count, self.index_count
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn vertex_layout(&self) -> Result<VertexLayout> {
+        VertexLayout::from_raw(self.vertex_layout).ok_or_else(|| {
+            Error::InvalidMesh(format!("unknown vertex layout {}", self.vertex_layout))
+        })
+    }
+
+    pub fn index_format(&self) -> Result<wgpu::IndexFormat> {
+        match self.index_size {
+            2 => Ok(wgpu::IndexFormat::Uint16),
+            4 => Ok(wgpu::IndexFormat::Uint32),
+            size => Err(Error::InvalidMesh(format!(
+                "unsupported index size {}",
+                size
+            ))),
+        }
+    }
+
+    pub fn compression(&self) -> Result<Compression> {
+        Compression::from_raw(self.compression)
+            .ok_or_else(|| Error::InvalidMesh(format!("unknown compression {}", self.compression)))
+    }
+
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.extend_from_slice(&self.magic);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        bytes.extend_from_slice(&self.vertex_layout.to_le_bytes());
+        bytes.push(self.index_size);
+        bytes.push(self.compression);
+        bytes.extend_from_slice(&self.reserved.to_le_bytes());
+        for value in [
+            self.vertex_count,
+            self.index_count,
+            self.payload_size,
+            self.stored_size,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+pub enum Indices<'a> {
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+/// A mesh read from uncompressed file contents. Vertices in [`VertexLayout::CURRENT`] are
+/// borrowed from the file, older layouts are converted. Only available on little-endian hosts,
+/// where the values of the file can be used as they are.
+pub struct MeshView<'a> {
+    header: MeshHeader,
+    vertices: Cow<'a, [Vertex]>,
+    indices: Indices<'a>,
+}
+
+impl<'a> MeshView<'a> {
+    /// Interprets `bytes` as an uncompressed mesh file. `bytes` needs to be 4-byte aligned.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self> {
+        if cfg!(target_endian = "big") {
+            return Err(Error::InvalidMesh(
+                "meshes can only be viewed in place on little-endian hosts".to_string(),
+            ));
+        }
+        let header = MeshHeader::read(bytes)?;
+        if header.compression()? != Compression::None {
+            return Err(Error::InvalidMesh(
+                "compressed meshes can not be viewed in place".to_string(),
+            ));
+        }
+        let (vertex_payload, index_payload) = split_payload(
+            &header,
+            &bytes[HEADER_SIZE..][..header.payload_size as usize],
+        )?;
+        let misaligned = |_| Error::InvalidMesh("payload is not 4-byte aligned".to_string());
+        let vertices = match header.vertex_layout()? {
+            VertexLayout::PositionUvNormal => {
+                Cow::Borrowed(bytemuck::try_cast_slice(vertex_payload).map_err(misaligned)?)
+            }
+            VertexLayout::PositionUv => Cow::Owned(
+                bytemuck::try_cast_slice::<_, [f32; 5]>(vertex_payload)
+                    .map_err(misaligned)?
+                    .iter()
+                    .map(|v| Vertex::new([v[0], v[1], v[2]], [v[3], v[4]], [0.0; 3]))
+                    .collect(),
+            ),
+        };
+        let indices = match header.index_format()? {
+            wgpu::IndexFormat::Uint16 => {
+                Indices::U16(bytemuck::try_cast_slice(index_payload).map_err(misaligned)?)
+            }
+            wgpu::IndexFormat::Uint32 => {
+                Indices::U32(bytemuck::try_cast_slice(index_payload).map_err(misaligned)?)
+            }
+        };
+        Ok(Self {
+            header,
+            vertices,
+            indices,
+        })
+    }
+
+    pub fn header(&self) -> &MeshHeader {
+        &self.header
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &Indices<'a> {
+        &self.indices
+    }
+
+    pub fn to_model_data(&self) -> ModelData {
+        let indices = match self.indices {
+            Indices::U16(indices) => indices.iter().map(|&index| index as u32).collect(),
+            Indices::U32(indices) => indices.to_vec(),
+        };
+        ModelData::from_vertices(&self.vertices, indices)
+    }
+}
+
+/// Splits `payload` into its vertices and its indices.
+fn split_payload<'a>(header: &MeshHeader, payload: &'a [u8]) -> Result<(&'a [u8], &'a [u8])> {
+    let vertex_bytes = header.vertex_count as usize * header.vertex_layout()?.stride();
+    let index_bytes = header.index_count as usize * header.index_size as usize;
+    let (vertex_payload, index_payload) = payload.split_at(vertex_bytes);
+    Ok((vertex_payload, &index_payload[..index_bytes]))
+}
+
+/// Decodes a mesh file, decompressing it if necessary.
+pub fn decode(bytes: &[u8]) -> Result<ModelData> {
+    let header = MeshHeader::read(bytes)?;
+    let stored = &bytes[HEADER_SIZE..][..header.stored_size as usize];
+    let decompressed;
+    let payload = match header.compression()? {
+        Compression::None => stored,
+        Compression::Deflate => {
+            // The header checks bound the size, but the stored data may still inflate to more
+            // or less than it claims.
+            let mut payload = Vec::with_capacity(header.payload_size as usize);
+            flate2::read::ZlibDecoder::new(stored)
+                .take(header.payload_size as u64 + 1)
+                .read_to_end(&mut payload)
+                .map_err(|err| {
+                    Error::InvalidMesh(format!("could not decompress payload: {}", err))
+                })?;
+            if payload.len() > header.payload_size as usize {
+                return Err(Error::InvalidMesh(format!(
+                    "payload decompresses to more than {} bytes",
+                    header.payload_size
+                )));
+            }
+            if payload.len() < header.payload_size as usize {
+                return Err(Error::InvalidMesh(format!(
+                    "payload decompresses to {} instead of {} bytes",
+                    payload.len(),
+                    header.payload_size
+                )));
+            }
+            decompressed = payload;
+            &decompressed
+        }
+    };
+    let (vertex_payload, index_payload) = split_payload(&header, payload)?;
+    let floats = read_le(vertex_payload, f32::from_le_bytes);
+    let vertices: Vec<Vertex> = match header.vertex_layout()? {
+        VertexLayout::PositionUvNormal => floats
+            .chunks_exact(8)
+            .map(|v| Vertex::new([v[0], v[1], v[2]], [v[3], v[4]], [v[5], v[6], v[7]]))
+            .collect(),
+        VertexLayout::PositionUv => floats
+            .chunks_exact(5)
+            .map(|v| Vertex::new([v[0], v[1], v[2]], [v[3], v[4]], [0.0; 3]))
+            .collect(),
+    };
+    let indices = match header.index_format()? {
+        wgpu::IndexFormat::Uint16 => read_le(index_payload, u16::from_le_bytes)
+            .into_iter()
+            .map(u32::from)
+            .collect(),
+        wgpu::IndexFormat::Uint32 => read_le(index_payload, u32::from_le_bytes),
+    };
+    Ok(ModelData::from_vertices(&vertices, indices))
+}
+
+pub fn load(path: &Path) -> Result<ModelData> {
+    let bytes = std::fs::read(path).map_err(|err| Error::io(path, err))?;
+    decode(&bytes)?.validated(path)
+}
+
+pub struct EncodeOptions {
+    /// The index size to store, or `None` for the smallest that fits.
+    pub index_format: Option<wgpu::IndexFormat>,
+    pub compression: Compression,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            index_format: None,
+            compression: Compression::None,
+        }
+    }
+}
+
+pub fn encode(model: &ModelData, options: &EncodeOptions) -> Result<Vec<u8>> {
+    let vertices = model.vertices();
+    let indices = model.indices();
+    let index_format = options.index_format.unwrap_or_else(|| model.index_format());
+    if index_format == wgpu::IndexFormat::Uint16 && model.index_format() != index_format {
+        return Err(Error::InvalidMesh(
+            "indices do not fit into 16 bits below the restart value".to_string(),
+        ));
+    }
+    let mut payload: Vec<u8> = bytemuck::cast_slice::<_, f32>(&vertices)
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    for &index in indices {
+        match index_format {
+            wgpu::IndexFormat::Uint16 => payload.extend_from_slice(&(index as u16).to_le_bytes()),
+            wgpu::IndexFormat::Uint32 => payload.extend_from_slice(&index.to_le_bytes()),
+        }
+    }
+    payload.resize(padded(payload.len()), 0);
+
+    let stored = match options.compression {
+        Compression::None => payload.clone(),
+        Compression::Deflate => {
+            let mut encoder =
+                flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder
+                .write_all(&payload)
+                .and_then(|_| encoder.finish())
+                .map_err(|err| Error::InvalidMesh(format!("could not compress payload: {}", err)))?
+        }
+    };
+    let header = MeshHeader {
+        magic: MAGIC,
+        version: FORMAT_VERSION,
+        vertex_layout: VertexLayout::CURRENT as u16,
+        index_size: match index_format {
+            wgpu::IndexFormat::Uint16 => 2,
+            wgpu::IndexFormat::Uint32 => 4,
+        },
+        compression: options.compression as u8,
+        reserved: 0,
+        vertex_count: vertices.len() as u32,
+        index_count: indices.len() as u32,
+        payload_size: payload.len() as u32,
+        stored_size: stored.len() as u32,
+    };
+    let mut bytes = header.to_bytes();
+    bytes.extend_from_slice(&stored);
+    Ok(bytes)
+}
+
+pub fn save(path: &Path, model: &ModelData, options: &EncodeOptions) -> Result<()> {
+    let bytes = encode(model, options)?;
+    std::fs::write(path, bytes).map_err(|err| Error::io(path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives;
+
+    fn assert_same(decoded: &ModelData, model: &ModelData) {
+        assert_eq!(decoded.positions(), model.positions());
+        assert_eq!(decoded.uvs(), model.uvs());
+        assert_eq!(decoded.normals(), model.normals());
+        assert_eq!(decoded.indices(), model.indices());
+    }
+
+    /// A model with an index too large for 16 bits.
+    fn large_model() -> ModelData {
+        let positions: Vec<[f32; 3]> = (0..0x10000)
+            .map(|i| [i as f32, (i % 2) as f32, 0.0])
+            .collect();
+        let uvs = vec![[0.5, 0.5]; positions.len()];
+        ModelData::new(positions, uvs, vec![0, 1, 0xFFFF])
+    }
+
+    #[test]
+    fn round_trips() {
+        let model = primitives::uv_sphere(1.0, 8, 6);
+        for compression in [Compression::None, Compression::Deflate] {
+            let options = EncodeOptions {
+                compression,
+                ..Default::default()
+            };
+            let bytes = encode(&model, &options).unwrap();
+            assert_same(&decode(&bytes).unwrap(), &model);
+        }
+    }
+
+    #[test]
+    fn round_trips_both_index_sizes() {
+        let model = primitives::plane(1.0, 1.0, 2);
+        let bytes = encode(&model, &EncodeOptions::default()).unwrap();
+        assert_eq!(
+            MeshHeader::read(&bytes).unwrap().index_format().unwrap(),
+            wgpu::IndexFormat::Uint16
+        );
+        let options = EncodeOptions {
+            index_format: Some(wgpu::IndexFormat::Uint32),
+            ..Default::default()
+        };
+        assert_same(&decode(&encode(&model, &options).unwrap()).unwrap(), &model);
+
+        let model = large_model();
+        let bytes = encode(&model, &EncodeOptions::default()).unwrap();
+        assert_eq!(
+            MeshHeader::read(&bytes).unwrap().index_format().unwrap(),
+            wgpu::IndexFormat::Uint32
+        );
+        assert_same(&decode(&bytes).unwrap(), &model);
+    }
+
+    #[test]
+    fn rejects_indices_too_large_for_16_bits() {
+        let options = EncodeOptions {
+            index_format: Some(wgpu::IndexFormat::Uint16),
+            ..Default::default()
+        };
+        assert!(matches!(
+            encode(&large_model(), &options),
+            Err(Error::InvalidMesh(_))
+        ));
+    }
+
+    #[test]
+    fn header_is_little_endian() {
+        let model = primitives::plane(1.0, 1.0, 1);
+        let bytes = encode(&model, &EncodeOptions::default()).unwrap();
+        assert_eq!(&bytes[0..4], &MAGIC);
+        assert_eq!(&bytes[4..6], &FORMAT_VERSION.to_le_bytes());
+        let vertex_count = model.positions().len() as u32;
+        assert_eq!(&bytes[12..16], &vertex_count.to_le_bytes());
+        let first = model.positions()[0][0];
+        assert_eq!(&bytes[HEADER_SIZE..HEADER_SIZE + 4], &first.to_le_bytes());
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let model = primitives::plane(1.0, 1.0, 1);
+        let bytes = encode(&model, &EncodeOptions::default()).unwrap();
+        assert!(decode(&bytes[..HEADER_SIZE - 1]).is_err());
+        assert!(decode(&bytes[..bytes.len() - 4]).is_err());
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(decode(&bad_magic).is_err());
+        let mut bad_version = bytes;
+        bad_version[4] = 0xFF;
+        assert!(decode(&bad_version).is_err());
+    }
+
+    /// `bytes` of a mesh with `vertex_count` vertices claimed in its header, and the payload
+    /// size to match.
+    fn with_vertex_count(bytes: &[u8], vertex_count: u32) -> Vec<u8> {
+        let header = MeshHeader::read(bytes).unwrap();
+        let payload_size =
+            header.payload_size as i64 + (vertex_count as i64 - header.vertex_count as i64) * 32;
+        let mut bytes = bytes.to_vec();
+        bytes[12..16].copy_from_slice(&vertex_count.to_le_bytes());
+        bytes[20..24].copy_from_slice(&(payload_size as u32).to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn rejects_payload_sizes_before_allocating() {
+        let model = primitives::plane(1.0, 1.0, 1);
+        let options = EncodeOptions {
+            compression: Compression::Deflate,
+            ..Default::default()
+        };
+        let bytes = encode(&model, &options).unwrap();
+        let vertex_count = model.positions().len() as u32;
+        for vertex_count in [0x0200_0000, 10_000] {
+            assert!(matches!(
+                MeshHeader::read(&with_vertex_count(&bytes, vertex_count)),
+                Err(Error::InvalidMesh(_))
+            ));
+        }
+        for vertex_count in [vertex_count - 1, vertex_count + 1] {
+            let bytes = with_vertex_count(&bytes, vertex_count);
+            assert!(MeshHeader::read(&bytes).is_ok());
+            assert!(matches!(decode(&bytes), Err(Error::InvalidMesh(_))));
+        }
+    }
+
+    #[test]
+    fn views_in_place() {
+        let model = primitives::cube(1.0, 1);
+        let bytes = encode(&model, &EncodeOptions::default()).unwrap();
+        // Copied into words for the alignment the view needs.
+        let mut words = vec![0u32; bytes.len() / 4];
+        bytemuck::cast_slice_mut(&mut words).copy_from_slice(&bytes);
+        let view = MeshView::parse(bytemuck::cast_slice(&words)).unwrap();
+        assert_eq!(
+            view.header().vertex_count() as usize,
+            model.positions().len()
+        );
+        assert_same(&view.to_model_data(), &model);
+    }
+}
@@ -1,9 +1,11 @@
-use anyhow::Context;
+use crate::bounds::{Aabb, BoundingSphere};
+use crate::error::{Error, Result, ValidationError};
+use crate::material::Material;
+use cgmath::{InnerSpace, Vector3};
 use log::*;
 use serde::Deserialize;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -11,9 +13,31 @@ use wgpu::util::DeviceExt;
 pub struct Vertex {
     position: [f32; 3],
     uv: [f32; 2],
+    /// All zero for models without normals.
+    normal: [f32; 3],
 }
 
 impl Vertex {
+    pub fn new(position: [f32; 3], uv: [f32; 2], normal: [f32; 3]) -> Self {
+        Self {
+            position,
+            uv,
+            normal,
+        }
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
+    pub fn uv(&self) -> [f32; 2] {
+        self.uv
+    }
+
+    pub fn normal(&self) -> [f32; 3] {
+        self.normal
+    }
+
     pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -29,66 +53,289 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
             ],
         }
     }
 }
 
-#[derive(Deserialize)]
+/// Whether the triangle `a`, `b`, `c` has (almost) no area for the length of its edges.
+pub(crate) fn is_degenerate([a, b, c]: [Vector3<f32>; 3]) -> bool {
+    let longest_edge_squared = (b - a)
+        .magnitude2()
+        .max((c - b).magnitude2())
+        .max((a - c).magnitude2());
+    (b - a).cross(c - a).magnitude() <= 1e-6 * longest_edge_squared
+}
+
+#[derive(Clone, Deserialize)]
 pub struct ModelData {
     positions: Vec<[f32; 3]>,
     uvs: Vec<[f32; 2]>,
-    indices: Vec<u16>,
+    /// Either empty or one normal per position.
+    #[serde(default)]
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+    /// The `.material` file the model is drawn with, relative to the model file in the file.
+    #[serde(default)]
+    material: Option<PathBuf>,
 }
 
 impl ModelData {
+    pub fn new(positions: Vec<[f32; 3]>, uvs: Vec<[f32; 2]>, indices: Vec<u32>) -> Self {
+        Self {
+            positions,
+            uvs,
+            normals: Vec::new(),
+            indices,
+            material: None,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: Vec<[f32; 3]>) -> Self {
+        self.normals = normals;
+        self
+    }
+
+    pub fn with_material(mut self, material: impl Into<PathBuf>) -> Self {
+        self.material = Some(material.into());
+        self
+    }
+
+    /// Builds model data from interleaved vertices, which are taken to have normals unless all
+    /// of them are zero.
+    pub fn from_vertices(vertices: &[Vertex], indices: Vec<u32>) -> Self {
+        let has_normals = vertices.iter().any(|vertex| vertex.normal != [0.0; 3]);
+        Self {
+            positions: vertices.iter().map(Vertex::position).collect(),
+            uvs: vertices.iter().map(Vertex::uv).collect(),
+            normals: if has_normals {
+                vertices.iter().map(Vertex::normal).collect()
+            } else {
+                Vec::new()
+            },
+            indices,
+            material: None,
+        }
+    }
+
+    pub fn positions(&self) -> &[[f32; 3]] {
+        &self.positions
+    }
+
+    pub fn uvs(&self) -> &[[f32; 2]] {
+        &self.uvs
+    }
+
+    /// The vertex normals, empty if the model has none.
+    pub fn normals(&self) -> &[[f32; 3]] {
+        &self.normals
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(&self.positions)
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere::from_points(&self.positions)
+    }
+
     pub fn vertices(&self) -> Vec<Vertex> {
         self.positions
             .iter()
             .zip(self.uvs.iter())
-            .map(|(&position, &uv)| Vertex { position, uv })
+            .enumerate()
+            .map(|(i, (&position, &uv))| Vertex {
+                position,
+                uv,
+                normal: self.normals.get(i).copied().unwrap_or_default(),
+            })
             .collect()
     }
 
-    pub fn indices(&self) -> &[u16] {
+    pub fn indices(&self) -> &[u32] {
         &self.indices
     }
 
-    pub fn load(path: &Path) -> anyhow::Result<ModelData> {
-        serde_json::from_reader(BufReader::new(
-            File::open(path).with_context(|| format!("ModelData::load({:?})", path))?,
-        ))
-        .map_err(|err| anyhow::Error::from(err))
+    /// The path of the material file the model is drawn with, if any.
+    pub fn material(&self) -> Option<&Path> {
+        self.material.as_deref()
+    }
+
+    /// The smallest index format that can address every vertex referenced by the indices.
+    /// 16-bit indices stop below 0xFFFF, which some backends take as the primitive restart value.
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        if self.indices.iter().all(|&index| index < u16::MAX as u32) {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+
+    /// Checks that all attributes have one entry per vertex and that the indices make up whole
+    /// triangles referring to existing vertices, which is all that processing a model needs.
+    pub fn validate_structure(&self) -> std::result::Result<(), ValidationError> {
+        let vertex_count = self.positions.len();
+        if self.uvs.len() != vertex_count {
+            return Err(ValidationError::AttributeLength {
+                attribute: "uvs",
+                len: self.uvs.len(),
+                expected: vertex_count,
+            });
+        }
+        if !self.normals.is_empty() && self.normals.len() != vertex_count {
+            return Err(ValidationError::AttributeLength {
+                attribute: "normals",
+                len: self.normals.len(),
+                expected: vertex_count,
+            });
+        }
+        if self.indices.len() % 3 != 0 {
+            return Err(ValidationError::IndexCount(self.indices.len()));
+        }
+        if let Some(index) = self
+            .indices
+            .iter()
+            .position(|&value| value as usize >= vertex_count)
+        {
+            return Err(ValidationError::IndexOutOfRange {
+                index,
+                value: self.indices[index],
+                vertex_count,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the structure like [`ModelData::validate_structure`], and also that all values
+    /// are finite and that no triangle is degenerate.
+    pub fn validate(&self) -> std::result::Result<(), ValidationError> {
+        self.validate_structure()?;
+        if let Some(element) = self
+            .positions
+            .iter()
+            .position(|position| !position.iter().all(|v| v.is_finite()))
+        {
+            return Err(ValidationError::NonFinite {
+                attribute: "positions",
+                element,
+            });
+        }
+        if let Some(element) = self
+            .uvs
+            .iter()
+            .position(|uv| !uv.iter().all(|v| v.is_finite()))
+        {
+            return Err(ValidationError::NonFinite {
+                attribute: "uvs",
+                element,
+            });
+        }
+        if let Some(element) = self
+            .normals
+            .iter()
+            .position(|normal| !normal.iter().all(|v| v.is_finite()))
+        {
+            return Err(ValidationError::NonFinite {
+                attribute: "normals",
+                element,
+            });
+        }
+        for (triangle, corners) in self.indices.chunks_exact(3).enumerate() {
+            if is_degenerate([0, 1, 2].map(|i| Vector3::from(self.positions[corners[i] as usize])))
+            {
+                return Err(ValidationError::DegenerateTriangle { triangle });
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates `self`, attributing failures to the file at `path`.
+    pub(crate) fn validated(self, path: &Path) -> Result<Self> {
+        self.validate().map_err(|source| Error::InvalidModel {
+            path: Some(path.into()),
+            source,
+        })?;
+        Ok(self)
+    }
+
+    /// Validates the structure of `self`, attributing failures to the file at `path`.
+    pub(crate) fn validated_structure(self, path: &Path) -> Result<Self> {
+        self.validate_structure()
+            .map_err(|source| Error::InvalidModel {
+                path: Some(path.into()),
+                source,
+            })?;
+        Ok(self)
+    }
+
+    pub fn load(path: &Path) -> Result<ModelData> {
+        let contents = std::fs::read_to_string(path).map_err(|err| Error::io(path, err))?;
+        let mut model = serde_json::from_str::<ModelData>(&contents)
+            .map_err(|err| Error::parse(path, err))?
+            .validated(path)?;
+        if let Some(material) = &mut model.material {
+            *material = path
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(&material);
+        }
+        Ok(model)
     }
 }
 
 pub struct Model {
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
+    index_format: wgpu::IndexFormat,
     num_vertices: u32,
+    vertex_count: u32,
+    aabb: Aabb,
+    bounding_sphere: BoundingSphere,
+    material: Option<Arc<Material>>,
 }
 
 impl Model {
-    pub fn new(device: &wgpu::Device, model_data: &ModelData) -> anyhow::Result<Model> {
+    pub fn new(device: &wgpu::Device, model_data: &ModelData) -> Result<Model> {
+        model_data
+            .validate()
+            .map_err(|source| Error::InvalidModel { path: None, source })?;
         let vertices = model_data.vertices();
-        log!(Level::Info, "vertices = #{:?}", vertices);
+        log!(Level::Debug, "vertices = #{:?}", vertices);
         let indices = model_data.indices();
-        log!(Level::Info, "indices = #{:?}", indices);
+        log!(Level::Debug, "indices = #{:?}", indices);
+        let index_format = model_data.index_format();
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
             contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX,
+            // Also read as storage by the barycentric wireframe.
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE,
         });
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(indices),
-            usage: wgpu::BufferUsages::INDEX,
+            contents: &match index_format {
+                wgpu::IndexFormat::Uint16 => {
+                    let narrow: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+                    bytemuck::cast_slice(&narrow).to_vec()
+                }
+                wgpu::IndexFormat::Uint32 => bytemuck::cast_slice(indices).to_vec(),
+            },
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::STORAGE,
         });
         let num_vertices = indices.len() as u32;
         Ok(Self {
             vertex_buffer,
             index_buffer,
+            index_format,
             num_vertices,
+            vertex_count: vertices.len() as u32,
+            aabb: model_data.aabb(),
+            bounding_sphere: model_data.bounding_sphere(),
+            material: None,
         })
     }
 
@@ -100,7 +347,157 @@ impl Model {
         &self.index_buffer
     }
 
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        self.index_format
+    }
+
     pub fn num_vertices(&self) -> u32 {
         self.num_vertices
     }
+
+    /// The vertices in the vertex buffer, which [`Model::num_vertices`] counts once per index.
+    pub fn vertex_count(&self) -> u32 {
+        self.vertex_count
+    }
+
+    pub fn aabb(&self) -> &Aabb {
+        &self.aabb
+    }
+
+    pub fn bounding_sphere(&self) -> &BoundingSphere {
+        &self.bounding_sphere
+    }
+
+    /// The material the model is drawn with, or `None` to draw it with the renderer's default.
+    pub fn material(&self) -> Option<&Material> {
+        self.material.as_deref()
+    }
+
+    pub fn set_material(&mut self, material: Option<Arc<Material>>) {
+        self.material = material;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> ModelData {
+        ModelData::new(
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+            vec![0, 1, 2],
+        )
+    }
+
+    #[test]
+    fn accepts_valid_model() {
+        assert!(triangle().validate().is_ok());
+        let with_normals = triangle().with_normals(vec![[0.0, 0.0, 1.0]; 3]);
+        assert!(with_normals.validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_attribute_length_mismatch() {
+        let mut model = triangle();
+        model.uvs.pop();
+        assert!(matches!(
+            model.validate(),
+            Err(ValidationError::AttributeLength {
+                attribute: "uvs",
+                len: 2,
+                expected: 3,
+            })
+        ));
+        let model = triangle().with_normals(vec![[0.0, 0.0, 1.0]; 4]);
+        assert!(matches!(
+            model.validate(),
+            Err(ValidationError::AttributeLength {
+                attribute: "normals",
+                len: 4,
+                expected: 3,
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_partial_triangles() {
+        let mut model = triangle();
+        model.indices.push(0);
+        assert!(matches!(
+            model.validate(),
+            Err(ValidationError::IndexCount(4))
+        ));
+    }
+
+    #[test]
+    fn names_out_of_range_index() {
+        let mut model = triangle();
+        model.indices[2] = 3;
+        assert!(matches!(
+            model.validate(),
+            Err(ValidationError::IndexOutOfRange {
+                index: 2,
+                value: 3,
+                vertex_count: 3,
+            })
+        ));
+    }
+
+    #[test]
+    fn names_degenerate_triangle() {
+        let mut model = triangle();
+        model.indices.extend_from_slice(&[0, 1, 1]);
+        assert!(matches!(
+            model.validate(),
+            Err(ValidationError::DegenerateTriangle { triangle: 1 })
+        ));
+        assert!(model.validate_structure().is_ok());
+        let mut sliver = triangle();
+        sliver.positions[2] = [0.5, 1e-8, 0.0];
+        assert!(matches!(
+            sliver.validate(),
+            Err(ValidationError::DegenerateTriangle { triangle: 0 })
+        ));
+    }
+
+    #[test]
+    fn names_non_finite_element() {
+        let mut model = triangle();
+        model.positions[1][2] = f32::NAN;
+        assert!(matches!(
+            model.validate(),
+            Err(ValidationError::NonFinite {
+                attribute: "positions",
+                element: 1,
+            })
+        ));
+        let mut model = triangle();
+        model.uvs[2][0] = f32::INFINITY;
+        assert!(matches!(
+            model.validate(),
+            Err(ValidationError::NonFinite {
+                attribute: "uvs",
+                element: 2,
+            })
+        ));
+    }
+
+    #[test]
+    fn vertices_fill_missing_normals_with_zero() {
+        let vertices = triangle().vertices();
+        assert_eq!(vertices.len(), 3);
+        assert!(vertices.iter().all(|vertex| vertex.normal() == [0.0; 3]));
+        let model = ModelData::from_vertices(&vertices, vec![0, 1, 2]);
+        assert!(model.normals().is_empty());
+    }
+
+    #[test]
+    fn index_format_stays_below_restart_value() {
+        let mut model = triangle();
+        model.indices = vec![0, 1, 0xFFFE];
+        assert_eq!(model.index_format(), wgpu::IndexFormat::Uint16);
+        model.indices = vec![0, 1, 0xFFFF];
+        assert_eq!(model.index_format(), wgpu::IndexFormat::Uint32);
+    }
 }
@@ -0,0 +1,76 @@
+// Vertex Shader
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) uv: vec2<f32>
+};
+
+struct Vertex {
+ @location(0) position: vec3<f32>,
+ @location(1) uv: vec2<f32>
+};
+
+struct CameraUniform {
+  view_proj: mat4x4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct OutlineUniform {
+  color: vec4<f32>,
+  // Center of the selected object, which the outline grows away from.
+  center: vec4<f32>,
+  // width in pixels, viewport width, viewport height
+  params: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> outline: OutlineUniform;
+
+// Pushes each vertex a constant number of pixels away from the object's center on screen. Unlike
+// pushing along normals, this leaves no gaps at hard edges.
+@vertex
+fn vs_main(vertex: Vertex) -> VertexOutput {
+  var out: VertexOutput;
+  out.uv = vertex.uv;
+  let clip = camera.view_proj * vec4<f32>(vertex.position, 1.0);
+  let center = camera.view_proj * vec4<f32>(outline.center.xyz, 1.0);
+  let screen_direction = clip.xy / clip.w - center.xy / center.w;
+  if (dot(screen_direction, screen_direction) < 1e-12) {
+    out.clip_position = clip;
+    return out;
+  }
+  let pixel_size = 2.0 / outline.params.yz;
+  let offset = normalize(screen_direction) * outline.params.x * pixel_size;
+  out.clip_position = vec4<f32>(clip.xy + offset * clip.w, clip.zw);
+  return out;
+}
+
+// Fragment Shader
+
+@group(2) @binding(0)
+var t_base_color: texture_2d<f32>;
+
+@group(2) @binding(4)
+var s_material: sampler;
+
+struct MaterialUniform {
+  base_color_factor: vec4<f32>,
+  emissive_factor: vec4<f32>,
+  // metallic factor, roughness factor, normal scale
+  params: vec4<f32>
+};
+
+@group(2) @binding(5)
+var<uniform> material: MaterialUniform;
+
+// The grown object keeps its texture, so that the outline follows the opaque texels.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let alpha = textureSample(t_base_color, s_material, in.uv).a * material.base_color_factor.a;
+  if (alpha < 0.5) {
+    discard;
+  }
+  return outline.color;
+}
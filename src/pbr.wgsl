@@ -0,0 +1,130 @@
+// Vertex Shader
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+  @location(1) world_position: vec3<f32>,
+  @location(2) normal: vec3<f32>
+};
+
+struct Vertex {
+ @location(0) position: vec3<f32>,
+ @location(1) uv: vec2<f32>,
+ @location(2) normal: vec3<f32>
+};
+
+@vertex
+fn vs_main(vertex: Vertex)
+  -> VertexOutput {
+  var out: VertexOutput;
+
+  out.clip_position = camera.view_proj * vec4<f32>(vertex.position, 1.0);
+  out.uv = vertex.uv;
+  out.world_position = vertex.position;
+  out.normal = vertex.normal;
+  return out;
+}
+
+// Fragment Shader
+
+@group(0) @binding(0)
+var t_base_color: texture_2d<f32>;
+
+@group(0) @binding(1)
+var t_normal: texture_2d<f32>;
+
+@group(0) @binding(2)
+var t_metallic_roughness: texture_2d<f32>;
+
+@group(0) @binding(3)
+var t_emissive: texture_2d<f32>;
+
+@group(0) @binding(4)
+var s_material: sampler;
+
+struct MaterialUniform {
+  base_color_factor: vec4<f32>,
+  emissive_factor: vec4<f32>,
+  // metallic factor, roughness factor, normal scale
+  params: vec4<f32>
+};
+
+@group(0) @binding(5)
+var<uniform> material: MaterialUniform;
+
+struct SelectionUniform {
+  // Color mixed into the material, by its alpha
+  tint: vec4<f32>
+};
+
+@group(2) @binding(0)
+var<uniform> selection: SelectionUniform;
+
+// Tilts `normal` by a tangent space normal map sample, with the tangent frame worked out from
+// screen space derivatives so that models need no tangents.
+fn perturb_normal(
+  normal: vec3<f32>,
+  dp_dx: vec3<f32>,
+  dp_dy: vec3<f32>,
+  duv_dx: vec2<f32>,
+  duv_dy: vec2<f32>,
+  tangent_normal: vec3<f32>
+) -> vec3<f32> {
+  let dp_dy_perp = cross(dp_dy, normal);
+  let dp_dx_perp = cross(normal, dp_dx);
+  let tangent = dp_dy_perp * duv_dx.x + dp_dx_perp * duv_dy.x;
+  let bitangent = dp_dy_perp * duv_dx.y + dp_dx_perp * duv_dy.y;
+  let scale = max(dot(tangent, tangent), dot(bitangent, bitangent));
+  if (scale <= 0.0) {
+    return normal;
+  }
+  let frame = mat3x3<f32>(tangent * inverseSqrt(scale), bitangent * inverseSqrt(scale), normal);
+  return normalize(frame * tangent_normal);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  // Derivatives and samples first, while control flow is uniform.
+  let dp_dx = dpdx(in.world_position);
+  let dp_dy = dpdy(in.world_position);
+  let duv_dx = dpdx(in.uv);
+  let duv_dy = dpdy(in.uv);
+  let base_color = textureSample(t_base_color, s_material, in.uv) * material.base_color_factor;
+  let normal_sample = textureSample(t_normal, s_material, in.uv).xyz;
+  let metallic_roughness = textureSample(t_metallic_roughness, s_material, in.uv);
+  let emissive = textureSample(t_emissive, s_material, in.uv).rgb * material.emissive_factor.rgb;
+  // Transparent texels don't hide what is behind them, nor mark it as selected.
+  if (base_color.a < 0.5) {
+    discard;
+  }
+
+  let to_eye = normalize(camera.position.xyz - in.world_position);
+  // Models without normals are shaded flat. Screen space y points down, so this is the order
+  // that faces the camera; turn it around anyway where the derivatives come out flipped.
+  var normal = cross(dp_dy, dp_dx);
+  if (dot(normal, to_eye) < 0.0) {
+    normal = -normal;
+  }
+  if (dot(in.normal, in.normal) > 0.0) {
+    normal = in.normal;
+  }
+  // Shadow lookups are offset along the normal before normal mapping.
+  let geometric_normal = normalize(normal);
+  let normal_scale = material.params.z;
+  let tangent_normal = (normal_sample * 2.0 - 1.0) * vec3<f32>(normal_scale, normal_scale, 1.0);
+  normal = perturb_normal(geometric_normal, dp_dx, dp_dy, duv_dx, duv_dy, tangent_normal);
+
+  let metallic = clamp(metallic_roughness.b * material.params.x, 0.0, 1.0);
+  let roughness = clamp(metallic_roughness.g * material.params.y, 0.045, 1.0);
+  var surface: Surface;
+  surface.normal = normal;
+  surface.to_eye = to_eye;
+  surface.diffuse_color = base_color.rgb * (1.0 - metallic);
+  surface.f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+  surface.roughness = roughness;
+  surface.alpha = roughness * roughness;
+
+  let luminance = shade(surface, in.world_position, geometric_normal);
+  let color = luminance + emissive;
+  return vec4<f32>(mix(color, selection.tint.rgb, selection.tint.a), base_color.a);
+}
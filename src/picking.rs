@@ -0,0 +1,225 @@
+//! Ray casting against models, for finding what is under the cursor.
+
+use crate::bounds::Aabb;
+use crate::model::ModelData;
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// Determinants below this are taken to mean the ray is parallel to a triangle.
+const EPSILON: f32 = 1e-7;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    /// Of unit length, so that distances along the ray are world distances.
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// The distance at which the ray enters `aabb`, or 0 if it starts inside of it.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let (origin, direction) = (self.origin[axis], self.direction[axis]);
+            if direction == 0.0 {
+                // Parallel to the slab, so either always or never inside of it.
+                if origin < aabb.min[axis] || origin > aabb.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let a = (aabb.min[axis] - origin) / direction;
+            let b = (aabb.max[axis] - origin) / direction;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        (near <= far).then_some(near)
+    }
+
+    /// The distance at which the ray hits the front of the triangle `a`, `b`, `c`, and the
+    /// barycentric weights of `b` and `c` at that point.
+    pub fn intersect_triangle(&self, [a, b, c]: [Point3<f32>; 3]) -> Option<(f32, [f32; 2])> {
+        let ab = b - a;
+        let ac = c - a;
+        let p = self.direction.cross(ac);
+        let determinant = ab.dot(p);
+        // Counter-clockwise triangles face the ray when the determinant is positive.
+        if determinant < EPSILON {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(ab);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = ac.dot(q) * inverse;
+        (distance >= 0.0).then_some((distance, [u, v]))
+    }
+}
+
+/// Where a ray hits a model.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit {
+    pub distance: f32,
+    pub position: Point3<f32>,
+    /// The index of the triangle hit, counting in threes through the model's indices.
+    pub triangle: usize,
+    /// The texture coordinates interpolated at the hit position.
+    pub uv: [f32; 2],
+}
+
+/// The closest front facing triangle of `model` hit by `ray`. `aabb` is the bounding box of the
+/// model, if known, to skip testing the triangles of models the ray misses.
+pub fn raycast(ray: &Ray, model: &ModelData, aabb: Option<&Aabb>) -> Option<Hit> {
+    if aabb.is_some_and(|aabb| ray.intersect_aabb(aabb).is_none()) {
+        return None;
+    }
+    let mut closest: Option<Hit> = None;
+    for (triangle, indices) in model.indices().chunks_exact(3).enumerate() {
+        let [a, b, c] = [0, 1, 2].map(|corner| indices[corner] as usize);
+        let corners = [a, b, c].map(|i| Point3::from(model.positions()[i]));
+        let (distance, [u, v]) = match ray.intersect_triangle(corners) {
+            Some(hit) => hit,
+            None => continue,
+        };
+        if closest.is_some_and(|hit| hit.distance <= distance) {
+            continue;
+        }
+        let uvs = [a, b, c].map(|i| Vector3::new(model.uvs()[i][0], model.uvs()[i][1], 0.0));
+        let uv = uvs[0] * (1.0 - u - v) + uvs[1] * u + uvs[2] * v;
+        closest = Some(Hit {
+            distance,
+            position: ray.at(distance),
+            triangle,
+            uv: [uv.x, uv.y],
+        });
+    }
+    closest
+}
+
+/// The hit closest to the origin of the ray, of those in `hits`.
+pub fn closest<T>(hits: impl IntoIterator<Item = (T, Hit)>) -> Option<(T, Hit)> {
+    hits.into_iter()
+        .min_by(|(_, a), (_, b)| a.distance.total_cmp(&b.distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+
+    /// A counter-clockwise unit quad facing +z at `z`, with texture coordinates following x, y.
+    fn quad(z: f32) -> ModelData {
+        let positions = vec![[0.0, 0.0, z], [1.0, 0.0, z], [1.0, 1.0, z], [0.0, 1.0, z]];
+        let uvs = positions.iter().map(|p| [p[0], p[1]]).collect();
+        ModelData::new(positions, uvs, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    fn toward(origin: [f32; 3], direction: [f32; 3]) -> Ray {
+        Ray::new(Point3::from(origin), Vector3::from(direction))
+    }
+
+    #[test]
+    fn hits_front_of_triangles() {
+        let triangle = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].map(Point3::from);
+        let (distance, [u, v]) = toward([0.25, 0.5, 2.0], [0.0, 0.0, -1.0])
+            .intersect_triangle(triangle)
+            .unwrap();
+        assert!((distance - 2.0).abs() < 1e-6);
+        assert!((u - 0.25).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+
+        let back = toward([0.25, 0.5, -2.0], [0.0, 0.0, 1.0]);
+        assert!(back.intersect_triangle(triangle).is_none());
+        let outside = toward([0.75, 0.75, 2.0], [0.0, 0.0, -1.0]);
+        assert!(outside.intersect_triangle(triangle).is_none());
+        let behind = toward([0.25, 0.5, -2.0], [0.0, 0.0, -1.0]);
+        assert!(behind.intersect_triangle(triangle).is_none());
+    }
+
+    #[test]
+    fn enters_boxes() {
+        let aabb = Aabb {
+            min: Point3::new(-1.0, -1.0, -1.0),
+            max: Point3::new(1.0, 1.0, 1.0),
+        };
+        let distance = toward([-3.0, 0.0, 0.0], [1.0, 0.0, 0.0]).intersect_aabb(&aabb);
+        assert_eq!(distance, Some(2.0));
+        let inside = toward([0.0, 0.0, 0.0], [1.0, 1.0, 0.0]).intersect_aabb(&aabb);
+        assert_eq!(inside, Some(0.0));
+        assert!(toward([-3.0, 2.0, 0.0], [1.0, 0.0, 0.0])
+            .intersect_aabb(&aabb)
+            .is_none());
+        assert!(toward([3.0, 0.0, 0.0], [1.0, 0.0, 0.0])
+            .intersect_aabb(&aabb)
+            .is_none());
+        assert!(toward([-3.0, 0.0, 0.0], [1.0, 2.0, 0.0])
+            .intersect_aabb(&aabb)
+            .is_none());
+    }
+
+    #[test]
+    fn raycast_finds_closest_triangle() {
+        let mut near = quad(1.0);
+        let far = quad(0.0);
+        let offset = near.positions().len() as u32;
+        near = ModelData::new(
+            [far.positions(), near.positions()].concat(),
+            [far.uvs(), near.uvs()].concat(),
+            far.indices()
+                .iter()
+                .copied()
+                .chain(near.indices().iter().map(|index| index + offset))
+                .collect(),
+        );
+        let ray = toward([0.75, 0.25, 3.0], [0.0, 0.0, -1.0]);
+        let aabb = Aabb::from_points(near.positions());
+        let hit = raycast(&ray, &near, Some(&aabb)).unwrap();
+        assert_eq!(hit.triangle, 2);
+        assert!((hit.distance - 2.0).abs() < 1e-6);
+        assert!((hit.uv[0] - 0.75).abs() < 1e-6 && (hit.uv[1] - 0.25).abs() < 1e-6);
+
+        let miss = toward([2.0, 2.0, 3.0], [0.0, 0.0, -1.0]);
+        assert!(raycast(&miss, &near, Some(&aabb)).is_none());
+    }
+
+    #[test]
+    fn picks_closest_hit() {
+        let ray = toward([0.5, 0.5, 3.0], [0.0, 0.0, -1.0]);
+        let hits = vec![
+            ("far", quad(0.0)),
+            ("near", quad(1.0)),
+            ("behind", quad(4.0)),
+        ]
+        .into_iter()
+        .filter_map(|(name, model)| Some((name, raycast(&ray, &model, None)?)));
+        let (name, hit) = closest(hits).unwrap();
+        assert_eq!(name, "near");
+        assert!((hit.position.z - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn cursor_ray_goes_through_center() {
+        let camera = Camera::new(800, 600);
+        let ray = camera.cursor_ray(400.0, 300.0, 800, 600);
+        // The default camera looks at the origin.
+        let to_target = Point3::new(0.0, 0.0, 0.0) - ray.origin;
+        assert!(to_target.normalize().dot(ray.direction) > 0.9999);
+    }
+}
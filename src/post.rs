@@ -0,0 +1,1106 @@
+//! Fullscreen effects applied to the rendered scene on its way into the frame.
+
+use crate::error::{Error, Result};
+use crate::renderer::create_shader_module;
+use crate::tonemap::{TonemapUniform, Tonemapping, HDR_FORMAT};
+use image::error::{ParameterError, ParameterErrorKind};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+/// Most levels of the bloom chain, each half the size of the one before.
+const MAX_BLOOM_LEVELS: usize = 8;
+
+/// The shader of a post-processing pass: `post.wgsl` followed by the file `$name`.
+macro_rules! post_shader {
+    ($name:literal) => {
+        wgpu::ShaderModuleDescriptor {
+            label: Some($name),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("post.wgsl"), include_str!($name)).into(),
+            ),
+        }
+    };
+}
+
+/// Light bleeding from the bright parts of the scene into their surroundings.
+#[derive(Clone, Copy, Debug)]
+pub struct Bloom {
+    /// The brightness, after exposure, above which colors bleed.
+    pub threshold: f32,
+    /// How far below `threshold` bleeding fades in.
+    pub knee: f32,
+    /// The factor of the bled light added onto the scene.
+    pub intensity: f32,
+    /// How many levels of halved size the light is blurred over, which sets how far it bleeds.
+    pub levels: u32,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self {
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.3,
+            levels: 6,
+        }
+    }
+}
+
+/// Fast approximate anti-aliasing, which blurs along edges found in the image.
+#[derive(Clone, Copy, Debug)]
+pub struct Fxaa {
+    /// The longest blur, in pixels.
+    pub span_max: f32,
+    /// How much bright edges shorten the blur.
+    pub reduce_mul: f32,
+    /// The least shortening, which keeps dark edges from blurring too far.
+    pub reduce_min: f32,
+}
+
+impl Default for Fxaa {
+    fn default() -> Self {
+        Self {
+            span_max: 8.0,
+            reduce_mul: 1.0 / 8.0,
+            reduce_min: 1.0 / 128.0,
+        }
+    }
+}
+
+/// Darkening towards the corners of the frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Vignette {
+    /// How dark the corners get, from 0 to 1.
+    pub intensity: f32,
+    /// Where darkening starts, from 0 in the center to 1 in the corners.
+    pub radius: f32,
+    /// How far beyond `radius` the darkening is complete.
+    pub softness: f32,
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self {
+            intensity: 0.5,
+            radius: 0.5,
+            softness: 0.5,
+        }
+    }
+}
+
+/// A 3D table mapping sRGB colors to graded sRGB colors.
+pub struct Lut {
+    view: wgpu::TextureView,
+    size: u32,
+}
+
+impl Lut {
+    /// Creates a table of `size` texels per side from the colors `grade` maps the sRGB colors
+    /// of the texels to.
+    pub fn from_fn(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: u32,
+        grade: impl Fn([f32; 3]) -> [f32; 3],
+    ) -> Self {
+        let size = size.max(2);
+        Self::new(device, queue, size, &graded_texels(size, grade))
+    }
+
+    /// Loads a table stored as an image of `size` squares side by side, each `size` pixels wide.
+    /// Red increases to the right within each square, green downwards and blue from square to
+    /// square, as in the usual 16 and 32 texel tables.
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<Self> {
+        let (size, texels) = read_strip(path)?;
+        Ok(Self::new(device, queue, size, &texels))
+    }
+
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, size: u32, texels: &[u8]) -> Self {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("lut_texture"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            texels,
+        );
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            size,
+        }
+    }
+
+    /// Texels per side.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+/// The RGBA texels of a table of `size` texels per side, red varying fastest and blue slowest,
+/// holding the colors `grade` maps the sRGB colors of the texels to.
+fn graded_texels(size: u32, grade: impl Fn([f32; 3]) -> [f32; 3]) -> Vec<u8> {
+    let scale = 1.0 / (size - 1) as f32;
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                let color = grade([r as f32 * scale, g as f32 * scale, b as f32 * scale]);
+                texels.extend(
+                    color
+                        .iter()
+                        .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+                );
+                texels.push(255);
+            }
+        }
+    }
+    texels
+}
+
+/// The size and the texels, ordered as by [`graded_texels`], of the table stored in the image at
+/// `path`, see [`Lut::load`].
+fn read_strip(path: &Path) -> Result<(u32, Vec<u8>)> {
+    let decode_error = |source| Error::ImageDecode {
+        name: path.display().to_string(),
+        source,
+    };
+    let file = File::open(path).map_err(|err| Error::io(path, err))?;
+    let image_format = image::ImageFormat::from_path(path).map_err(decode_error)?;
+    let image = image::load(BufReader::new(file), image_format)
+        .map_err(decode_error)?
+        .to_rgba8();
+    let size = image.height();
+    if size < 2 || image.width() != size * size {
+        return Err(decode_error(image::ImageError::Parameter(
+            ParameterError::from_kind(ParameterErrorKind::DimensionMismatch),
+        )));
+    }
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                texels.extend_from_slice(&image.get_pixel(b * size + r, g).0);
+            }
+        }
+    }
+    Ok((size, texels))
+}
+
+/// Replacing colors through a [`Lut`].
+#[derive(Clone)]
+pub struct ColorGrading {
+    pub lut: Arc<Lut>,
+    /// How much of the graded color replaces the original one, from 0 to 1.
+    pub intensity: f32,
+}
+
+impl ColorGrading {
+    pub fn new(lut: Arc<Lut>) -> Self {
+        Self {
+            lut,
+            intensity: 1.0,
+        }
+    }
+}
+
+/// Removing the color of the frame.
+#[derive(Clone, Copy, Debug)]
+pub struct Grayscale {
+    /// How much of the color is removed, from 0 to 1.
+    pub amount: f32,
+}
+
+impl Default for Grayscale {
+    fn default() -> Self {
+        Self { amount: 1.0 }
+    }
+}
+
+/// A post-processing effect and its parameters.
+#[derive(Clone)]
+pub enum Effect {
+    Bloom(Bloom),
+    Fxaa(Fxaa),
+    Vignette(Vignette),
+    ColorGrading(ColorGrading),
+    Grayscale(Grayscale),
+}
+
+impl Effect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::Bloom(_) => "bloom",
+            Effect::Fxaa(_) => "FXAA",
+            Effect::Vignette(_) => "vignette",
+            Effect::ColorGrading(_) => "color grading",
+            Effect::Grayscale(_) => "grayscale",
+        }
+    }
+
+    /// Whether the effect can work on the HDR scene, so that tonemapping waits for it when it
+    /// comes first in the chain. Otherwise it works on the colors of the frame.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, Effect::Bloom(_))
+    }
+}
+
+/// An entry of the post-processing chain, see [`Renderer::post_effects_mut`].
+///
+/// [`Renderer::post_effects_mut`]: crate::Renderer::post_effects_mut
+#[derive(Clone)]
+pub struct PostEffect {
+    pub effect: Effect,
+    pub enabled: bool,
+}
+
+impl PostEffect {
+    pub fn new(effect: Effect) -> Self {
+        Self {
+            effect,
+            enabled: true,
+        }
+    }
+}
+
+/// A texture that passes draw into, bound as the input of the next pass.
+struct PostTarget {
+    view: wgpu::TextureView,
+    input: wgpu::BindGroup,
+}
+
+impl PostTarget {
+    fn new(
+        device: &wgpu::Device,
+        label: &str,
+        (width, height): (u32, u32),
+        format: wgpu::TextureFormat,
+        input_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let input = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: input_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("post_input_bind_group"),
+        });
+        Self { view, input }
+    }
+}
+
+/// The textures of the chain, all of the size of the frame except for the bloom levels.
+struct PostTargets {
+    /// The scene, then the HDR effects alternating between the two.
+    hdr: [PostTarget; 2],
+    /// The effects after tonemapping alternate between these, except for the last one, which
+    /// draws into the frame.
+    ldr: [PostTarget; 2],
+    /// Halving in size from half the size of the frame.
+    bloom: Vec<PostTarget>,
+}
+
+/// A uniform buffer of parameters and its bind group, bound at group 1 after the input.
+struct EffectUniform {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl EffectUniform {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, size: usize) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Post Effect Buffer"),
+            size: size as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("post_effect_bind_group"),
+        });
+        Self { buffer, bind_group }
+    }
+
+    fn write<T: bytemuck::Pod>(&self, queue: &wgpu::Queue, uniform: &T) {
+        queue.write_buffer(
+            &self.buffer,
+            0,
+            bytemuck::cast_slice(std::slice::from_ref(uniform)),
+        );
+    }
+}
+
+/// A pipeline drawing the input with the parameters of an [`EffectUniform`].
+struct EffectPass {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl EffectPass {
+    fn new(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        layouts: &[&wgpu::BindGroupLayout],
+    ) -> Self {
+        Self {
+            pipeline: pipeline(device, shader, "fs_main", format, layouts, None),
+        }
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        input: &wgpu::BindGroup,
+        uniform: &EffectUniform,
+        target: &wgpu::TextureView,
+    ) {
+        draw(
+            encoder,
+            &self.pipeline,
+            &[input, &uniform.bind_group],
+            target,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        );
+    }
+}
+
+fn pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    blend: Option<wgpu::BlendState>,
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Post Pipeline Layout"),
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// Draws a triangle covering `target` with `pipeline`, binding `bind_groups` from group 0 up.
+fn draw(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Post Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations { load, store: true },
+        })],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    for (index, bind_group) in bind_groups.iter().enumerate() {
+        render_pass.set_bind_group(index as u32, bind_group, &[]);
+    }
+    render_pass.draw(0..3, 0..1);
+}
+
+struct BloomPass {
+    prefilter: wgpu::RenderPipeline,
+    downsample: wgpu::RenderPipeline,
+    /// Adds onto the larger level.
+    upsample: wgpu::RenderPipeline,
+    /// Into the HDR scene, and into the colors of the frame for bloom after tonemapping.
+    composite_hdr: wgpu::RenderPipeline,
+    composite_frame: wgpu::RenderPipeline,
+}
+
+impl BloomPass {
+    fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        input_layout: &wgpu::BindGroupLayout,
+        uniform_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, post_shader!("bloom.wgsl"))?;
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent::REPLACE,
+        };
+        let composite_layouts = [input_layout, uniform_layout, input_layout];
+        let composite = |format| {
+            pipeline(
+                device,
+                &shader,
+                "fs_composite",
+                format,
+                &composite_layouts,
+                None,
+            )
+        };
+        Ok(Self {
+            prefilter: pipeline(
+                device,
+                &shader,
+                "fs_prefilter",
+                HDR_FORMAT,
+                &[input_layout, uniform_layout],
+                None,
+            ),
+            downsample: pipeline(
+                device,
+                &shader,
+                "fs_downsample",
+                HDR_FORMAT,
+                &[input_layout],
+                None,
+            ),
+            upsample: pipeline(
+                device,
+                &shader,
+                "fs_upsample",
+                HDR_FORMAT,
+                &[input_layout],
+                Some(additive),
+            ),
+            composite_hdr: composite(HDR_FORMAT),
+            composite_frame: composite(format),
+        })
+    }
+
+    /// Draws `input` with bloom over `levels` levels into `output`, which is of [`HDR_FORMAT`]
+    /// if `hdr` and of the format of the frame otherwise.
+    fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        uniform: &EffectUniform,
+        input: &wgpu::BindGroup,
+        output: &wgpu::TextureView,
+        levels: &[PostTarget],
+        hdr: bool,
+    ) {
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+        let uniform = &uniform.bind_group;
+        draw(
+            encoder,
+            &self.prefilter,
+            &[input, uniform],
+            &levels[0].view,
+            clear,
+        );
+        for pair in levels.windows(2) {
+            draw(
+                encoder,
+                &self.downsample,
+                &[&pair[0].input],
+                &pair[1].view,
+                clear,
+            );
+        }
+        for pair in levels.windows(2).rev() {
+            draw(
+                encoder,
+                &self.upsample,
+                &[&pair[1].input],
+                &pair[0].view,
+                wgpu::LoadOp::Load,
+            );
+        }
+        let composite = match hdr {
+            true => &self.composite_hdr,
+            false => &self.composite_frame,
+        };
+        draw(
+            encoder,
+            composite,
+            &[input, uniform, &levels[0].input],
+            output,
+            clear,
+        );
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct GradingUniform {
+    /// Intensity, size of the table.
+    params: [f32; 4],
+    /// Whether the input is linear rather than sRGB.
+    mode: [u32; 4],
+}
+
+/// The parameters and the table of a color grading entry of the chain.
+struct GradingBinding {
+    lut: Arc<Lut>,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+struct ColorGradingPass {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// What [`ColorGradingPass::prepare`] last set, by the index of the entry in the chain.
+    bindings: Vec<Option<GradingBinding>>,
+}
+
+impl ColorGradingPass {
+    fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        input_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, post_shader!("color_grading.wgsl"))?;
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("color_grading_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Ok(Self {
+            pipeline: pipeline(
+                device,
+                &shader,
+                "fs_main",
+                format,
+                &[input_layout, &bind_group_layout],
+                None,
+            ),
+            bind_group_layout,
+            sampler,
+            bindings: Vec::new(),
+        })
+    }
+
+    /// Writes the parameters of `grading`, the entry at `index` of the chain, and binds its table
+    /// if it changed.
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+        grading: &ColorGrading,
+        linear_input: bool,
+    ) {
+        if !self.is_prepared(index, grading) {
+            let binding = self.create_binding(device, grading.lut.clone());
+            if self.bindings.len() <= index {
+                self.bindings.resize_with(index + 1, || None);
+            }
+            self.bindings[index] = Some(binding);
+        }
+        let uniform = GradingUniform {
+            params: [grading.intensity, grading.lut.size() as f32, 0.0, 0.0],
+            mode: [linear_input as u32, 0, 0, 0],
+        };
+        if let Some(Some(binding)) = self.bindings.get(index) {
+            queue.write_buffer(&binding.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        }
+    }
+
+    fn create_binding(&self, device: &wgpu::Device, lut: Arc<Lut>) -> GradingBinding {
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Color Grading Buffer"),
+            size: std::mem::size_of::<GradingUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(lut.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("color_grading_bind_group"),
+        });
+        GradingBinding {
+            lut,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+
+    /// Whether the table of `grading` is bound for the entry at `index`.
+    fn is_prepared(&self, index: usize, grading: &ColorGrading) -> bool {
+        matches!(
+            self.bindings.get(index),
+            Some(Some(binding)) if Arc::ptr_eq(&binding.lut, &grading.lut)
+        )
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        index: usize,
+        input: &wgpu::BindGroup,
+        target: &wgpu::TextureView,
+    ) {
+        if let Some(Some(binding)) = self.bindings.get(index) {
+            draw(
+                encoder,
+                &self.pipeline,
+                &[input, &binding.bind_group],
+                target,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            );
+        }
+    }
+}
+
+/// Takes the HDR scene through the enabled effects into the frame, in order. The scene is
+/// tonemapped before the first effect that works on the colors of the frame, so the effects
+/// before it work on the HDR scene.
+pub(crate) struct PostChain {
+    /// Whether the frame stores colors as they are, rather than encoding them as sRGB.
+    linear_frame: bool,
+    input_layout: wgpu::BindGroupLayout,
+    /// Binds a single uniform buffer of parameters.
+    uniform_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    targets: PostTargets,
+    frame_format: wgpu::TextureFormat,
+    tonemap: EffectPass,
+    tonemap_uniform: EffectUniform,
+    bloom: BloomPass,
+    fxaa: EffectPass,
+    vignette: EffectPass,
+    color_grading: ColorGradingPass,
+    grayscale: EffectPass,
+    /// The parameters of the effects other than color grading, by the index of the entry in the
+    /// chain, so that each entry keeps its own.
+    uniforms: Vec<EffectUniform>,
+}
+
+impl PostChain {
+    /// Creates a chain for frames of `format` and `size`.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+    ) -> Result<Self> {
+        let input_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("post_input_bind_group_layout"),
+        });
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("post_uniform_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let layouts = [&input_layout, &uniform_layout];
+        let effect =
+            |shader: &wgpu::ShaderModule| EffectPass::new(device, shader, format, &layouts);
+        let tonemap = effect(&create_shader_module(device, post_shader!("tonemap.wgsl"))?);
+        let fxaa = effect(&create_shader_module(device, post_shader!("fxaa.wgsl"))?);
+        let vignette = effect(&create_shader_module(
+            device,
+            post_shader!("vignette.wgsl"),
+        )?);
+        let grayscale = effect(&create_shader_module(
+            device,
+            post_shader!("grayscale.wgsl"),
+        )?);
+        let targets = Self::create_targets(device, format, size, &input_layout, &sampler);
+        Ok(Self {
+            linear_frame: !format.describe().srgb,
+            tonemap_uniform: EffectUniform::new(
+                device,
+                &uniform_layout,
+                std::mem::size_of::<TonemapUniform>(),
+            ),
+            bloom: BloomPass::new(device, format, &input_layout, &uniform_layout)?,
+            color_grading: ColorGradingPass::new(device, format, &input_layout)?,
+            input_layout,
+            uniform_layout,
+            sampler,
+            targets,
+            frame_format: format,
+            tonemap,
+            fxaa,
+            vignette,
+            grayscale,
+            uniforms: Vec::new(),
+        })
+    }
+
+    fn create_targets(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        (width, height): (u32, u32),
+        input_layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+    ) -> PostTargets {
+        let target = |label, size, format| {
+            PostTarget::new(device, label, size, format, input_layout, sampler)
+        };
+        let mut bloom = Vec::new();
+        let mut size = (width, height);
+        while bloom.len() < MAX_BLOOM_LEVELS && (bloom.is_empty() || size != (1, 1)) {
+            size = ((size.0 / 2).max(1), (size.1 / 2).max(1));
+            bloom.push(target("Bloom Level", size, HDR_FORMAT));
+        }
+        PostTargets {
+            hdr: [
+                target("HDR Target", (width, height), HDR_FORMAT),
+                target("HDR Target", (width, height), HDR_FORMAT),
+            ],
+            ldr: [
+                target("Post Target", (width, height), format),
+                target("Post Target", (width, height), format),
+            ],
+            bloom,
+        }
+    }
+
+    /// Recreates the targets for frames of `size`.
+    pub fn resize(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        self.targets = Self::create_targets(
+            device,
+            self.frame_format,
+            size,
+            &self.input_layout,
+            &self.sampler,
+        );
+    }
+
+    /// The texture of [`HDR_FORMAT`] the scene is to be rendered into.
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.targets.hdr[0].view
+    }
+
+    /// Writes the parameters of `tonemapping` and `effects` for the next frame, with the scene
+    /// multiplied by `exposure` when it is tonemapped.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        tonemapping: &Tonemapping,
+        exposure: f32,
+        effects: &[PostEffect],
+    ) {
+        self.tonemap_uniform.write(
+            queue,
+            &TonemapUniform::new(tonemapping, exposure, self.linear_frame),
+        );
+        let tonemap_at = effects
+            .iter()
+            .position(|entry| entry.enabled && !entry.effect.is_hdr())
+            .unwrap_or(effects.len());
+        for (index, entry) in effects.iter().enumerate() {
+            if !entry.enabled {
+                continue;
+            }
+            let params = match &entry.effect {
+                Effect::Bloom(bloom) => {
+                    // Before tonemapping, the brightness is compared before exposure.
+                    let scale = match index < tonemap_at {
+                        true => 1.0 / exposure.max(1e-6),
+                        false => 1.0,
+                    };
+                    let knee = bloom.knee.max(1e-4) * scale;
+                    [bloom.threshold * scale, knee, bloom.intensity, 0.0]
+                }
+                Effect::Fxaa(fxaa) => [fxaa.span_max, fxaa.reduce_mul, fxaa.reduce_min, 0.0],
+                Effect::Vignette(vignette) => {
+                    [vignette.intensity, vignette.radius, vignette.softness, 0.0]
+                }
+                Effect::ColorGrading(grading) => {
+                    self.color_grading
+                        .prepare(device, queue, index, grading, !self.linear_frame);
+                    continue;
+                }
+                Effect::Grayscale(grayscale) => [grayscale.amount, 0.0, 0.0, 0.0],
+            };
+            while self.uniforms.len() <= index {
+                self.uniforms.push(EffectUniform::new(
+                    device,
+                    &self.uniform_layout,
+                    std::mem::size_of::<[f32; 4]>(),
+                ));
+            }
+            self.uniforms[index].write(queue, &params);
+        }
+    }
+
+    /// Draws the scene through tonemapping and `effects` into `frame`.
+    pub fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        effects: &[PostEffect],
+        frame: &wgpu::TextureView,
+    ) {
+        let active: Vec<(usize, &Effect)> = effects
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.enabled)
+            .map(|(index, entry)| (index, &entry.effect))
+            // Entries without parameters yet are skipped until the next update.
+            .filter(|&(index, effect)| match effect {
+                Effect::ColorGrading(grading) => self.color_grading.is_prepared(index, grading),
+                _ => index < self.uniforms.len(),
+            })
+            .collect();
+        let tonemap_at = active
+            .iter()
+            .position(|(_, effect)| !effect.is_hdr())
+            .unwrap_or(active.len());
+        let (hdr, ldr) = active.split_at(tonemap_at);
+
+        let targets = &self.targets;
+        let bloom_levels = |bloom: &Bloom| {
+            let levels = (bloom.levels as usize).clamp(1, targets.bloom.len());
+            &targets.bloom[..levels]
+        };
+        let mut input = 0;
+        for &(index, effect) in hdr {
+            let output = 1 - input;
+            if let Effect::Bloom(bloom) = effect {
+                self.bloom.encode(
+                    encoder,
+                    &self.uniforms[index],
+                    &targets.hdr[input].input,
+                    &targets.hdr[output].view,
+                    bloom_levels(bloom),
+                    true,
+                );
+            }
+            input = output;
+        }
+
+        let tonemapped = match ldr.is_empty() {
+            true => frame,
+            false => &targets.ldr[0].view,
+        };
+        self.tonemap.draw(
+            encoder,
+            &targets.hdr[input].input,
+            &self.tonemap_uniform,
+            tonemapped,
+        );
+
+        let mut input = 0;
+        for (i, &(index, effect)) in ldr.iter().enumerate() {
+            let output = 1 - input;
+            let target = match i + 1 == ldr.len() {
+                true => frame,
+                false => &targets.ldr[output].view,
+            };
+            let source = &targets.ldr[input].input;
+            match effect {
+                Effect::Bloom(bloom) => self.bloom.encode(
+                    encoder,
+                    &self.uniforms[index],
+                    source,
+                    target,
+                    bloom_levels(bloom),
+                    false,
+                ),
+                Effect::Fxaa(_) => self
+                    .fxaa
+                    .draw(encoder, source, &self.uniforms[index], target),
+                Effect::Vignette(_) => {
+                    self.vignette
+                        .draw(encoder, source, &self.uniforms[index], target)
+                }
+                Effect::ColorGrading(_) => self.color_grading.draw(encoder, index, source, target),
+                Effect::Grayscale(_) => {
+                    self.grayscale
+                        .draw(encoder, source, &self.uniforms[index], target)
+                }
+            }
+            input = output;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    /// The RGBA texel of `texels` for the coordinates `[r, g, b]` of a table of `size`.
+    fn texel(texels: &[u8], size: u32, [r, g, b]: [u32; 3]) -> [u8; 4] {
+        let index = (((b * size + g) * size + r) * 4) as usize;
+        texels[index..index + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn identity_table_holds_its_coordinates() {
+        let size = 4;
+        let texels = graded_texels(size, |color| color);
+        assert_eq!(texels.len(), (size * size * size * 4) as usize);
+        assert_eq!(texel(&texels, size, [0, 0, 0]), [0, 0, 0, 255]);
+        assert_eq!(texel(&texels, size, [3, 0, 0]), [255, 0, 0, 255]);
+        assert_eq!(texel(&texels, size, [1, 2, 3]), [85, 170, 255, 255]);
+        let inverted = graded_texels(size, |[r, g, b]| [1.0 - r, 1.0 - g, 1.0 - b]);
+        assert_eq!(texel(&inverted, size, [1, 2, 3]), [170, 85, 0, 255]);
+    }
+
+    #[test]
+    fn reads_squares_side_by_side() {
+        let size = 4;
+        let identity = graded_texels(size, |color| color);
+        // Red to the right within a square, green downwards, blue from square to square.
+        let strip = image::RgbaImage::from_fn(size * size, size, |x, y| {
+            image::Rgba(texel(&identity, size, [x % size, y, x / size]))
+        });
+        let path = std::env::temp_dir().join("hello-wgpu-identity-lut.png");
+        strip.save(&path).unwrap();
+        let (read_size, texels) = read_strip(&path).unwrap();
+        assert_eq!(read_size, size);
+        assert_eq!(texels, identity);
+
+        // A single marked pixel lands on the texel of its square and position.
+        let mut marked = strip;
+        marked.put_pixel(2 * size + 1, 3, image::Rgba([1, 2, 3, 4]));
+        marked.save(&path).unwrap();
+        let (_, texels) = read_strip(&path).unwrap();
+        assert_eq!(texel(&texels, size, [1, 3, 2]), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn rejects_strips_of_other_shapes() {
+        let path = std::env::temp_dir().join("hello-wgpu-square-lut.png");
+        image::RgbaImage::new(4, 4).save(&path).unwrap();
+        assert!(matches!(read_strip(&path), Err(Error::ImageDecode { .. })));
+    }
+}
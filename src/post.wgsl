@@ -0,0 +1,44 @@
+// Shared by the post-processing passes: a triangle covering the screen and the image being
+// processed. The shader of each pass follows.
+
+@group(0) @binding(0)
+var t_input: texture_2d<f32>;
+
+@group(0) @binding(1)
+var s_input: sampler;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  // 0, 0 at the top left
+  @location(0) uv: vec2<f32>
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+  let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+  var out: VertexOutput;
+  out.clip_position = vec4<f32>(uv * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+  out.uv = uv;
+  return out;
+}
+
+// The size of a texel of the input in UV.
+fn input_texel() -> vec2<f32> {
+  return 1.0 / vec2<f32>(textureDimensions(t_input));
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+  return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+fn linear_to_srgb(x: vec3<f32>) -> vec3<f32> {
+  let low = x * 12.92;
+  let high = 1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055;
+  return select(high, low, x <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(x: vec3<f32>) -> vec3<f32> {
+  let low = x / 12.92;
+  let high = pow((x + 0.055) / 1.055, vec3<f32>(2.4));
+  return select(high, low, x <= vec3<f32>(0.04045));
+}
@@ -0,0 +1,360 @@
+//! Generators for common shapes, centered on the origin with `y` pointing up.
+//!
+//! Every generator produces positions, UVs, normals and counter-clockwise triangles facing
+//! outwards. Tessellation parameters are clamped to the smallest value that still gives a closed
+//! shape.
+
+use crate::model::{is_degenerate, ModelData};
+use cgmath::{InnerSpace, Vector3};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+#[derive(Default)]
+struct Builder {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+}
+
+impl Builder {
+    fn vertex(&mut self, position: Vector3<f32>, uv: [f32; 2], normal: Vector3<f32>) -> u32 {
+        self.positions.push(position.into());
+        self.uvs.push(uv);
+        self.normals.push(normal.into());
+        self.positions.len() as u32 - 1
+    }
+
+    /// Adds the triangle `a`, `b`, `c`, flipping it to face along its vertex normals and
+    /// dropping it if it has (almost) no area, as happens at poles and apexes.
+    fn triangle(&mut self, a: u32, b: u32, c: u32) {
+        let [pa, pb, pc] = [a, b, c].map(|i| Vector3::from(self.positions[i as usize]));
+        let normal = [a, b, c]
+            .iter()
+            .map(|&i| Vector3::from(self.normals[i as usize]))
+            .sum::<Vector3<f32>>();
+        if is_degenerate([pa, pb, pc]) {
+            return;
+        }
+        let cross = (pb - pa).cross(pc - pa);
+        if cross.dot(normal) >= 0.0 {
+            self.indices.extend_from_slice(&[a, b, c]);
+        } else {
+            self.indices.extend_from_slice(&[a, c, b]);
+        }
+    }
+
+    /// Adds a grid of `columns` by `rows` quads. `point` maps UV coordinates in `[0, 1]` to a
+    /// position and a normal.
+    fn surface(
+        &mut self,
+        columns: u32,
+        rows: u32,
+        point: impl Fn(f32, f32) -> (Vector3<f32>, Vector3<f32>),
+    ) {
+        let base = self.positions.len() as u32;
+        for row in 0..=rows {
+            let v = row as f32 / rows as f32;
+            for column in 0..=columns {
+                let u = column as f32 / columns as f32;
+                let (position, normal) = point(u, v);
+                self.vertex(position, [u, v], normal);
+            }
+        }
+        for row in 0..rows {
+            for column in 0..columns {
+                let top_left = base + row * (columns + 1) + column;
+                let bottom_left = top_left + columns + 1;
+                self.triangle(top_left, bottom_left, bottom_left + 1);
+                self.triangle(top_left, bottom_left + 1, top_left + 1);
+            }
+        }
+    }
+
+    /// Adds a disk at height `y`, facing up if `up` and down otherwise.
+    fn disk(&mut self, radius: f32, y: f32, segments: u32, up: bool) {
+        let normal = if up {
+            Vector3::unit_y()
+        } else {
+            -Vector3::unit_y()
+        };
+        self.surface(segments, 1, |u, v| {
+            let (sin, cos) = (u * TAU).sin_cos();
+            (Vector3::new(cos * radius * v, y, sin * radius * v), normal)
+        });
+    }
+
+    fn build(self) -> ModelData {
+        ModelData::new(self.positions, self.uvs, self.indices).with_normals(self.normals)
+    }
+}
+
+/// A `width` by `depth` plane in the XZ plane facing up, split into `subdivisions` quads along
+/// each side.
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> ModelData {
+    let subdivisions = subdivisions.max(1);
+    let mut builder = Builder::default();
+    builder.surface(subdivisions, subdivisions, |u, v| {
+        (
+            Vector3::new((u - 0.5) * width, 0.0, (v - 0.5) * depth),
+            Vector3::unit_y(),
+        )
+    });
+    builder.build()
+}
+
+/// An axis-aligned cube with edges of length `size`, each face split into `subdivisions` quads
+/// along each side. Every face maps the whole texture.
+pub fn cube(size: f32, subdivisions: u32) -> ModelData {
+    let subdivisions = subdivisions.max(1);
+    let mut builder = Builder::default();
+    let half = size / 2.0;
+    for axis in 0..3 {
+        for &sign in &[1.0f32, -1.0] {
+            let mut normal = Vector3::new(0.0, 0.0, 0.0);
+            normal[axis] = sign;
+            let mut right = Vector3::new(0.0, 0.0, 0.0);
+            right[(axis + 1) % 3] = sign;
+            let down = -normal.cross(right);
+            builder.surface(subdivisions, subdivisions, |u, v| {
+                (
+                    normal * half + right * (u - 0.5) * size + down * (v - 0.5) * size,
+                    normal,
+                )
+            });
+        }
+    }
+    builder.build()
+}
+
+fn sphere_point(u: f32, v: f32) -> Vector3<f32> {
+    let (sin_theta, cos_theta) = (v * PI).sin_cos();
+    let (sin_phi, cos_phi) = (u * TAU).sin_cos();
+    Vector3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi)
+}
+
+/// A sphere made of `segments` slices around the `y` axis and `rings` stacked bands.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> ModelData {
+    let mut builder = Builder::default();
+    builder.surface(segments.max(3), rings.max(2), |u, v| {
+        let normal = sphere_point(u, v);
+        (normal * radius, normal)
+    });
+    builder.build()
+}
+
+/// A sphere made by splitting each triangle of an icosahedron into four `subdivisions` times.
+pub fn icosphere(radius: f32, subdivisions: u32) -> ModelData {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut directions: Vec<Vector3<f32>> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .iter()
+    .map(|&p| Vector3::from(p).normalize())
+    .collect();
+    #[rustfmt::skip]
+    let mut triangles: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+    for _ in 0..subdivisions {
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                directions.push((directions[a] + directions[b]).normalize());
+                directions.len() - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut builder = Builder::default();
+    // Vertices are shared, except that those on the seam get a copy with `u` wrapped past 1.
+    let mut vertices = HashMap::new();
+    for triangle in triangles {
+        let us = triangle.map(|i| directions[i].z.atan2(directions[i].x).rem_euclid(TAU) / TAU);
+        let max_u = us.iter().copied().fold(0.0, f32::max);
+        let [a, b, c] = [0, 1, 2].map(|corner| {
+            let index = triangle[corner];
+            let wrapped = max_u - us[corner] > 0.5;
+            *vertices.entry((index, wrapped)).or_insert_with(|| {
+                let direction = directions[index];
+                let u = if wrapped {
+                    us[corner] + 1.0
+                } else {
+                    us[corner]
+                };
+                let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+                builder.vertex(direction * radius, [u, v], direction)
+            })
+        });
+        builder.triangle(a, b, c);
+    }
+    builder.build()
+}
+
+/// A capped cylinder along the `y` axis, with `segments` slices around the axis and `rings`
+/// bands along it.
+pub fn cylinder(radius: f32, height: f32, segments: u32, rings: u32) -> ModelData {
+    let segments = segments.max(3);
+    let mut builder = Builder::default();
+    builder.surface(segments, rings.max(1), |u, v| {
+        let (sin, cos) = (u * TAU).sin_cos();
+        let normal = Vector3::new(cos, 0.0, sin);
+        (
+            normal * radius + Vector3::unit_y() * (0.5 - v) * height,
+            normal,
+        )
+    });
+    builder.disk(radius, height / 2.0, segments, true);
+    builder.disk(radius, -height / 2.0, segments, false);
+    builder.build()
+}
+
+/// A cone along the `y` axis with its apex at the top and a cap at the bottom, with `segments`
+/// slices around the axis and `rings` bands from apex to base.
+pub fn cone(radius: f32, height: f32, segments: u32, rings: u32) -> ModelData {
+    let segments = segments.max(3);
+    let mut builder = Builder::default();
+    builder.surface(segments, rings.max(1), |u, v| {
+        let (sin, cos) = (u * TAU).sin_cos();
+        let normal = Vector3::new(cos * height, radius, sin * height).normalize();
+        (
+            Vector3::new(cos * radius * v, (0.5 - v) * height, sin * radius * v),
+            normal,
+        )
+    });
+    builder.disk(radius, -height / 2.0, segments, false);
+    builder.build()
+}
+
+/// A torus around the `y` axis. `major_radius` is the distance from the center to the middle of
+/// the tube, `minor_radius` the radius of the tube.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> ModelData {
+    let mut builder = Builder::default();
+    builder.surface(major_segments.max(3), minor_segments.max(3), |u, v| {
+        let (sin_major, cos_major) = (u * TAU).sin_cos();
+        let (sin_minor, cos_minor) = (v * TAU).sin_cos();
+        let outwards = Vector3::new(cos_major, 0.0, sin_major);
+        let normal = outwards * cos_minor + Vector3::unit_y() * sin_minor;
+        (outwards * major_radius + normal * minor_radius, normal)
+    });
+    builder.build()
+}
+
+/// A cylinder along the `y` axis with hemispheres instead of caps. `height` is the length of the
+/// cylindrical part, `rings` the number of bands in each hemisphere.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> ModelData {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let mut builder = Builder::default();
+    for &(offset, v_start) in &[(height / 2.0, 0.0), (-height / 2.0, 0.5)] {
+        builder.surface(segments, rings, |u, v| {
+            let normal = sphere_point(u, v_start + v / 2.0);
+            (normal * radius + Vector3::unit_y() * offset, normal)
+        });
+    }
+    builder.surface(segments, 1, |u, v| {
+        let normal = sphere_point(u, 0.5);
+        (
+            normal * radius + Vector3::unit_y() * (0.5 - v) * height,
+            normal,
+        )
+    });
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `model` is valid and has `triangles` triangles, each wound to face the way its
+    /// vertex normals point.
+    fn check(model: ModelData, triangles: usize) {
+        assert!(model.validate().is_ok());
+        assert_eq!(model.indices().len() / 3, triangles);
+        assert_eq!(model.normals().len(), model.positions().len());
+        for triangle in model.indices().chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vector3::from(model.positions()[triangle[i] as usize]));
+            let face_normal = (b - a).cross(c - a);
+            for &index in triangle {
+                let normal = Vector3::from(model.normals()[index as usize]);
+                assert!(face_normal.dot(normal) > 0.0, "triangle {:?}", triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn planes_face_up() {
+        check(plane(2.0, 1.0, 4), 2 * 4 * 4);
+        for normal in plane(2.0, 1.0, 4).normals() {
+            assert_eq!(*normal, [0.0, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn cubes_face_outwards() {
+        check(cube(1.0, 2), 6 * 2 * 2 * 2);
+    }
+
+    // Triangles with a corner at a pole or apex collapse, one per segment.
+
+    #[test]
+    fn spheres_face_outwards() {
+        check(uv_sphere(1.0, 16, 8), 2 * 16 * 8 - 2 * 16);
+        check(icosphere(1.0, 2), 20 * 4 * 4);
+    }
+
+    #[test]
+    fn cylinders_and_cones_face_outwards() {
+        check(cylinder(0.5, 2.0, 16, 2), 2 * 16 * 2 + 2 * 16);
+        check(cone(0.5, 1.0, 16, 2), 2 * 16 * 2 - 16 + 16);
+    }
+
+    #[test]
+    fn tori_and_capsules_face_outwards() {
+        check(torus(1.0, 0.25, 24, 12), 2 * 24 * 12);
+        check(capsule(0.5, 1.0, 16, 4), 2 * (2 * 16 * 4 - 16) + 2 * 16);
+    }
+
+    #[test]
+    fn clamps_tessellation() {
+        for model in [
+            plane(1.0, 1.0, 0),
+            cube(1.0, 0),
+            uv_sphere(1.0, 0, 0),
+            icosphere(1.0, 0),
+            cylinder(1.0, 1.0, 0, 0),
+            cone(1.0, 1.0, 0, 0),
+            torus(1.0, 0.25, 0, 0),
+            capsule(1.0, 1.0, 0, 0),
+        ] {
+            assert!(model.validate().is_ok());
+            assert!(!model.indices().is_empty());
+        }
+    }
+}
@@ -0,0 +1,904 @@
+//! Clean-up and optimization passes over [`ModelData`].
+//!
+//! Every pass returns a new model and leaves its input untouched. Passes that change the number
+//! of vertices keep all of their attributes, including normals when the input has them, and the
+//! material of the input. Inputs that fail [`ModelData::validate_structure`] are rejected.
+
+use crate::error::{Error, Result};
+use crate::model::{is_degenerate, ModelData};
+use cgmath::{InnerSpace, Vector3, Zero};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::AddAssign;
+
+/// Vertices kept in the simulated post-transform cache when ordering triangles.
+const CACHE_SIZE: usize = 32;
+/// The cache size assumed when splitting triangles into clusters for overdraw ordering.
+const CLUSTER_CACHE_SIZE: usize = 16;
+/// How much more moving a vertex away from an open boundary costs than moving it off its
+/// triangles' planes, so that simplification keeps the outline of open meshes.
+const BOUNDARY_WEIGHT: f64 = 10.0;
+
+/// Fails on models the passes can not work on. Degenerate triangles and the like are left for
+/// [`weld`] and [`remove_unused_vertices`] to clean up.
+fn check(model: &ModelData) -> Result<()> {
+    model
+        .validate_structure()
+        .map_err(|source| Error::InvalidModel { path: None, source })
+}
+
+/// A model with the given attributes and the material of `model`.
+fn rebuild(
+    model: &ModelData,
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+    indices: Vec<u32>,
+) -> ModelData {
+    let rebuilt = ModelData::new(positions, uvs, indices).with_normals(normals);
+    match model.material() {
+        Some(material) => rebuilt.with_material(material),
+        None => rebuilt,
+    }
+}
+
+fn triangles(model: &ModelData) -> impl Iterator<Item = [usize; 3]> + '_ {
+    model
+        .indices()
+        .chunks_exact(3)
+        .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+}
+
+fn corners(model: &ModelData, triangle: [usize; 3]) -> [Vector3<f32>; 3] {
+    triangle.map(|i| Vector3::from(model.positions()[i]))
+}
+
+/// The normal of a triangle, with a length of twice its area.
+fn area_normal([a, b, c]: [Vector3<f32>; 3]) -> Vector3<f32> {
+    (b - a).cross(c - a)
+}
+
+fn normalize_or_zero<S: cgmath::BaseFloat>(v: Vector3<S>) -> Vector3<S> {
+    if v.magnitude2() > S::zero() {
+        v.normalize()
+    } else {
+        v
+    }
+}
+
+/// Gives each triangle its own three vertices, all with the normal of the triangle.
+pub fn flat_normals(model: &ModelData) -> Result<ModelData> {
+    check(model)?;
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    for triangle in triangles(model) {
+        let normal = normalize_or_zero(area_normal(corners(model, triangle)));
+        for i in triangle {
+            positions.push(model.positions()[i]);
+            uvs.push(model.uvs()[i]);
+            normals.push(normal.into());
+        }
+    }
+    let indices = (0..positions.len() as u32).collect();
+    Ok(rebuild(model, positions, uvs, normals, indices))
+}
+
+/// Replaces the normals of `model` by the average of the normals of the triangles around each
+/// vertex, weighted by the angle of the triangle at that vertex. Vertices at the same position
+/// get the same normal, so seams between UV islands stay invisible.
+pub fn smooth_normals(model: &ModelData) -> Result<ModelData> {
+    check(model)?;
+    let mut sums: HashMap<[u32; 3], Vector3<f32>> = HashMap::new();
+    let key = |i: usize| model.positions()[i].map(f32::to_bits);
+    for triangle in triangles(model) {
+        let points = corners(model, triangle);
+        let normal = normalize_or_zero(area_normal(points));
+        for corner in 0..3 {
+            let to_next = points[(corner + 1) % 3] - points[corner];
+            let to_previous = points[(corner + 2) % 3] - points[corner];
+            if to_next.magnitude2() == 0.0 || to_previous.magnitude2() == 0.0 {
+                continue;
+            }
+            let angle = to_next.angle(to_previous).0;
+            *sums
+                .entry(key(triangle[corner]))
+                .or_insert_with(Vector3::zero) += normal * angle;
+        }
+    }
+    let normals = (0..model.positions().len())
+        .map(|i| {
+            let sum = sums.get(&key(i)).copied().unwrap_or_else(Vector3::zero);
+            normalize_or_zero(sum).into()
+        })
+        .collect();
+    Ok(rebuild(
+        model,
+        model.positions().to_vec(),
+        model.uvs().to_vec(),
+        normals,
+        model.indices().to_vec(),
+    ))
+}
+
+struct TangentGeometry<'a> {
+    model: &'a ModelData,
+    /// One tangent per index.
+    tangents: Vec<[f32; 4]>,
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.model.indices().len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.model.positions()[self.model.indices()[face * 3 + vert] as usize]
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.model.normals()[self.model.indices()[face * 3 + vert] as usize]
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.model.uvs()[self.model.indices()[face * 3 + vert] as usize]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+/// Generates MikkTSpace tangents for a model with normals. The `w` component of each tangent is
+/// the sign of the bitangent, `cross(normal, tangent) * w`.
+///
+/// MikkTSpace may give the corners sharing a vertex different tangents, in which case the vertex
+/// is split. The returned model has one tangent per vertex.
+pub fn generate_tangents(model: &ModelData) -> Result<(ModelData, Vec<[f32; 4]>)> {
+    check(model)?;
+    if model.normals().is_empty() {
+        return Err(Error::InvalidMesh(
+            "tangents can only be generated for models with normals".to_string(),
+        ));
+    }
+    let mut geometry = TangentGeometry {
+        model,
+        tangents: vec![[0.0; 4]; model.indices().len()],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        return Err(Error::InvalidMesh(
+            "failed to generate tangents".to_string(),
+        ));
+    }
+
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut tangents = Vec::new();
+    let mut vertices = HashMap::new();
+    let mut indices = Vec::with_capacity(model.indices().len());
+    for (&index, tangent) in model.indices().iter().zip(geometry.tangents) {
+        let i = index as usize;
+        let new_index = *vertices
+            .entry((index, tangent.map(f32::to_bits)))
+            .or_insert_with(|| {
+                positions.push(model.positions()[i]);
+                uvs.push(model.uvs()[i]);
+                normals.push(model.normals()[i]);
+                tangents.push(tangent);
+                positions.len() as u32 - 1
+            });
+        indices.push(new_index);
+    }
+    Ok((rebuild(model, positions, uvs, normals, indices), tangents))
+}
+
+/// Merges vertices whose positions, UVs and normals each differ by at most `tolerance` per
+/// component, drops the triangles that collapse as a result or were degenerate to begin with and
+/// removes unused vertices.
+pub fn weld(model: &ModelData, tolerance: f32) -> Result<ModelData> {
+    check(model)?;
+    let tolerance = tolerance.max(0.0);
+    let has_normals = !model.normals().is_empty();
+    let close = |a: usize, b: usize| {
+        let near = |x: &[f32], y: &[f32]| x.iter().zip(y).all(|(x, y)| (x - y).abs() <= tolerance);
+        near(&model.positions()[a], &model.positions()[b])
+            && near(&model.uvs()[a], &model.uvs()[b])
+            && (!has_normals || near(&model.normals()[a], &model.normals()[b]))
+    };
+    // Vertices are bucketed by position on a grid of `tolerance` sized cells, so candidates for
+    // merging are always in the same or a neighbouring cell.
+    let cell_size = if tolerance > 0.0 { tolerance } else { 1.0 };
+    let cell = |i: usize| model.positions()[i].map(|x| (x / cell_size).floor() as i64);
+    let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    let mut remap = Vec::with_capacity(model.positions().len());
+    for i in 0..model.positions().len() {
+        let [x, y, z] = cell(i);
+        let mut target = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if let Some(candidates) = grid.get(&[x + dx, y + dy, z + dz]) {
+                        if let Some(&j) = candidates.iter().find(|&&j| close(i, j)) {
+                            target = Some(j);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+        remap.push(target.unwrap_or_else(|| {
+            grid.entry([x, y, z]).or_default().push(i);
+            i
+        }) as u32);
+    }
+
+    let indices = model
+        .indices()
+        .chunks_exact(3)
+        .map(|t| [0, 1, 2].map(|corner| remap[t[corner] as usize]))
+        .filter(|triangle| !is_degenerate(corners(model, triangle.map(|i| i as usize))))
+        .flatten()
+        .collect();
+    Ok(compact(&with_indices(model, indices)))
+}
+
+fn with_indices(model: &ModelData, indices: Vec<u32>) -> ModelData {
+    rebuild(
+        model,
+        model.positions().to_vec(),
+        model.uvs().to_vec(),
+        model.normals().to_vec(),
+        indices,
+    )
+}
+
+/// Drops degenerate triangles and removes the vertices no remaining triangle refers to. The
+/// remaining vertices are ordered by first use, which also makes vertex fetches more local.
+pub fn remove_unused_vertices(model: &ModelData) -> Result<ModelData> {
+    check(model)?;
+    let indices = triangles(model)
+        .filter(|&triangle| !is_degenerate(corners(model, triangle)))
+        .flatten()
+        .map(|index| index as u32)
+        .collect();
+    Ok(compact(&with_indices(model, indices)))
+}
+
+fn compact(model: &ModelData) -> ModelData {
+    let mut remap = vec![u32::MAX; model.positions().len()];
+    let mut order = Vec::new();
+    let indices = model
+        .indices()
+        .iter()
+        .map(|&index| {
+            let new_index = &mut remap[index as usize];
+            if *new_index == u32::MAX {
+                *new_index = order.len() as u32;
+                order.push(index as usize);
+            }
+            *new_index
+        })
+        .collect();
+    let normals = if model.normals().is_empty() {
+        Vec::new()
+    } else {
+        order.iter().map(|&i| model.normals()[i]).collect()
+    };
+    rebuild(
+        model,
+        order.iter().map(|&i| model.positions()[i]).collect(),
+        order.iter().map(|&i| model.uvs()[i]).collect(),
+        normals,
+        indices,
+    )
+}
+
+/// Score of a vertex in Tom Forsyth's "Linear-Speed Vertex Cache Optimisation".
+fn vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        None => 0.0,
+        // The last triangle's vertices get a fixed score, so that the next triangle doesn't
+        // simply reuse its edge and produce long strips.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(1.5),
+    };
+    // Vertices with few remaining triangles are boosted to get rid of them early.
+    cache_score + 2.0 * (remaining_triangles as f32).powf(-0.5)
+}
+
+/// Reorders triangles so that consecutive triangles share vertices, improving the hit rate of
+/// the GPU's post-transform vertex cache.
+pub fn optimize_vertex_cache(model: &ModelData) -> Result<ModelData> {
+    check(model)?;
+    Ok(vertex_cache_order(model))
+}
+
+fn vertex_cache_order(model: &ModelData) -> ModelData {
+    let triangle_count = model.indices().len() / 3;
+    let vertex_count = model.positions().len();
+    let mut vertex_triangles = vec![Vec::new(); vertex_count];
+    for (t, triangle) in triangles(model).enumerate() {
+        for i in triangle {
+            vertex_triangles[i].push(t);
+        }
+    }
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = vertex_triangles
+        .iter()
+        .map(|triangles| vertex_score(None, triangles.len()))
+        .collect();
+    let triangle_vertices: Vec<[usize; 3]> = triangles(model).collect();
+    let mut added = vec![false; triangle_count];
+    let mut cache: Vec<usize> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut indices = Vec::with_capacity(model.indices().len());
+    let mut next_unadded = 0;
+    let mut best = None;
+
+    for _ in 0..triangle_count {
+        // Without a candidate from the cache, continue with the next triangle in input order.
+        let triangle = best.unwrap_or_else(|| {
+            while added[next_unadded] {
+                next_unadded += 1;
+            }
+            next_unadded
+        });
+        added[triangle] = true;
+        let vertices = triangle_vertices[triangle];
+        for &i in &vertices {
+            indices.push(i as u32);
+            vertex_triangles[i].retain(|&t| t != triangle);
+        }
+
+        let mut new_cache = vertices.to_vec();
+        new_cache.extend(cache.iter().filter(|i| !vertices.contains(i)));
+        for &evicted in new_cache.iter().skip(CACHE_SIZE) {
+            cache_positions[evicted] = None;
+            vertex_scores[evicted] = vertex_score(None, vertex_triangles[evicted].len());
+        }
+        new_cache.truncate(CACHE_SIZE);
+        cache = new_cache;
+        for (position, &i) in cache.iter().enumerate() {
+            cache_positions[i] = Some(position);
+            vertex_scores[i] = vertex_score(Some(position), vertex_triangles[i].len());
+        }
+
+        best = None;
+        let mut best_score = f32::NEG_INFINITY;
+        for &i in &cache {
+            for &t in &vertex_triangles[i] {
+                let score: f32 = triangle_vertices[t].iter().map(|&v| vertex_scores[v]).sum();
+                if score > best_score {
+                    best_score = score;
+                    best = Some(t);
+                }
+            }
+        }
+    }
+    with_indices(model, indices)
+}
+
+/// Reorders the triangles of a cache optimized model to reduce overdraw.
+///
+/// The triangles are split into clusters wherever the vertex cache would start over, and the
+/// clusters are sorted so that those facing away from the center of the model, which are likely
+/// to occlude the others, are drawn first.
+pub fn optimize_overdraw(model: &ModelData) -> Result<ModelData> {
+    check(model)?;
+    Ok(overdraw_order(model))
+}
+
+fn overdraw_order(model: &ModelData) -> ModelData {
+    let triangles: Vec<[usize; 3]> = triangles(model).collect();
+    let mut cluster_starts = Vec::new();
+    let mut cache: Vec<usize> = Vec::new();
+    for (t, triangle) in triangles.iter().enumerate() {
+        let misses: Vec<usize> = triangle
+            .iter()
+            .copied()
+            .filter(|i| !cache.contains(i))
+            .collect();
+        if misses.len() == 3 {
+            cluster_starts.push(t);
+        }
+        for i in misses {
+            cache.insert(0, i);
+        }
+        cache.truncate(CLUSTER_CACHE_SIZE);
+    }
+
+    // Area weighted sum of triangle centers, sum of area weighted normals and total area.
+    let weighted_sums = |range: &[[usize; 3]]| {
+        range.iter().fold(
+            (Vector3::zero(), Vector3::zero(), 0.0),
+            |(centroid, normal, area), &triangle| {
+                let points = corners(model, triangle);
+                let area_normal = area_normal(points);
+                let weight = area_normal.magnitude();
+                let center = (points[0] + points[1] + points[2]) / 3.0;
+                (
+                    centroid + center * weight,
+                    normal + area_normal,
+                    area + weight,
+                )
+            },
+        )
+    };
+    let (centroid, _, area) = weighted_sums(&triangles);
+    let mesh_center = if area > 0.0 {
+        centroid / area
+    } else {
+        centroid
+    };
+
+    let mut clusters: Vec<(f32, &[[usize; 3]])> = cluster_starts
+        .iter()
+        .zip(cluster_starts.iter().skip(1).chain(&[triangles.len()]))
+        .map(|(&start, &end)| {
+            let cluster = &triangles[start..end];
+            let (centroid, normal, area) = weighted_sums(cluster);
+            let center = if area > 0.0 {
+                centroid / area
+            } else {
+                centroid
+            };
+            (normalize_or_zero(normal).dot(center - mesh_center), cluster)
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let indices = clusters
+        .iter()
+        .flat_map(|(_, cluster)| cluster.iter())
+        .flat_map(|triangle| triangle.map(|i| i as u32))
+        .collect();
+    with_indices(model, indices)
+}
+
+/// A symmetric 4x4 matrix giving the weighted sum of squared distances of a point to a set of
+/// planes, along with the sum of the weights.
+#[derive(Clone, Copy, Default)]
+struct Quadric {
+    coefficients: [f64; 10],
+    weight: f64,
+}
+
+impl Quadric {
+    fn plane(normal: Vector3<f64>, point: Vector3<f64>, weight: f64) -> Self {
+        let [a, b, c] = [normal.x, normal.y, normal.z];
+        let d = -normal.dot(point);
+        let coefficients = [
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ];
+        Self {
+            coefficients: coefficients.map(|x| x * weight),
+            weight,
+        }
+    }
+
+    fn error(&self, p: Vector3<f64>) -> f64 {
+        let q = &self.coefficients;
+        let (x, y, z) = (p.x, p.y, p.z);
+        q[0] * x * x
+            + q[4] * y * y
+            + q[7] * z * z
+            + q[9]
+            + 2.0 * (q[1] * x * y + q[2] * x * z + q[5] * y * z + q[3] * x + q[6] * y + q[8] * z)
+    }
+
+    /// The root mean square distance of `p` to the planes, weighted like them.
+    fn distance(&self, p: Vector3<f64>) -> f64 {
+        if self.weight > 0.0 {
+            (self.error(p).max(0.0) / self.weight).sqrt()
+        } else {
+            0.0
+        }
+    }
+}
+
+impl AddAssign for Quadric {
+    fn add_assign(&mut self, other: Self) {
+        for (a, b) in self.coefficients.iter_mut().zip(other.coefficients) {
+            *a += b;
+        }
+        self.weight += other.weight;
+    }
+}
+
+/// Moving vertex `from` onto vertex `to`, valid as long as neither has changed since.
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    stamps: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    /// Cheaper collapses are greater, so that they come first out of a [`BinaryHeap`].
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// Reduces `model` to at most `target_triangles` triangles if possible, by repeatedly collapsing
+/// the edge whose removal changes the surface the least, as measured by quadric error metrics.
+///
+/// Each collapse moves one vertex onto a neighbour, so the remaining vertices keep their
+/// attributes. Vertices on UV or normal seams, that is sharing their position with another
+/// vertex, are never moved, and collapses that would flip a triangle are skipped. Simplification
+/// also stops before a vertex would end up further than `max_error` from the surface around it,
+/// measured as a fraction of the radius of the bounding sphere of `model`. The result may
+/// therefore have more triangles than asked for.
+pub fn simplify(model: &ModelData, target_triangles: usize, max_error: f32) -> Result<ModelData> {
+    check(model)?;
+    let vertex_count = model.positions().len();
+    let max_distance = f64::from(max_error.max(0.0) * model.bounding_sphere().radius);
+    let position = |i: usize| Vector3::from(model.positions()[i]);
+
+    // Vertices at the same position share a quadric.
+    let mut groups = HashMap::new();
+    let group: Vec<usize> = model
+        .positions()
+        .iter()
+        .map(|p| {
+            let next = groups.len();
+            *groups.entry(p.map(f32::to_bits)).or_insert(next)
+        })
+        .collect();
+    let mut group_sizes = vec![0; groups.len()];
+    for &g in &group {
+        group_sizes[g] += 1;
+    }
+    let movable: Vec<bool> = group.iter().map(|&g| group_sizes[g] == 1).collect();
+
+    let mut quadrics = vec![Quadric::default(); groups.len()];
+    let mut edge_uses: HashMap<(usize, usize), usize> = HashMap::new();
+    for triangle in triangles(model) {
+        let normal = area_normal(corners(model, triangle)).cast::<f64>().unwrap();
+        let area = normal.magnitude() / 2.0;
+        let plane = Quadric::plane(
+            normalize_or_zero(normal),
+            position(triangle[0]).cast().unwrap(),
+            area,
+        );
+        for corner in 0..3 {
+            quadrics[group[triangle[corner]]] += plane;
+            let (a, b) = (group[triangle[corner]], group[triangle[(corner + 1) % 3]]);
+            *edge_uses.entry((a.min(b), a.max(b))).or_default() += 1;
+        }
+    }
+    for triangle in triangles(model) {
+        let normal = area_normal(corners(model, triangle)).cast::<f64>().unwrap();
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            let (ga, gb) = (group[a], group[b]);
+            if edge_uses[&(ga.min(gb), ga.max(gb))] != 1 {
+                continue;
+            }
+            let edge = (position(b) - position(a)).cast::<f64>().unwrap();
+            let plane = Quadric::plane(
+                normalize_or_zero(edge.cross(normal)),
+                position(a).cast().unwrap(),
+                BOUNDARY_WEIGHT * edge.magnitude2(),
+            );
+            quadrics[ga] += plane;
+            quadrics[gb] += plane;
+        }
+    }
+
+    let mut triangles: Vec<[usize; 3]> = triangles(model).collect();
+    let mut alive = vec![true; triangles.len()];
+    let mut live_triangles = triangles.len();
+    let mut vertex_triangles = vec![Vec::new(); vertex_count];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &i in triangle {
+            vertex_triangles[i].push(t);
+        }
+    }
+    let mut stamps = vec![0u32; vertex_count];
+    let mut heap = BinaryHeap::new();
+    let push = |heap: &mut BinaryHeap<Collapse>,
+                quadrics: &[Quadric],
+                stamps: &[u32],
+                from: usize,
+                to: usize| {
+        if !movable[from] {
+            return;
+        }
+        let mut quadric = quadrics[group[from]];
+        quadric += quadrics[group[to]];
+        heap.push(Collapse {
+            cost: quadric.distance(position(to).cast().unwrap()),
+            from,
+            to,
+            stamps: (stamps[from], stamps[to]),
+        });
+    };
+    for triangle in &triangles {
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            push(&mut heap, &quadrics, &stamps, a, b);
+            push(&mut heap, &quadrics, &stamps, b, a);
+        }
+    }
+
+    while live_triangles > target_triangles {
+        let Collapse {
+            cost,
+            from,
+            to,
+            stamps: (from_stamp, to_stamp),
+        } = match heap.pop() {
+            Some(collapse) => collapse,
+            None => break,
+        };
+        if stamps[from] != from_stamp || stamps[to] != to_stamp || vertex_triangles[from].is_empty()
+        {
+            continue;
+        }
+        // Collapses come out cheapest first, so all remaining ones would exceed the error too.
+        if cost > max_distance {
+            break;
+        }
+        let flips = vertex_triangles[from].iter().any(|&t| {
+            let triangle = triangles[t];
+            if triangle.contains(&to) {
+                return false;
+            }
+            let moved = triangle.map(|i| if i == from { to } else { i });
+            let before = area_normal(triangle.map(position));
+            let after = area_normal(moved.map(position));
+            after.dot(before) <= 0.0
+        });
+        if flips {
+            continue;
+        }
+
+        for t in std::mem::take(&mut vertex_triangles[from]) {
+            if triangles[t].contains(&to) {
+                alive[t] = false;
+                live_triangles -= 1;
+                for i in triangles[t] {
+                    vertex_triangles[i].retain(|&other| other != t);
+                }
+            } else {
+                for i in triangles[t].iter_mut() {
+                    if *i == from {
+                        *i = to;
+                    }
+                }
+                vertex_triangles[to].push(t);
+            }
+        }
+        let moved = quadrics[group[from]];
+        quadrics[group[to]] += moved;
+        stamps[from] += 1;
+        stamps[to] += 1;
+        let mut neighbours: Vec<usize> = vertex_triangles[to]
+            .iter()
+            .flat_map(|&t| triangles[t])
+            .filter(|&i| i != to)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for neighbour in neighbours {
+            push(&mut heap, &quadrics, &stamps, to, neighbour);
+            push(&mut heap, &quadrics, &stamps, neighbour, to);
+        }
+    }
+
+    let indices = triangles
+        .iter()
+        .zip(alive)
+        .filter(|(_, alive)| *alive)
+        .flat_map(|(triangle, _)| triangle.map(|i| i as u32))
+        .collect();
+    Ok(compact(&with_indices(model, indices)))
+}
+
+/// Runs the vertex cache, overdraw and vertex fetch optimizations, in that order.
+pub fn optimize(model: &ModelData) -> Result<ModelData> {
+    check(model)?;
+    Ok(compact(&overdraw_order(&vertex_cache_order(model))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::primitives;
+
+    /// A unit quad in the XY plane with the two vertices on its diagonal duplicated.
+    fn split_quad() -> ModelData {
+        let positions = vec![
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ];
+        let uvs = positions.iter().map(|p| [p[0], p[1]]).collect();
+        ModelData::new(positions, uvs, vec![0, 1, 2, 3, 4, 5])
+    }
+
+    fn triangle_count(model: &ModelData) -> usize {
+        model.indices().len() / 3
+    }
+
+    #[test]
+    fn flat_normals_face_triangles() {
+        let model = flat_normals(&primitives::plane(2.0, 2.0, 2)).unwrap();
+        assert_eq!(model.positions().len(), model.indices().len());
+        for normal in model.normals() {
+            assert!((Vector3::from(*normal) - Vector3::unit_y()).magnitude() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_of_sphere_point_outwards() {
+        let model = smooth_normals(&primitives::icosphere(1.0, 2)).unwrap();
+        for (position, normal) in model.positions().iter().zip(model.normals()) {
+            let outwards = Vector3::from(*position).normalize();
+            assert!(outwards.dot(Vector3::from(*normal)) > 0.99);
+        }
+    }
+
+    #[test]
+    fn generates_tangents_along_u() {
+        let (model, tangents) = generate_tangents(&primitives::plane(1.0, 1.0, 1)).unwrap();
+        assert_eq!(tangents.len(), model.positions().len());
+        for [x, y, z, w] in tangents {
+            assert!((x.abs() - 1.0).abs() < 1e-5 && y.abs() < 1e-5 && z.abs() < 1e-5);
+            assert_eq!(w.abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn weld_merges_duplicates() {
+        let model = weld(&split_quad(), 1e-5).unwrap();
+        assert_eq!(model.positions().len(), 4);
+        assert_eq!(triangle_count(&model), 2);
+        assert!(model.validate().is_ok());
+    }
+
+    #[test]
+    fn removes_unused_vertices() {
+        let quad = split_quad();
+        let mut positions = quad.positions().to_vec();
+        let mut uvs = quad.uvs().to_vec();
+        positions.insert(0, [5.0, 5.0, 5.0]);
+        uvs.insert(0, [0.0, 0.0]);
+        let indices = quad.indices().iter().map(|index| index + 1).collect();
+        let model = remove_unused_vertices(&ModelData::new(positions, uvs, indices)).unwrap();
+        assert_eq!(model.positions(), quad.positions());
+        assert_eq!(model.indices(), quad.indices());
+    }
+
+    #[test]
+    fn cleans_up_degenerate_triangles() {
+        let quad = split_quad();
+        // A sliver along the diagonal, and a triangle using one vertex twice.
+        let mut positions = quad.positions().to_vec();
+        let mut uvs = quad.uvs().to_vec();
+        positions.push([0.5, 0.5, 0.0]);
+        uvs.push([0.5, 0.5]);
+        let mut indices = quad.indices().to_vec();
+        indices.extend_from_slice(&[0, 6, 2, 1, 1, 5]);
+        let messy = ModelData::new(positions, uvs, indices);
+        assert!(messy.validate().is_err());
+
+        // The other passes take it as it is.
+        assert_eq!(smooth_normals(&messy).unwrap().indices(), messy.indices());
+        assert_eq!(triangle_count(&optimize(&messy).unwrap()), 4);
+
+        let cleaned = remove_unused_vertices(&messy).unwrap();
+        assert!(cleaned.validate().is_ok());
+        assert_eq!(triangle_count(&cleaned), 2);
+        assert_eq!(cleaned.positions().len(), 6);
+        let welded = weld(&messy, 1e-5).unwrap();
+        assert!(welded.validate().is_ok());
+        assert_eq!(triangle_count(&welded), 2);
+        assert_eq!(welded.positions().len(), 4);
+    }
+
+    #[test]
+    fn optimize_keeps_triangles() {
+        let sphere = primitives::uv_sphere(1.0, 16, 8);
+        let triangles = |model: &ModelData| {
+            let mut triangles: Vec<Vec<[u32; 3]>> = model
+                .indices()
+                .chunks_exact(3)
+                .map(|triangle| {
+                    // Rotated to start at the smallest position, which keeps the winding.
+                    let corners = [0, 1, 2].map(|i| model.positions()[triangle[i] as usize]);
+                    let mut corners = corners.map(|p| p.map(f32::to_bits)).to_vec();
+                    let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                    corners.rotate_left(first);
+                    corners
+                })
+                .collect();
+            triangles.sort();
+            triangles
+        };
+        let optimized = optimize(&sphere).unwrap();
+        assert_eq!(triangles(&optimized), triangles(&sphere));
+    }
+
+    #[test]
+    fn simplify_flattens_planes() {
+        let plane = primitives::plane(4.0, 4.0, 8).with_material("plane.material");
+        let simplified = simplify(&plane, 8, 0.01).unwrap();
+        assert!(triangle_count(&simplified) <= triangle_count(&plane) / 4);
+        assert!(simplified.validate().is_ok());
+        assert!(simplified.positions().iter().all(|p| p[1] == 0.0));
+        assert_eq!(simplified.aabb(), plane.aabb());
+        assert_eq!(simplified.material(), plane.material());
+        let without_uvs =
+            ModelData::new(plane.positions().to_vec(), vec![], plane.indices().to_vec());
+        assert!(simplify(&without_uvs, 8, 0.01).is_err());
+    }
+
+    #[test]
+    fn simplify_stops_at_max_error() {
+        let sphere = primitives::uv_sphere(1.0, 16, 8);
+        let simplified = simplify(&sphere, 1, 0.0).unwrap();
+        assert_eq!(triangle_count(&simplified), triangle_count(&sphere));
+        let simplified = simplify(&sphere, 1, 0.2).unwrap();
+        assert!(triangle_count(&simplified) < triangle_count(&sphere));
+        for position in simplified.positions() {
+            assert!((Vector3::from(*position).magnitude() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn passes_keep_material() {
+        let model = primitives::plane(1.0, 1.0, 4).with_material("plane.material");
+        let material = Some(std::path::Path::new("plane.material"));
+        assert_eq!(flat_normals(&model).unwrap().material(), material);
+        assert_eq!(smooth_normals(&model).unwrap().material(), material);
+        assert_eq!(weld(&model, 1e-5).unwrap().material(), material);
+        assert_eq!(optimize(&model).unwrap().material(), material);
+    }
+
+    #[test]
+    fn rejects_invalid_models() {
+        let quad = split_quad();
+        let model = ModelData::new(quad.positions().to_vec(), vec![], quad.indices().to_vec());
+        assert!(matches!(
+            smooth_normals(&model),
+            Err(Error::InvalidModel { path: None, .. })
+        ));
+        assert!(optimize(&model).is_err());
+    }
+}
@@ -0,0 +1,1639 @@
+use crate::camera::Camera;
+use crate::debug_draw::{DebugDraw, DebugDrawPass};
+use crate::debug_view::{DebugPass, DebugView};
+use crate::environment::{self, Environment, EnvironmentOptions};
+use crate::error::{Error, Result};
+use crate::grid::{GizmoPass, Grid, GridPass};
+use crate::id_buffer::{self, IdBuffer, IdDraw};
+use crate::light::Lights;
+use crate::lod::{LodChain, LodOptions};
+use crate::material::{Material, MaterialData, MaterialTextures};
+use crate::model::{Model, ModelData, Vertex};
+use crate::picking::{self, Hit};
+use crate::post::{PostChain, PostEffect};
+use crate::shadow::{ShadowMaps, ShadowOptions};
+use crate::skybox::{Background, SkyPass};
+use crate::terrain::{Heightmap, Terrain, TerrainLayer, TerrainOptions};
+use crate::texture::Texture;
+use crate::tonemap::{Tonemap, Tonemapping, HDR_FORMAT};
+use raw_window_handle::HasRawWindowHandle;
+use std::path::Path;
+use std::sync::Arc;
+use wgpu::include_wgsl;
+use wgpu::util::DeviceExt;
+
+/// Color mixed into selected models, by its alpha.
+const SELECTION_TINT: [f32; 4] = [1.0, 0.6, 0.1, 0.3];
+const OUTLINE_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+/// Width of the selection outline in pixels.
+const OUTLINE_WIDTH: f32 = 3.0;
+
+/// Replaces the stencil value with the stencil reference wherever something is drawn, which
+/// marks the pixels of selected objects.
+const STENCIL_MARK: wgpu::StencilFaceState = wgpu::StencilFaceState {
+    compare: wgpu::CompareFunction::Always,
+    fail_op: wgpu::StencilOperation::Keep,
+    depth_fail_op: wgpu::StencilOperation::Keep,
+    pass_op: wgpu::StencilOperation::Replace,
+};
+
+/// Passes only outside of the pixels marked with the stencil reference.
+const STENCIL_OUTSIDE: wgpu::StencilFaceState = wgpu::StencilFaceState {
+    compare: wgpu::CompareFunction::NotEqual,
+    fail_op: wgpu::StencilOperation::Keep,
+    depth_fail_op: wgpu::StencilOperation::Keep,
+    pass_op: wgpu::StencilOperation::Keep,
+};
+
+fn stencil_state(face: wgpu::StencilFaceState) -> wgpu::StencilState {
+    wgpu::StencilState {
+        front: face,
+        back: face,
+        read_mask: !0,
+        write_mask: !0,
+    }
+}
+
+pub(crate) fn depth_stencil_state(
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+    stencil: wgpu::StencilState,
+) -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: Texture::DEPTH_FORMAT,
+        depth_write_enabled,
+        depth_compare,
+        stencil,
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+/// The shader of a lit pipeline: `lit.wgsl` followed by the file `$name`.
+macro_rules! lit_shader {
+    ($name:literal) => {
+        wgpu::ShaderModuleDescriptor {
+            label: Some($name),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(include_str!("lit.wgsl"), include_str!($name)).into(),
+            ),
+        }
+    };
+}
+
+/// The layout of the bind group of the lit shaders: the lights, the irradiance and prefiltered
+/// cubemaps of the environment, the BRDF lookup table, its sampler, the shadow maps, their
+/// comparison sampler and how to sample them.
+fn lighting_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let uniform = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    let texture = |binding, view_dimension, sample_type| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension,
+            sample_type,
+        },
+        count: None,
+    };
+    let sampler = |binding, ty| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(ty),
+        count: None,
+    };
+    let filterable = wgpu::TextureSampleType::Float { filterable: true };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            uniform(0),
+            texture(1, wgpu::TextureViewDimension::Cube, filterable),
+            texture(2, wgpu::TextureViewDimension::Cube, filterable),
+            texture(3, wgpu::TextureViewDimension::D2, filterable),
+            sampler(4, wgpu::SamplerBindingType::Filtering),
+            texture(
+                5,
+                wgpu::TextureViewDimension::D2Array,
+                wgpu::TextureSampleType::Depth,
+            ),
+            sampler(6, wgpu::SamplerBindingType::Comparison),
+            uniform(7),
+        ],
+        label: Some("lighting_bind_group_layout"),
+    })
+}
+
+fn lighting_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    lights: &wgpu::Buffer,
+    environment: &Environment,
+    brdf_lut: &wgpu::TextureView,
+    shadow_maps: &ShadowMaps,
+) -> wgpu::BindGroup {
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(environment.irradiance()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(environment.prefiltered()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(brdf_lut),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::Sampler(&sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: wgpu::BindingResource::TextureView(shadow_maps.view()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::Sampler(shadow_maps.sampler()),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: shadow_maps.uniform_buffer().as_entire_binding(),
+            },
+        ],
+        label: Some("lighting_bind_group"),
+    })
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct OutlineUniform {
+    color: [f32; 4],
+    center: [f32; 4],
+    /// Width in pixels, viewport width and height.
+    params: [f32; 4],
+}
+
+/// The bind group layouts of the main render pass.
+struct SceneLayouts {
+    material: wgpu::BindGroupLayout,
+    camera: wgpu::BindGroupLayout,
+    /// A single uniform buffer, such as the selection tint or the outline.
+    uniform: wgpu::BindGroupLayout,
+    lighting: wgpu::BindGroupLayout,
+    terrain: wgpu::BindGroupLayout,
+}
+
+impl SceneLayouts {
+    fn new(device: &wgpu::Device) -> Self {
+        let uniform_layout = |label| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some(label),
+            })
+        };
+        Self {
+            material: Material::bind_group_layout(device),
+            camera: uniform_layout("camera_bind_group_layout"),
+            uniform: uniform_layout("uniform_bind_group_layout"),
+            lighting: lighting_bind_group_layout(device),
+            terrain: Terrain::bind_group_layout(device),
+        }
+    }
+}
+
+/// The pipelines of the main render pass, which depend on its sample count.
+struct ScenePipelines {
+    unlit: wgpu::RenderPipeline,
+    pbr: wgpu::RenderPipeline,
+    debug: DebugPass,
+    terrain: wgpu::RenderPipeline,
+    outline: wgpu::RenderPipeline,
+    sky_pass: SkyPass,
+    grid_pass: GridPass,
+}
+
+impl ScenePipelines {
+    fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        layouts: &SceneLayouts,
+    ) -> Result<Self> {
+        let marking = depth_stencil_state(
+            true,
+            wgpu::CompareFunction::Less,
+            stencil_state(STENCIL_MARK),
+        );
+        let shader = create_shader_module(device, include_wgsl!("shader.wgsl"))?;
+        let unlit = Renderer::make_pipeline(
+            device,
+            &shader,
+            format,
+            &[&layouts.material, &layouts.camera, &layouts.uniform],
+            marking.clone(),
+            sample_count,
+        );
+
+        let pbr_shader = create_shader_module(device, lit_shader!("pbr.wgsl"))?;
+        let pbr = Renderer::make_pipeline(
+            device,
+            &pbr_shader,
+            format,
+            &[
+                &layouts.material,
+                &layouts.camera,
+                &layouts.uniform,
+                &layouts.lighting,
+            ],
+            marking.clone(),
+            sample_count,
+        );
+
+        let debug = DebugPass::new(
+            device,
+            format,
+            sample_count,
+            marking.clone(),
+            &layouts.camera,
+            &layouts.uniform,
+        )?;
+
+        // Group 2 is only there to put the lighting where `lit.wgsl` has it.
+        let terrain_shader = create_shader_module(device, lit_shader!("terrain.wgsl"))?;
+        let terrain = Renderer::make_pipeline(
+            device,
+            &terrain_shader,
+            format,
+            &[
+                &layouts.terrain,
+                &layouts.camera,
+                &layouts.uniform,
+                &layouts.lighting,
+            ],
+            marking,
+            sample_count,
+        );
+
+        let outline_shader = create_shader_module(device, include_wgsl!("outline.wgsl"))?;
+        let outline = Renderer::make_pipeline(
+            device,
+            &outline_shader,
+            format,
+            &[&layouts.camera, &layouts.uniform, &layouts.material],
+            depth_stencil_state(
+                false,
+                wgpu::CompareFunction::Always,
+                stencil_state(STENCIL_OUTSIDE),
+            ),
+            sample_count,
+        );
+
+        let sky_pass = SkyPass::new(device, format, sample_count, &layouts.uniform)?;
+        let grid_pass = GridPass::new(device, format, sample_count, &layouts.uniform)?;
+
+        Ok(Self {
+            unlit,
+            pbr,
+            debug,
+            terrain,
+            outline,
+            sky_pass,
+            grid_pass,
+        })
+    }
+}
+
+/// Where the renderer puts its frames.
+enum RenderTarget {
+    Surface {
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+    },
+    Offscreen {
+        texture: wgpu::Texture,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl RenderTarget {
+    const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    fn offscreen(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::OFFSCREEN_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        RenderTarget::Offscreen {
+            texture,
+            format: Self::OFFSCREEN_FORMAT,
+            width,
+            height,
+        }
+    }
+
+    fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            RenderTarget::Surface { config, .. } => config.format,
+            RenderTarget::Offscreen { format, .. } => *format,
+        }
+    }
+
+    fn size(&self) -> (u32, u32) {
+        match self {
+            RenderTarget::Surface { config, .. } => (config.width, config.height),
+            RenderTarget::Offscreen { width, height, .. } => (*width, *height),
+        }
+    }
+}
+
+/// Creates a color attachment of the main pass.
+fn create_color_target(
+    device: &wgpu::Device,
+    label: &str,
+    (width, height): (u32, u32),
+    sample_count: u32,
+    usage: wgpu::TextureUsages,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        usage,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+/// Creates a shader module, reporting validation failures as an error instead of a panic.
+pub(crate) fn create_shader_module(
+    device: &wgpu::Device,
+    descriptor: wgpu::ShaderModuleDescriptor,
+) -> Result<wgpu::ShaderModule> {
+    let label = descriptor.label.unwrap_or("unnamed").to_string();
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(descriptor);
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(wgpu::Error::Validation { description, .. }) => {
+            Err(Error::ShaderValidation { label, description })
+        }
+        Some(err) => Err(Error::ShaderValidation {
+            label,
+            description: err.to_string(),
+        }),
+        None => Ok(shader),
+    }
+}
+
+/// Checks `config` against what `surface` and `device` support, since `Surface::configure`
+/// panics on invalid configurations.
+fn check_surface_configuration(
+    surface: &wgpu::Surface,
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> Result<()> {
+    let max_dimension = device.limits().max_texture_dimension_2d;
+    if config.width == 0 || config.height == 0 {
+        Err(Error::SurfaceConfiguration(format!(
+            "size {}x{} is empty",
+            config.width, config.height
+        )))
+    } else if config.width > max_dimension || config.height > max_dimension {
+        Err(Error::SurfaceConfiguration(format!(
+            "size {}x{} exceeds the maximum of {}",
+            config.width, config.height, max_dimension
+        )))
+    } else if !surface
+        .get_supported_formats(adapter)
+        .contains(&config.format)
+    {
+        Err(Error::SurfaceConfiguration(format!(
+            "format {:?} is not supported",
+            config.format
+        )))
+    } else if !surface
+        .get_supported_modes(adapter)
+        .contains(&config.present_mode)
+    {
+        Err(Error::SurfaceConfiguration(format!(
+            "present mode {:?} is not supported",
+            config.present_mode
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+async fn request_device(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface,
+            force_fallback_adapter: false,
+        })
+        .await
+        .ok_or(Error::AdapterRequest)?;
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                // Draws the wireframe debug view, which falls back to another method without it.
+                features: adapter.features() & wgpu::Features::POLYGON_MODE_LINE,
+                limits: wgpu::Limits::default(),
+                label: Some("Device"),
+            },
+            None,
+        )
+        .await
+        .map_err(Error::DeviceRequest)?;
+    Ok((adapter, device, queue))
+}
+
+/// The sample counts the main pass can use: 4 if the adapter can multisample and resolve
+/// [`HDR_FORMAT`] and multisample depth, otherwise only 1. wgpu validates render passes against
+/// the counts WebGPU guarantees, so 2 is never supported.
+fn supported_sample_counts(adapter: &wgpu::Adapter) -> Vec<u32> {
+    let supports = |format, flags| {
+        adapter
+            .get_texture_format_features(format)
+            .flags
+            .contains(flags)
+    };
+    let flags = wgpu::TextureFormatFeatureFlags::MULTISAMPLE;
+    let resolve = flags | wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE;
+    if supports(HDR_FORMAT, resolve) && supports(Texture::DEPTH_FORMAT, flags) {
+        vec![1, 4]
+    } else {
+        vec![1]
+    }
+}
+
+/// How many models the last frame drew and how many it skipped for being outside the view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub drawn: usize,
+    pub culled: usize,
+}
+
+/// How the model is lit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shading {
+    /// The material's colors as they are.
+    Unlit,
+    /// Physically based, following the metallic-roughness model of glTF and lit by
+    /// [`Renderer::lights`].
+    Pbr,
+}
+
+/// Something drawn by the renderer that can be picked with the cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pickable {
+    Model,
+    /// The terrain chunk with this index.
+    TerrainChunk(usize),
+}
+
+impl Pickable {
+    /// The ID the object is drawn with into the ID buffer.
+    fn id(self) -> u32 {
+        match self {
+            Pickable::Model => id_buffer::NO_OBJECT + 1,
+            Pickable::TerrainChunk(i) => id_buffer::NO_OBJECT + 2 + i as u32,
+        }
+    }
+
+    fn from_id(id: u32) -> Option<Self> {
+        match id.checked_sub(id_buffer::NO_OBJECT + 1)? {
+            0 => Some(Pickable::Model),
+            chunk => Some(Pickable::TerrainChunk(chunk as usize - 1)),
+        }
+    }
+}
+
+/// The result of picking through the ID buffer: the pixel read and the object drawn there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuPick {
+    pub x: u32,
+    pub y: u32,
+    pub picked: Option<Pickable>,
+}
+
+/// Draws a model with its material through a movable camera, either into a window surface or
+/// into an offscreen texture.
+pub struct Renderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    target: RenderTarget,
+    /// Samples per pixel of the main pass, 1 without MSAA.
+    sample_count: u32,
+    supported_sample_counts: Vec<u32>,
+    /// The color target of the main pass when it is multisampled, resolved into the scene target
+    /// of `post`.
+    multisampled_target: Option<wgpu::TextureView>,
+    depth_texture: Texture,
+    background_color: wgpu::Color,
+    background: Background,
+    tonemapping: Tonemapping,
+    /// Takes the scene through `post_effects` and tonemapping into the frame.
+    post: PostChain,
+    post_effects: Vec<PostEffect>,
+    /// Lines queued for the next frame.
+    debug_draw: DebugDraw,
+    debug_draw_pass: DebugDrawPass,
+    grid: Option<Grid>,
+    show_orientation_gizmo: bool,
+    gizmo_pass: GizmoPass,
+    layouts: SceneLayouts,
+    pipelines: ScenePipelines,
+    shading: Shading,
+    debug_view: Option<DebugView>,
+    lights: Lights,
+    lights_buffer: wgpu::Buffer,
+    /// Binds the lights, the environment, `brdf_lut` and the shadow maps.
+    lighting_bind_group: wgpu::BindGroup,
+    environment: Option<Environment>,
+    /// Lights the scene when there is no environment.
+    default_environment: Environment,
+    brdf_lut: wgpu::TextureView,
+    shadow_maps: ShadowMaps,
+    /// Draws models without a material of their own.
+    default_material: Material,
+    terrain: Option<Terrain>,
+    model: Option<LodChain>,
+    /// The CPU side of the full detail level of `model`, kept for picking.
+    model_data: Option<ModelData>,
+    camera: Camera,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    culling_stats: CullingStats,
+    id_buffer: IdBuffer,
+    selection: Option<Pickable>,
+    selection_buffer: wgpu::Buffer,
+    selection_bind_group: wgpu::BindGroup,
+    outline_buffer: wgpu::Buffer,
+    outline_bind_group: wgpu::BindGroup,
+}
+
+impl Renderer {
+    pub(crate) fn make_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        depth_stencil: wgpu::DepthStencilState,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        // Integer formats can't be blended.
+        let blend = match format.describe().sample_type {
+            wgpu::TextureSampleType::Uint | wgpu::TextureSampleType::Sint => None,
+            _ => Some(wgpu::BlendState::ALPHA_BLENDING),
+        };
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(depth_stencil),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Like [`Renderer::make_pipeline`], but only writing depth, into a texture of the format of
+    /// `depth_stencil`. `fs_main` has no outputs and only decides which fragments are discarded.
+    pub(crate) fn make_depth_pipeline(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        depth_stencil: wgpu::DepthStencilState,
+    ) -> wgpu::RenderPipeline {
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Depth Pipeline Layout"),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(depth_stencil),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Creates a renderer presenting to `window`, which is initially `width` by `height` pixels.
+    ///
+    /// # Safety
+    ///
+    /// `window` must stay valid for as long as the renderer exists, since the surface it
+    /// presents to is created from the raw handle of the window.
+    pub async unsafe fn new<W: HasRawWindowHandle>(
+        window: &W,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let surface = instance.create_surface(window);
+        let (adapter, device, queue) = request_device(&instance, Some(&surface)).await?;
+        let preferred_format = *surface
+            .get_supported_formats(&adapter)
+            .first()
+            .ok_or_else(|| Error::SurfaceConfiguration("no supported formats".to_string()))?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: preferred_format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        check_surface_configuration(&surface, &adapter, &device, &config)?;
+        surface.configure(&device, &config);
+        let sample_counts = supported_sample_counts(&adapter);
+        Self::with_target(
+            device,
+            queue,
+            RenderTarget::Surface { surface, config },
+            sample_counts,
+        )
+    }
+
+    /// Creates a renderer drawing into a `width` by `height` texture, see
+    /// [`Renderer::offscreen_texture`].
+    pub async fn new_offscreen(width: u32, height: u32) -> Result<Self> {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let (adapter, device, queue) = request_device(&instance, None).await?;
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max_dimension || height > max_dimension {
+            return Err(Error::SurfaceConfiguration(format!(
+                "offscreen size {}x{} is outside of 1..={}",
+                width, height, max_dimension
+            )));
+        }
+        let target = RenderTarget::offscreen(&device, width, height);
+        let sample_counts = supported_sample_counts(&adapter);
+        Self::with_target(device, queue, target, sample_counts)
+    }
+
+    fn with_target(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        target: RenderTarget,
+        supported_sample_counts: Vec<u32>,
+    ) -> Result<Self> {
+        let layouts = SceneLayouts::new(&device);
+        let default_material = Material::new(
+            &device,
+            &queue,
+            &layouts.material,
+            &MaterialData::default(),
+            MaterialTextures::default(),
+        )?;
+
+        let background_color = wgpu::Color {
+            r: 1.0,
+            g: 1.0,
+            b: 1.0,
+            a: 1.0,
+        };
+
+        let (width, height) = target.size();
+        let camera = Camera::new(width, height);
+
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera.to_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layouts.camera,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        let selection_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Selection Buffer"),
+            contents: bytemuck::cast_slice(&[[0.0f32; 4]]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let selection_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layouts.uniform,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: selection_buffer.as_entire_binding(),
+            }],
+            label: Some("selection_bind_group"),
+        });
+
+        let lights = Lights::default();
+        let lights_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lights Buffer"),
+            contents: bytemuck::cast_slice(&[lights.to_uniform()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let brdf_lut = environment::brdf_lut(&device, &queue)?;
+        let default_environment = Environment::black(&device, &queue)?;
+        let shadow_maps = ShadowMaps::new(
+            &device,
+            &layouts.uniform,
+            &layouts.material,
+            ShadowOptions::default(),
+        )?;
+        let lighting_bind_group = lighting_bind_group(
+            &device,
+            &layouts.lighting,
+            &lights_buffer,
+            &default_environment,
+            &brdf_lut,
+            &shadow_maps,
+        );
+
+        let outline_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Outline Buffer"),
+            contents: bytemuck::cast_slice(&[OutlineUniform {
+                color: OUTLINE_COLOR,
+                center: [0.0; 4],
+                params: [OUTLINE_WIDTH, width as f32, height as f32, 0.0],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let outline_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &layouts.uniform,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: outline_buffer.as_entire_binding(),
+            }],
+            label: Some("outline_bind_group"),
+        });
+
+        let sample_count = 1;
+        let pipelines = ScenePipelines::new(&device, HDR_FORMAT, sample_count, &layouts)?;
+        let depth_texture =
+            Texture::create_depth_texture(&device, width, height, sample_count, "depth_texture");
+        let debug_draw_pass = DebugDrawPass::new(&device, target.format(), &layouts.camera)?;
+        let gizmo_pass = GizmoPass::new(&device, target.format(), &layouts.uniform)?;
+        let post = PostChain::new(&device, target.format(), (width, height))?;
+
+        let id_shader = create_shader_module(&device, include_wgsl!("id.wgsl"))?;
+        let id_buffer = IdBuffer::new(
+            &device,
+            &queue,
+            &id_shader,
+            &layouts.material,
+            &layouts.camera,
+            width,
+            height,
+        );
+
+        Ok(Self {
+            device,
+            queue,
+            target,
+            sample_count,
+            supported_sample_counts,
+            multisampled_target: None,
+            depth_texture,
+            background_color,
+            background: Background::Color,
+            tonemapping: Tonemapping::default(),
+            post,
+            post_effects: Vec::new(),
+            debug_draw: DebugDraw::default(),
+            debug_draw_pass,
+            grid: Some(Grid::default()),
+            show_orientation_gizmo: true,
+            gizmo_pass,
+            layouts,
+            pipelines,
+            shading: Shading::Pbr,
+            debug_view: None,
+            lights,
+            lights_buffer,
+            lighting_bind_group,
+            environment: None,
+            default_environment,
+            brdf_lut,
+            shadow_maps,
+            default_material,
+            terrain: None,
+            model: None,
+            model_data: None,
+            camera,
+            camera_buffer,
+            camera_bind_group,
+            culling_stats: CullingStats::default(),
+            id_buffer,
+            selection: None,
+            selection_buffer,
+            selection_bind_group,
+            outline_buffer,
+            outline_bind_group,
+        })
+    }
+
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// The layout of the bind groups of materials drawn by this renderer, see [`Material::new`].
+    pub fn material_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layouts.material
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.target.size()
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.target.format()
+    }
+
+    /// The texture frames are rendered into, if this is an offscreen renderer.
+    pub fn offscreen_texture(&self) -> Option<&wgpu::Texture> {
+        match &self.target {
+            RenderTarget::Offscreen { texture, .. } => Some(texture),
+            RenderTarget::Surface { .. } => None,
+        }
+    }
+
+    /// Samples per pixel of the main pass, see [`Renderer::set_sample_count`].
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// The sample counts [`Renderer::set_sample_count`] accepts, in increasing order.
+    pub fn supported_sample_counts(&self) -> &[u32] {
+        &self.supported_sample_counts
+    }
+
+    /// Smooths edges with `sample_count` samples per pixel, 1 turning MSAA off. A count the
+    /// adapter doesn't support falls back to the highest supported count below it.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<()> {
+        let supported = self
+            .supported_sample_counts
+            .iter()
+            .copied()
+            .filter(|&count| count <= sample_count)
+            .max()
+            .unwrap_or(1);
+        if supported != sample_count {
+            log::warn!(
+                "Sample count {} is not supported, using {}",
+                sample_count,
+                supported
+            );
+        }
+        if supported == self.sample_count {
+            return Ok(());
+        }
+        let mut pipelines =
+            ScenePipelines::new(&self.device, HDR_FORMAT, supported, &self.layouts)?;
+        pipelines
+            .sky_pass
+            .set_background(&self.device, &self.background);
+        self.pipelines = pipelines;
+        self.sample_count = supported;
+        self.create_attachments();
+        Ok(())
+    }
+
+    /// Creates the depth and color targets of the main pass for the current size and sample
+    /// count.
+    fn create_attachments(&mut self) {
+        let size = self.size();
+        self.depth_texture = Texture::create_depth_texture(
+            &self.device,
+            size.0,
+            size.1,
+            self.sample_count,
+            "depth_texture",
+        );
+        self.post.resize(&self.device, size);
+        self.multisampled_target = if self.sample_count > 1 {
+            Some(create_color_target(
+                &self.device,
+                "Multisampled Target",
+                size,
+                self.sample_count,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ))
+        } else {
+            None
+        };
+    }
+
+    pub fn tonemapping(&self) -> &Tonemapping {
+        &self.tonemapping
+    }
+
+    /// Changes how the HDR scene is mapped into the colors of the frame.
+    pub fn set_tonemapping(&mut self, tonemapping: Tonemapping) {
+        self.tonemapping = tonemapping;
+    }
+
+    /// The post-processing effects, applied in order. The scene is tonemapped before the first
+    /// enabled effect that works on the colors of the frame, see [`Effect::is_hdr`].
+    ///
+    /// [`Effect::is_hdr`]: crate::post::Effect::is_hdr
+    pub fn post_effects(&self) -> &[PostEffect] {
+        &self.post_effects
+    }
+
+    pub fn post_effects_mut(&mut self) -> &mut Vec<PostEffect> {
+        &mut self.post_effects
+    }
+
+    /// The lines drawn over the next frame, which are removed after it.
+    pub fn debug_draw(&self) -> &DebugDraw {
+        &self.debug_draw
+    }
+
+    pub fn debug_draw_mut(&mut self) -> &mut DebugDraw {
+        &mut self.debug_draw
+    }
+
+    pub fn grid(&self) -> Option<&Grid> {
+        self.grid.as_ref()
+    }
+
+    /// Draws `grid` on the ground plane, or no grid.
+    pub fn set_grid(&mut self, grid: Option<Grid>) {
+        self.grid = grid;
+    }
+
+    pub fn shows_orientation_gizmo(&self) -> bool {
+        self.show_orientation_gizmo
+    }
+
+    /// Shows or hides the axes in the bottom left corner, which turn with the camera.
+    pub fn set_orientation_gizmo(&mut self, show: bool) {
+        self.show_orientation_gizmo = show;
+    }
+
+    /// Sets the clear color, which is the background in [`Background::Color`] mode.
+    pub fn set_background_color(&mut self, color: wgpu::Color) {
+        self.background_color = color;
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.pipelines
+            .sky_pass
+            .set_background(&self.device, &background);
+        self.background = background;
+    }
+
+    /// Asks for the object at pixel `x`, `y` to be found by rendering object IDs with the next
+    /// frame. The result is available from [`Renderer::gpu_pick`] a few frames later.
+    pub fn request_gpu_pick(&mut self, x: f64, y: f64) {
+        self.id_buffer.request(x.max(0.0) as u32, y.max(0.0) as u32);
+    }
+
+    /// The result of the last requested GPU pick, once it has been read back. Doesn't wait for
+    /// the GPU.
+    pub fn gpu_pick(&mut self) -> Option<GpuPick> {
+        self.id_buffer.poll(&self.device).map(|(x, y, id)| GpuPick {
+            x,
+            y,
+            picked: Pickable::from_id(id),
+        })
+    }
+
+    /// Selects `selection`, which is then tinted and outlined, or clears the selection.
+    pub fn select(&mut self, selection: Option<Pickable>) {
+        self.selection = selection;
+    }
+
+    pub fn selection(&self) -> Option<Pickable> {
+        self.selection
+    }
+
+    /// The model drawn for `pickable`, if it exists.
+    fn pickable_model(&self, pickable: Pickable) -> Option<&Model> {
+        match pickable {
+            Pickable::Model => self.model.as_ref().map(|lods| lods.level(&self.camera)),
+            Pickable::TerrainChunk(i) => self.terrain.as_ref()?.chunks().get(i),
+        }
+    }
+
+    /// Culling statistics of the last rendered frame.
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culling_stats
+    }
+
+    pub fn debug_view(&self) -> Option<DebugView> {
+        self.debug_view
+    }
+
+    /// Draws the model and the terrain in `view` instead of shading them, or shades them again.
+    /// Debug views show their colors without tonemapping.
+    pub fn set_debug_view(&mut self, view: Option<DebugView>) {
+        self.debug_view = view;
+    }
+
+    pub fn shading(&self) -> Shading {
+        self.shading
+    }
+
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+    }
+
+    /// The lights of the scene, used by [`Shading::Pbr`].
+    pub fn lights(&self) -> &Lights {
+        &self.lights
+    }
+
+    pub fn lights_mut(&mut self) -> &mut Lights {
+        &mut self.lights
+    }
+
+    /// Lights the scene with `environment` in addition to the lights, scaled by
+    /// [`Lights::environment_intensity`], or removes the environment.
+    pub fn set_environment(&mut self, environment: Option<Environment>) {
+        self.environment = environment;
+        self.rebind_lighting();
+    }
+
+    /// Binds the current environment and shadow maps for the lit shader.
+    fn rebind_lighting(&mut self) {
+        self.lighting_bind_group = lighting_bind_group(
+            &self.device,
+            &self.layouts.lighting,
+            &self.lights_buffer,
+            self.environment
+                .as_ref()
+                .unwrap_or(&self.default_environment),
+            &self.brdf_lut,
+            &self.shadow_maps,
+        );
+    }
+
+    pub fn environment(&self) -> Option<&Environment> {
+        self.environment.as_ref()
+    }
+
+    /// Loads an equirectangular `.hdr` file as an environment for [`Renderer::set_environment`].
+    pub fn load_environment(
+        &self,
+        path: &Path,
+        options: &EnvironmentOptions,
+    ) -> Result<Environment> {
+        Environment::load(&self.device, &self.queue, path, options)
+    }
+
+    pub fn shadow_options(&self) -> &ShadowOptions {
+        self.shadow_maps.options()
+    }
+
+    /// Changes how shadows are rendered, for the lights that cast shadows.
+    pub fn set_shadow_options(&mut self, options: ShadowOptions) {
+        self.shadow_maps.set_options(&self.device, options);
+        self.rebind_lighting();
+    }
+
+    /// The pipeline the model is drawn with.
+    fn model_pipeline(&self) -> &wgpu::RenderPipeline {
+        match self.shading {
+            Shading::Pbr => &self.pipelines.pbr,
+            Shading::Unlit => &self.pipelines.unlit,
+        }
+    }
+
+    /// Replaces the drawn model with `model_data`, with the default levels of detail. Models too
+    /// simple to simplify within the default error keep only their full detail level, see
+    /// [`Renderer::set_model_with_lods`].
+    pub fn set_model(&mut self, model_data: &ModelData) -> Result<()> {
+        self.set_model_with_lods(model_data, &LodOptions::default())
+    }
+
+    /// Replaces the drawn model with `model_data`, simplified into levels of detail as set by
+    /// `options`. The model is drawn with the material it refers to, if any.
+    pub fn set_model_with_lods(
+        &mut self,
+        model_data: &ModelData,
+        options: &LodOptions,
+    ) -> Result<()> {
+        let material = model_data
+            .material()
+            .map(|path| self.load_material(path))
+            .transpose()?;
+        let mut lods = LodChain::new(&self.device, model_data, options)?;
+        lods.set_material(material.map(Arc::new));
+        self.model = Some(lods);
+        self.model_data = Some(model_data.clone());
+        Ok(())
+    }
+
+    /// The closest model or terrain chunk under the pixel at `x`, `y`, and where it is hit.
+    pub fn pick(&self, x: f64, y: f64) -> Option<(Pickable, Hit)> {
+        let (width, height) = self.size();
+        let ray = self.camera.cursor_ray(x, y, width, height);
+        let model = self
+            .model
+            .as_ref()
+            .zip(self.model_data.as_ref())
+            .and_then(|(lods, data)| {
+                picking::raycast(&ray, data, Some(lods.levels()[0].aabb()))
+                    .map(|hit| (Pickable::Model, hit))
+            });
+        let chunks = self.terrain.iter().flat_map(|terrain| {
+            terrain
+                .chunks()
+                .iter()
+                .zip(terrain.chunk_data())
+                .enumerate()
+                .filter_map(|(i, (chunk, data))| {
+                    picking::raycast(&ray, data, Some(chunk.aabb()))
+                        .map(|hit| (Pickable::TerrainChunk(i), hit))
+                })
+        });
+        picking::closest(model.into_iter().chain(chunks))
+    }
+
+    /// Loads a `.material` file for drawing with this renderer.
+    pub fn load_material(&self, path: &Path) -> Result<Material> {
+        Material::load(&self.device, &self.queue, &self.layouts.material, path)
+    }
+
+    /// Replaces the material the model is drawn with. Does nothing if there is no model.
+    pub fn set_material(&mut self, material: Material) {
+        if let Some(lods) = &mut self.model {
+            lods.set_material(Some(Arc::new(material)));
+        }
+    }
+
+    /// The base color texture of the model's material, or `None` if there is no model.
+    #[deprecated(note = "use the material of the model instead")]
+    pub fn texture(&self) -> Option<&Texture> {
+        self.model.as_ref().map(|lods| {
+            lods.levels()[0]
+                .material()
+                .unwrap_or(&self.default_material)
+                .base_color()
+        })
+    }
+
+    /// Replaces the material of the model by a default one with `texture` as its base color.
+    /// Does nothing if there is no model.
+    #[deprecated(note = "use `Renderer::set_material` instead")]
+    pub fn set_texture(&mut self, texture: Texture) -> Result<()> {
+        let material = Material::new(
+            &self.device,
+            &self.queue,
+            &self.layouts.material,
+            &MaterialData::default(),
+            MaterialTextures {
+                base_color: Some(texture),
+                ..Default::default()
+            },
+        )?;
+        self.set_material(material);
+        Ok(())
+    }
+
+    /// Replaces the terrain drawn below the model with one generated from `heightmap`.
+    pub fn set_terrain(
+        &mut self,
+        heightmap: &Heightmap,
+        options: &TerrainOptions,
+        layers: Vec<TerrainLayer>,
+    ) -> Result<()> {
+        self.terrain = Some(Terrain::new(
+            &self.device,
+            &self.queue,
+            &self.layouts.terrain,
+            heightmap,
+            options,
+            layers,
+        )?);
+        Ok(())
+    }
+
+    pub fn terrain(&self) -> Option<&Terrain> {
+        self.terrain.as_ref()
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        log::info!("Resizing to {}x{}", width, height);
+        if width == 0 || height == 0 {
+            return;
+        }
+        match &mut self.target {
+            RenderTarget::Surface { surface, config } => {
+                config.width = width;
+                config.height = height;
+                surface.configure(&self.device, config);
+            }
+            RenderTarget::Offscreen { .. } => {
+                self.target = RenderTarget::offscreen(&self.device, width, height);
+            }
+        }
+        self.create_attachments();
+        self.id_buffer.resize(&self.device, width, height);
+    }
+
+    pub fn update(&mut self) {
+        self.queue.write_buffer(
+            &self.camera_buffer,
+            0,
+            bytemuck::cast_slice(&[self.camera.to_uniform()]),
+        );
+        let mut lights = self.lights.to_uniform();
+        let environment = self.environment.as_ref();
+        lights.set_specular_levels(
+            environment
+                .unwrap_or(&self.default_environment)
+                .specular_levels(),
+        );
+        self.queue
+            .write_buffer(&self.lights_buffer, 0, bytemuck::cast_slice(&[lights]));
+        self.pipelines
+            .sky_pass
+            .update(&self.queue, &self.camera, &self.background, &self.lights);
+        self.shadow_maps
+            .update(&self.queue, &self.camera, &self.lights);
+        self.pipelines.debug.update(&self.queue, &self.camera);
+        if let Some(grid) = &self.grid {
+            self.pipelines
+                .grid_pass
+                .update(&self.queue, &self.camera, grid);
+        }
+        self.gizmo_pass.update(&self.queue, &self.camera);
+        let (tonemapping, exposure) = match self.debug_view {
+            Some(_) => (
+                Tonemapping {
+                    operator: Tonemap::None,
+                    gamma: 1.0,
+                },
+                1.0,
+            ),
+            None => (self.tonemapping, self.lights.scene_exposure()),
+        };
+        self.post.update(
+            &self.device,
+            &self.queue,
+            &tonemapping,
+            exposure,
+            &self.post_effects,
+        );
+        let tint = match self.selection {
+            Some(Pickable::Model) => SELECTION_TINT,
+            _ => [0.0; 4],
+        };
+        self.queue
+            .write_buffer(&self.selection_buffer, 0, bytemuck::cast_slice(&[tint]));
+        let center = self
+            .selection
+            .and_then(|selection| self.pickable_model(selection))
+            .map(|model| model.bounding_sphere().center);
+        if let Some(center) = center {
+            let (width, height) = self.size();
+            let outline = OutlineUniform {
+                color: OUTLINE_COLOR,
+                center: [center.x, center.y, center.z, 1.0],
+                params: [OUTLINE_WIDTH, width as f32, height as f32, 0.0],
+            };
+            self.queue
+                .write_buffer(&self.outline_buffer, 0, bytemuck::cast_slice(&[outline]));
+        }
+    }
+
+    pub fn render(&mut self) -> std::result::Result<(), wgpu::SurfaceError> {
+        let (output, view) = match &self.target {
+            RenderTarget::Surface { surface, .. } => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (Some(output), view)
+            }
+            RenderTarget::Offscreen { texture, .. } => (
+                None,
+                texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            ),
+        };
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Render Encoder"),
+            });
+        let mut casters: Vec<&Model> = Vec::new();
+        if let Some(terrain) = &self.terrain {
+            casters.extend(terrain.chunks());
+        }
+        if let Some(lods) = &self.model {
+            casters.push(lods.level(&self.camera));
+        }
+        let shadow_casters: Vec<(&Model, &Material)> = casters
+            .iter()
+            .map(|&model| (model, model.material().unwrap_or(&self.default_material)))
+            .collect();
+        self.shadow_maps.encode(&mut encoder, &shadow_casters);
+
+        if let Some(view) = self.debug_view {
+            self.pipelines.debug.prepare(&self.device, view, &casters);
+        }
+
+        let frustum = self.camera.frustum();
+        let mut stats = CullingStats::default();
+        let mut visible = |model: &Model| {
+            let visible = frustum.intersects_sphere(model.bounding_sphere())
+                && frustum.intersects_aabb(model.aabb());
+            if visible {
+                stats.drawn += 1;
+            } else {
+                stats.culled += 1;
+            }
+            visible
+        };
+        {
+            let (color_view, resolve_target) = match &self.multisampled_target {
+                Some(multisampled) => (multisampled, Some(self.post.scene_view())),
+                None => (self.post.scene_view(), None),
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.background_color),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.depth_texture.view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(0),
+                        store: true,
+                    }),
+                }),
+            });
+            let mut selected = None;
+
+            if let Some(terrain) = &self.terrain {
+                for (i, chunk) in terrain.chunks().iter().enumerate() {
+                    if !visible(chunk) {
+                        continue;
+                    }
+                    let is_selected = self.selection == Some(Pickable::TerrainChunk(i));
+                    if is_selected {
+                        selected = Some(chunk);
+                    }
+                    render_pass.set_stencil_reference(is_selected as u32);
+                    if let Some(view) = self.debug_view {
+                        self.pipelines.debug.draw(
+                            &mut render_pass,
+                            view,
+                            chunk,
+                            &self.camera_bind_group,
+                        );
+                        continue;
+                    }
+                    render_pass.set_pipeline(&self.pipelines.terrain);
+                    render_pass.set_bind_group(0, terrain.bind_group(), &[]);
+                    render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                    render_pass.set_bind_group(2, &self.selection_bind_group, &[]);
+                    render_pass.set_bind_group(3, &self.lighting_bind_group, &[]);
+                    render_pass.set_vertex_buffer(0, chunk.vertex_buffer().slice(..));
+                    render_pass
+                        .set_index_buffer(chunk.index_buffer().slice(..), chunk.index_format());
+                    render_pass.draw_indexed(0..chunk.num_vertices(), 0, 0..1);
+                }
+            }
+
+            if let Some(lods) = &self.model {
+                let model = lods.level(&self.camera);
+                if visible(model) {
+                    let is_selected = self.selection == Some(Pickable::Model);
+                    if is_selected {
+                        selected = Some(model);
+                    }
+                    render_pass.set_stencil_reference(is_selected as u32);
+                    match self.debug_view {
+                        Some(view) => self.pipelines.debug.draw(
+                            &mut render_pass,
+                            view,
+                            model,
+                            &self.camera_bind_group,
+                        ),
+                        None => {
+                            let material = model.material().unwrap_or(&self.default_material);
+                            render_pass.set_pipeline(self.model_pipeline());
+                            render_pass.set_bind_group(0, material.bind_group(), &[]);
+                            render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+                            render_pass.set_bind_group(2, &self.selection_bind_group, &[]);
+                            render_pass.set_bind_group(3, &self.lighting_bind_group, &[]);
+                            render_pass.set_vertex_buffer(0, model.vertex_buffer().slice(..));
+                            render_pass.set_index_buffer(
+                                model.index_buffer().slice(..),
+                                model.index_format(),
+                            );
+                            render_pass.draw_indexed(0..model.num_vertices(), 0, 0..1);
+                        }
+                    }
+                }
+            }
+
+            self.pipelines
+                .sky_pass
+                .draw(&mut render_pass, &self.background);
+            if self.grid.is_some() {
+                self.pipelines.grid_pass.draw(&mut render_pass);
+            }
+
+            // Drawn last and over everything, so that the outline is visible even where the
+            // selected object is hidden.
+            if let Some(model) = selected {
+                render_pass.set_pipeline(&self.pipelines.outline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                render_pass.set_bind_group(1, &self.outline_bind_group, &[]);
+                let material = model.material().unwrap_or(&self.default_material);
+                render_pass.set_bind_group(2, material.bind_group(), &[]);
+                render_pass.set_stencil_reference(1);
+                render_pass.set_vertex_buffer(0, model.vertex_buffer().slice(..));
+                render_pass.set_index_buffer(model.index_buffer().slice(..), model.index_format());
+                render_pass.draw_indexed(0..model.num_vertices(), 0, 0..1);
+            }
+        }
+        self.post.encode(&mut encoder, &self.post_effects, &view);
+        self.debug_draw_pass
+            .prepare(&self.device, &self.queue, &self.debug_draw);
+        self.debug_draw_pass
+            .encode(&mut encoder, &view, &self.camera_bind_group);
+        self.debug_draw.clear();
+        if self.show_orientation_gizmo {
+            self.gizmo_pass.encode(&mut encoder, &view, self.size());
+        }
+        let default_material = &self.default_material;
+        let model_draw = self.model.as_ref().map(|lods| {
+            let model = lods.level(&self.camera);
+            IdDraw {
+                id: Pickable::Model.id(),
+                model,
+                material: model.material().unwrap_or(default_material),
+            }
+        });
+        let chunk_draws = self.terrain.iter().flat_map(|terrain| {
+            terrain
+                .chunks()
+                .iter()
+                .enumerate()
+                .map(|(i, chunk)| IdDraw {
+                    id: Pickable::TerrainChunk(i).id(),
+                    model: chunk,
+                    material: default_material,
+                })
+        });
+        self.id_buffer.encode(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &self.camera_bind_group,
+            chunk_draws.chain(model_draw),
+        );
+        if stats != self.culling_stats {
+            log::info!("Drawing {} models, culled {}", stats.drawn, stats.culled);
+            self.culling_stats = stats;
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.id_buffer.submitted();
+        if let Some(output) = output {
+            output.present();
+        }
+        Ok(())
+    }
+}
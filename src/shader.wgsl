@@ -28,12 +28,40 @@ fn vs_main(vertex: Vertex)
 }
 
 @group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_base_color: texture_2d<f32>;
 
-@group(0) @binding(1)
-var s_diffuse: sampler;
+@group(0) @binding(3)
+var t_emissive: texture_2d<f32>;
+
+@group(0) @binding(4)
+var s_material: sampler;
+
+struct MaterialUniform {
+  base_color_factor: vec4<f32>,
+  emissive_factor: vec4<f32>,
+  // metallic factor, roughness factor, normal scale
+  params: vec4<f32>
+};
+
+@group(0) @binding(5)
+var<uniform> material: MaterialUniform;
+
+struct SelectionUniform {
+  // Color mixed into the material, by its alpha
+  tint: vec4<f32>
+};
+
+@group(2) @binding(0)
+var<uniform> selection: SelectionUniform;
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-  return textureSample(t_diffuse, s_diffuse, in.uv);
-}
\ No newline at end of file
+  let base_color = textureSample(t_base_color, s_material, in.uv) * material.base_color_factor;
+  let emissive = textureSample(t_emissive, s_material, in.uv).rgb * material.emissive_factor.rgb;
+  // Transparent texels don't hide what is behind them, nor mark it as selected.
+  if (base_color.a < 0.5) {
+    discard;
+  }
+  let color = base_color.rgb + emissive;
+  return vec4<f32>(mix(color, selection.tint.rgb, selection.tint.a), base_color.a);
+}
@@ -0,0 +1,374 @@
+//! Shadow maps: the depth of shadow casters as seen from each light that casts shadows, with
+//! cascades of maps covering ever larger parts of the view for the directional light.
+
+use crate::camera::{Camera, LightSpaceUniform, OPENGL_TO_WGPU_MATRIX};
+use crate::error::Result;
+use crate::light::{Lights, MAX_SPOT_LIGHTS};
+use crate::material::Material;
+use crate::model::Model;
+use crate::renderer::{create_shader_module, Renderer};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Rad, Transform, Vector3};
+use wgpu::include_wgsl;
+
+/// Shadow maps of the directional light, from the nearest to the farthest.
+pub const CASCADE_COUNT: usize = 4;
+
+const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+/// The cascades, then one layer per spot light.
+const LAYER_COUNT: usize = CASCADE_COUNT + MAX_SPOT_LIGHTS;
+/// Distance of the near plane of the projections of spot lights.
+const SPOT_NEAR: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowOptions {
+    /// Width and height of each shadow map, in texels.
+    pub map_size: u32,
+    /// Distance from the camera up to which the directional light casts shadows.
+    pub distance: f32,
+    /// How much the cascades are spaced logarithmically rather than evenly, from 0 to 1. Higher
+    /// values give nearby shadows more detail.
+    pub split_lambda: f32,
+    /// Distance towards the directional light beyond each cascade up to which objects still
+    /// cast shadows into it.
+    pub caster_distance: f32,
+    /// Radius in texels of the percentage closer filter softening the edges of shadows.
+    pub pcf_radius: f32,
+    /// How far shadow lookups are moved along the surface normal, in texels, against surfaces
+    /// shadowing themselves.
+    pub normal_offset: f32,
+}
+
+impl Default for ShadowOptions {
+    fn default() -> Self {
+        Self {
+            map_size: 1024,
+            distance: 30.0,
+            split_lambda: 0.75,
+            caster_distance: 20.0,
+            pcf_radius: 1.0,
+            normal_offset: 1.5,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct ShadowUniform {
+    cascades: [[[f32; 4]; 4]; CASCADE_COUNT],
+    spots: [[[f32; 4]; 4]; MAX_SPOT_LIGHTS],
+    /// The far end of each cascade, as distance along the camera's forward direction.
+    cascade_splits: [f32; CASCADE_COUNT],
+    /// The size of a texel of each cascade, in world units.
+    cascade_texel_sizes: [f32; CASCADE_COUNT],
+    camera_forward: [f32; 4],
+    /// 1 for each spot light casting shadows, 0 for the others.
+    spot_shadows: [f32; MAX_SPOT_LIGHTS],
+    /// Size of a texel in UV, PCF radius, normal offset and 1 if the directional light casts
+    /// shadows.
+    params: [f32; 4],
+}
+
+/// A layer of the shadow map texture with the light space it is rendered in.
+struct Layer {
+    view: wgpu::TextureView,
+    light_space_buffer: wgpu::Buffer,
+    light_space_bind_group: wgpu::BindGroup,
+}
+
+pub(crate) struct ShadowMaps {
+    options: ShadowOptions,
+    pipeline: wgpu::RenderPipeline,
+    /// All layers, for sampling.
+    view: wgpu::TextureView,
+    layers: Vec<Layer>,
+    sampler: wgpu::Sampler,
+    uniform_buffer: wgpu::Buffer,
+    /// The layers rendered this frame.
+    active_layers: Vec<usize>,
+}
+
+impl ShadowMaps {
+    pub fn new(
+        device: &wgpu::Device,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+        options: ShadowOptions,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, include_wgsl!("shadow.wgsl"))?;
+        let pipeline = Renderer::make_depth_pipeline(
+            device,
+            &shader,
+            &[uniform_bind_group_layout, material_bind_group_layout],
+            wgpu::DepthStencilState {
+                format: SHADOW_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            },
+        );
+        let (view, layer_views) = Self::create_texture(device, options.map_size);
+        let layers = layer_views
+            .into_iter()
+            .map(|view| {
+                let light_space_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Light Space Buffer"),
+                    size: std::mem::size_of::<LightSpaceUniform>() as u64,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let light_space_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: uniform_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: light_space_buffer.as_entire_binding(),
+                    }],
+                    label: Some("light_space_bind_group"),
+                });
+                Layer {
+                    view,
+                    light_space_buffer,
+                    light_space_bind_group,
+                }
+            })
+            .collect();
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow Buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Ok(Self {
+            options,
+            pipeline,
+            view,
+            layers,
+            sampler,
+            uniform_buffer,
+            active_layers: Vec::new(),
+        })
+    }
+
+    /// A texture holding all shadow maps, viewed as a whole and by layer.
+    fn create_texture(
+        device: &wgpu::Device,
+        map_size: u32,
+    ) -> (wgpu::TextureView, Vec<wgpu::TextureView>) {
+        let map_size = map_size.clamp(1, device.limits().max_texture_dimension_2d);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("shadow_map_texture"),
+            size: wgpu::Extent3d {
+                width: map_size,
+                height: map_size,
+                depth_or_array_layers: LAYER_COUNT as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..LAYER_COUNT as u32)
+            .map(|layer| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: std::num::NonZeroU32::new(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        (view, layer_views)
+    }
+
+    pub fn options(&self) -> &ShadowOptions {
+        &self.options
+    }
+
+    /// Changes the options, recreating the shadow maps if their size changes. Views of them
+    /// have to be bound again afterwards.
+    pub fn set_options(&mut self, device: &wgpu::Device, options: ShadowOptions) {
+        if options.map_size != self.options.map_size {
+            let (view, layer_views) = Self::create_texture(device, options.map_size);
+            self.view = view;
+            for (layer, view) in self.layers.iter_mut().zip(layer_views) {
+                layer.view = view;
+            }
+        }
+        self.options = options;
+    }
+
+    /// All shadow maps, as a 2D array texture of depths.
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// A comparison sampler for the shadow maps.
+    pub fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+
+    /// The light space matrices of the shadow maps and how to sample them.
+    pub fn uniform_buffer(&self) -> &wgpu::Buffer {
+        &self.uniform_buffer
+    }
+
+    /// Fits the shadow maps to the view of `camera` and to `lights`.
+    pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, lights: &Lights) {
+        let options = self.options;
+        let forward = camera.forward();
+        let mut uniform = ShadowUniform {
+            cascades: [[[0.0; 4]; 4]; CASCADE_COUNT],
+            spots: [[[0.0; 4]; 4]; MAX_SPOT_LIGHTS],
+            cascade_splits: [0.0; CASCADE_COUNT],
+            cascade_texel_sizes: [0.0; CASCADE_COUNT],
+            camera_forward: [forward.x, forward.y, forward.z, 0.0],
+            spot_shadows: [0.0; MAX_SPOT_LIGHTS],
+            params: [
+                1.0 / options.map_size as f32,
+                options.pcf_radius,
+                options.normal_offset,
+                0.0,
+            ],
+        };
+        self.active_layers.clear();
+        let mut light_spaces = Vec::new();
+
+        if let Some(light) = lights.directional.filter(|light| light.casts_shadows) {
+            let near = camera.znear();
+            let far = options.distance.min(camera.zfar()).max(near);
+            let mut start = near;
+            for cascade in 0..CASCADE_COUNT {
+                let t = (cascade + 1) as f32 / CASCADE_COUNT as f32;
+                let logarithmic = near * (far / near).powf(t);
+                let even = near + (far - near) * t;
+                let end = options.split_lambda * logarithmic + (1.0 - options.split_lambda) * even;
+                let (view_proj, texel_size) =
+                    self.cascade_view_projection(camera, light.direction, start, end);
+                uniform.cascades[cascade] = view_proj.into();
+                uniform.cascade_splits[cascade] = end;
+                uniform.cascade_texel_sizes[cascade] = texel_size;
+                light_spaces.push((cascade, view_proj));
+                start = end;
+            }
+            uniform.params[3] = 1.0;
+        }
+
+        for (i, light) in lights.spots.iter().take(MAX_SPOT_LIGHTS).enumerate() {
+            if !light.casts_shadows {
+                continue;
+            }
+            let direction = light.direction.normalize();
+            let up = if direction.y.abs() > 0.99 {
+                Vector3::unit_z()
+            } else {
+                Vector3::unit_y()
+            };
+            let view = Matrix4::look_to_rh(light.position, direction, up);
+            let fovy = Rad((2.0 * light.outer_angle.0).clamp(0.01, 3.1));
+            let projection = cgmath::perspective(fovy, 1.0, SPOT_NEAR, light.range.max(SPOT_NEAR));
+            let view_proj = OPENGL_TO_WGPU_MATRIX * projection * view;
+            uniform.spots[i] = view_proj.into();
+            uniform.spot_shadows[i] = 1.0;
+            light_spaces.push((CASCADE_COUNT + i, view_proj));
+        }
+
+        for (layer, view_proj) in light_spaces {
+            queue.write_buffer(
+                &self.layers[layer].light_space_buffer,
+                0,
+                bytemuck::cast_slice(&[LightSpaceUniform::new(view_proj)]),
+            );
+            self.active_layers.push(layer);
+        }
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// An orthographic projection along `direction` covering the part of the view of `camera`
+    /// from `near` to `far`, and the size of its texels in world units.
+    fn cascade_view_projection(
+        &self,
+        camera: &Camera,
+        direction: Vector3<f32>,
+        near: f32,
+        far: f32,
+    ) -> (Matrix4<f32>, f32) {
+        let corners = camera.frustum_slice_corners(near, far);
+        let center = Point3::centroid(&corners);
+        let radius = corners
+            .iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0, f32::max);
+        // A size that doesn't change as the camera turns, and that is rounded so that it
+        // doesn't flicker either, keeps the edges of shadows still.
+        let radius = (radius * 16.0).ceil() / 16.0;
+        let texel_size = 2.0 * radius / self.options.map_size as f32;
+
+        let direction = direction.normalize();
+        let up = if direction.y.abs() > 0.99 {
+            Vector3::unit_z()
+        } else {
+            Vector3::unit_y()
+        };
+        let view = Matrix4::look_to_rh(Point3::origin(), direction, up);
+        // Moving the cascade only by whole texels keeps shadows still as the camera moves.
+        let center = view.transform_point(center);
+        let x = (center.x / texel_size).floor() * texel_size;
+        let y = (center.y / texel_size).floor() * texel_size;
+        let projection = cgmath::ortho(
+            x - radius,
+            x + radius,
+            y - radius,
+            y + radius,
+            -center.z - radius - self.options.caster_distance,
+            -center.z + radius,
+        );
+        (OPENGL_TO_WGPU_MATRIX * projection * view, texel_size)
+    }
+
+    /// Renders `casters` into the shadow maps used this frame, leaving out the transparent texels
+    /// of their materials.
+    pub fn encode(&self, encoder: &mut wgpu::CommandEncoder, casters: &[(&Model, &Material)]) {
+        for &layer in &self.active_layers {
+            let layer = &self.layers[layer];
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Render Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &layer.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &layer.light_space_bind_group, &[]);
+            for (model, material) in casters {
+                render_pass.set_bind_group(1, material.bind_group(), &[]);
+                render_pass.set_vertex_buffer(0, model.vertex_buffer().slice(..));
+                render_pass.set_index_buffer(model.index_buffer().slice(..), model.index_format());
+                render_pass.draw_indexed(0..model.num_vertices(), 0, 0..1);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Renders the depth of shadow casters as seen from a light.
+
+struct LightSpaceUniform {
+  view_proj: mat4x4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> light_space: LightSpaceUniform;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) uv: vec2<f32>
+};
+
+struct Vertex {
+ @location(0) position: vec3<f32>,
+ @location(1) uv: vec2<f32>
+};
+
+@vertex
+fn vs_main(vertex: Vertex) -> VertexOutput {
+  var out: VertexOutput;
+  out.clip_position = light_space.view_proj * vec4<f32>(vertex.position, 1.0);
+  out.uv = vertex.uv;
+  return out;
+}
+
+@group(1) @binding(0)
+var t_base_color: texture_2d<f32>;
+
+@group(1) @binding(4)
+var s_material: sampler;
+
+struct MaterialUniform {
+  base_color_factor: vec4<f32>,
+  emissive_factor: vec4<f32>,
+  // metallic factor, roughness factor, normal scale
+  params: vec4<f32>
+};
+
+@group(1) @binding(5)
+var<uniform> material: MaterialUniform;
+
+// Transparent texels let the light through.
+@fragment
+fn fs_main(in: VertexOutput) {
+  let alpha = textureSample(t_base_color, s_material, in.uv).a * material.base_color_factor.a;
+  if (alpha < 0.5) {
+    discard;
+  }
+}
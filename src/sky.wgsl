@@ -0,0 +1,60 @@
+// Draws the sky behind everything, as a triangle covering the screen at the far plane.
+
+struct SkyUniform {
+  // inverse of the view projection without translation
+  inverse_view_proj: mat4x4<f32>,
+  zenith: vec4<f32>,
+  horizon: vec4<f32>,
+  ground: vec4<f32>,
+  // factor applied to cubemap texels
+  params: vec4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> sky: SkyUniform;
+
+@group(1) @binding(0)
+var t_sky: texture_cube<f32>;
+
+@group(1) @binding(1)
+var s_sky: sampler;
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) ndc: vec2<f32>
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+  let ndc = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2.0 - 1.0;
+  var out: VertexOutput;
+  // A depth of 1, behind everything drawn.
+  out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+  out.ndc = ndc;
+  return out;
+}
+
+// The direction seen through a point on the screen.
+fn view_direction(ndc: vec2<f32>) -> vec3<f32> {
+  let far = sky.inverse_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+  return normalize(far.xyz / far.w);
+}
+
+@fragment
+fn fs_gradient(in: VertexOutput) -> @location(0) vec4<f32> {
+  let height = view_direction(in.ndc).y;
+  var color: vec3<f32>;
+  if (height >= 0.0) {
+    color = mix(sky.horizon.rgb, sky.zenith.rgb, sqrt(height));
+  } else {
+    // The ground takes over quickly below the horizon.
+    color = mix(sky.horizon.rgb, sky.ground.rgb, clamp(-height * 8.0, 0.0, 1.0));
+  }
+  return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_cubemap(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = textureSample(t_sky, s_sky, view_direction(in.ndc)).rgb * sky.params.x;
+  return vec4<f32>(color, 1.0);
+}
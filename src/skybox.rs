@@ -0,0 +1,341 @@
+//! What is drawn behind the scene: a clear color, a procedural gradient sky or a cubemap.
+
+use crate::camera::Camera;
+use crate::environment::{self, Environment, HdrImage};
+use crate::error::{Error, Result};
+use crate::light::{Lights, SCENE_WHITE};
+use crate::renderer::{self, create_shader_module};
+use cgmath::{Matrix4, SquareMatrix};
+use image::error::{ParameterError, ParameterErrorKind};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use wgpu::include_wgsl;
+use wgpu::util::DeviceExt;
+
+/// A sky fading from `horizon` up to `zenith` and down to `ground`, in linear RGB.
+#[derive(Clone, Copy, Debug)]
+pub struct SkyGradient {
+    pub zenith: [f32; 3],
+    pub horizon: [f32; 3],
+    pub ground: [f32; 3],
+}
+
+impl Default for SkyGradient {
+    /// A clear blue sky over brown ground.
+    fn default() -> Self {
+        Self {
+            zenith: [0.12, 0.3, 0.7],
+            horizon: [0.6, 0.72, 0.9],
+            ground: [0.2, 0.17, 0.14],
+        }
+    }
+}
+
+/// A cubemap shown as the sky.
+pub struct Skybox {
+    view: wgpu::TextureView,
+    /// Whether the texels are luminance relative to [`Lights::environment_intensity`] rather
+    /// than colors.
+    is_hdr: bool,
+}
+
+impl Skybox {
+    /// Loads the six faces of a cubemap in the order +X, -X, +Y, -Y, +Z, -Z. The faces are sRGB
+    /// colors and must all be square and of the same size.
+    pub fn load_faces<P: AsRef<Path>>(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[P; 6],
+    ) -> Result<Self> {
+        let mut faces = Vec::with_capacity(paths.len());
+        for path in paths {
+            let path = path.as_ref();
+            let decode_error = |source| Error::ImageDecode {
+                name: path.display().to_string(),
+                source,
+            };
+            let file = File::open(path).map_err(|err| Error::io(path, err))?;
+            let image_format = image::ImageFormat::from_path(path).map_err(decode_error)?;
+            let face = image::load(BufReader::new(file), image_format)
+                .map_err(decode_error)?
+                .to_rgba8();
+            let size = faces.first().map_or(face.width(), image::RgbaImage::width);
+            if face.width() != size || face.height() != size {
+                return Err(decode_error(image::ImageError::Parameter(
+                    ParameterError::from_kind(ParameterErrorKind::DimensionMismatch),
+                )));
+            }
+            faces.push(face);
+        }
+        let size = faces[0].width();
+        let pixels: Vec<u8> = faces
+            .iter()
+            .flat_map(|face| face.as_raw())
+            .copied()
+            .collect();
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("skybox_texture"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            },
+            &pixels,
+        );
+        Ok(Self {
+            view: Self::cube_view(&texture),
+            is_hdr: false,
+        })
+    }
+
+    /// Converts the equirectangular `image` into a cubemap with faces of `size`, on the GPU. The
+    /// image is shown as bright as it lights the scene when used as an
+    /// [`Environment`](crate::environment::Environment).
+    pub fn from_equirect(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &HdrImage,
+        size: u32,
+    ) -> Result<Self> {
+        let texture = environment::equirect_to_cube(device, queue, image, size)?;
+        Ok(Self {
+            view: Self::cube_view(&texture),
+            is_hdr: true,
+        })
+    }
+
+    /// Shows the cubemap of `environment`, so that the sky matches the light it casts.
+    pub fn from_environment(environment: &Environment) -> Self {
+        Self {
+            view: Self::cube_view(environment.cubemap_texture()),
+            is_hdr: true,
+        }
+    }
+
+    fn cube_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        })
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+/// What the renderer draws where there is no geometry.
+#[derive(Clone)]
+pub enum Background {
+    /// The color set by [`Renderer::set_background_color`](crate::Renderer::set_background_color).
+    Color,
+    Gradient(SkyGradient),
+    Skybox(Arc<Skybox>),
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct SkyUniform {
+    inverse_view_proj: [[f32; 4]; 4],
+    zenith: [f32; 4],
+    horizon: [f32; 4],
+    ground: [f32; 4],
+    /// The factor applied to cubemap texels.
+    params: [f32; 4],
+}
+
+/// Draws the gradient and cubemap backgrounds, at the far plane after the scene so that only
+/// uncovered pixels are shaded.
+pub(crate) struct SkyPass {
+    gradient_pipeline: wgpu::RenderPipeline,
+    cubemap_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    cubemap_bind_group_layout: wgpu::BindGroupLayout,
+    /// Binds the cubemap of the current [`Background::Skybox`].
+    cubemap_bind_group: Option<wgpu::BindGroup>,
+}
+
+impl SkyPass {
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        uniform_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Result<Self> {
+        let shader = create_shader_module(device, include_wgsl!("sky.wgsl"))?;
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sky Buffer"),
+            size: std::mem::size_of::<SkyUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform_buffer.as_entire_binding(),
+            }],
+            label: Some("sky_bind_group"),
+        });
+        let cubemap_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::Cube,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("skybox_bind_group_layout"),
+            });
+        let pipeline = |entry_point, bind_group_layouts: &[&wgpu::BindGroupLayout]| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Sky Pipeline Layout"),
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                // Only where nothing else was drawn.
+                depth_stencil: Some(renderer::depth_stencil_state(
+                    false,
+                    wgpu::CompareFunction::LessEqual,
+                    wgpu::StencilState::default(),
+                )),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                multiview: None,
+            })
+        };
+        let gradient_pipeline = pipeline("fs_gradient", &[uniform_bind_group_layout]);
+        let cubemap_pipeline = pipeline(
+            "fs_cubemap",
+            &[uniform_bind_group_layout, &cubemap_bind_group_layout],
+        );
+        Ok(Self {
+            gradient_pipeline,
+            cubemap_pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            cubemap_bind_group_layout,
+            cubemap_bind_group: None,
+        })
+    }
+
+    /// Prepares drawing `background`, which must be called whenever it changes.
+    pub fn set_background(&mut self, device: &wgpu::Device, background: &Background) {
+        self.cubemap_bind_group = match background {
+            Background::Skybox(skybox) => {
+                let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                    mag_filter: wgpu::FilterMode::Linear,
+                    min_filter: wgpu::FilterMode::Linear,
+                    mipmap_filter: wgpu::FilterMode::Linear,
+                    ..Default::default()
+                });
+                Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.cubemap_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(skybox.view()),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&sampler),
+                        },
+                    ],
+                    label: Some("skybox_bind_group"),
+                }))
+            }
+            Background::Color | Background::Gradient(_) => None,
+        };
+    }
+
+    pub fn update(
+        &self,
+        queue: &wgpu::Queue,
+        camera: &Camera,
+        background: &Background,
+        lights: &Lights,
+    ) {
+        let inverse_view_proj = camera
+            .rotation_view_projection()
+            .invert()
+            .unwrap_or_else(Matrix4::identity);
+        let gradient = match background {
+            Background::Gradient(gradient) => *gradient,
+            _ => SkyGradient::default(),
+        };
+        let scale = match background {
+            Background::Skybox(skybox) if skybox.is_hdr => {
+                lights.environment_intensity / SCENE_WHITE
+            }
+            _ => 1.0,
+        };
+        let color = |[r, g, b]: [f32; 3]| [r, g, b, 1.0];
+        let uniform = SkyUniform {
+            inverse_view_proj: inverse_view_proj.into(),
+            zenith: color(gradient.zenith),
+            horizon: color(gradient.horizon),
+            ground: color(gradient.ground),
+            params: [scale, 0.0, 0.0, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Draws `background` into the pixels still at the far plane.
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, background: &Background) {
+        match (background, &self.cubemap_bind_group) {
+            (Background::Gradient(_), _) => {
+                render_pass.set_pipeline(&self.gradient_pipeline);
+            }
+            (Background::Skybox(_), Some(cubemap_bind_group)) => {
+                render_pass.set_pipeline(&self.cubemap_pipeline);
+                render_pass.set_bind_group(1, cubemap_bind_group, &[]);
+            }
+            _ => return,
+        }
+        render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,400 @@
+//! Terrain generated from grayscale heightmaps.
+//!
+//! The terrain is split into square chunks, each a separate [`Model`], and textured with up to
+//! [`MAX_LAYERS`] tiling textures that are blended by height and slope in `terrain.wgsl`.
+
+use crate::error::{Error, Result};
+use crate::model::{Model, ModelData};
+use crate::texture::Texture;
+use cgmath::{InnerSpace, Vector3};
+use image::GenericImageView;
+use log::*;
+use std::ops::Range;
+use std::path::Path;
+use wgpu::util::DeviceExt;
+
+pub const MAX_LAYERS: usize = 4;
+
+/// Heights sampled from an image, normalized so that black is 0 and white is 1.
+pub struct Heightmap {
+    width: u32,
+    depth: u32,
+    heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn from_image(img: &image::DynamicImage) -> Self {
+        let color = img.color();
+        // Widening 8-bit images to 16 bits would scale white to 0xFF00 rather than 0xFFFF.
+        let heights = if color.bytes_per_pixel() == color.channel_count() {
+            let luma = img.to_luma8();
+            luma.pixels()
+                .map(|pixel| pixel[0] as f32 / u8::MAX as f32)
+                .collect()
+        } else {
+            let luma = img.to_luma16();
+            luma.pixels()
+                .map(|pixel| pixel[0] as f32 / u16::MAX as f32)
+                .collect()
+        };
+        Self {
+            width: img.width(),
+            depth: img.height(),
+            heights,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let img = image::open(path).map_err(|source| Error::ImageDecode {
+            name: path.display().to_string(),
+            source,
+        })?;
+        Ok(Self::from_image(&img))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The height at pixel `x`, `z`, clamped to the edges of the map.
+    pub fn height(&self, x: i64, z: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let z = z.clamp(0, self.depth as i64 - 1) as usize;
+        self.heights[z * self.width as usize + x]
+    }
+}
+
+pub struct TerrainOptions {
+    /// World units between neighbouring heightmap pixels.
+    pub horizontal_scale: f32,
+    /// World height of a white heightmap pixel.
+    pub vertical_scale: f32,
+    /// Quads along each side of a chunk.
+    pub chunk_size: u32,
+    /// World units covered by one repetition of the layer textures.
+    pub texture_scale: f32,
+    /// Width of the transition between layers, in normalized height and slope.
+    pub layer_blend: f32,
+}
+
+impl Default for TerrainOptions {
+    fn default() -> Self {
+        Self {
+            horizontal_scale: 1.0,
+            vertical_scale: 1.0,
+            chunk_size: 64,
+            texture_scale: 4.0,
+            layer_blend: 0.05,
+        }
+    }
+}
+
+/// A texture covering the parts of the terrain within a range of heights and slopes.
+pub struct TerrainLayer {
+    pub texture: Texture,
+    /// Normalized heights, 0 being black and 1 white in the heightmap.
+    pub heights: Range<f32>,
+    /// Slopes, 0 being flat and 1 vertical.
+    pub slopes: Range<f32>,
+}
+
+/// Splits `heightmap` into chunks of at most `options.chunk_size` quads along each side. The
+/// terrain is centered on the origin in the XZ plane, with normals computed from the whole map
+/// so that they are continuous across chunk borders.
+pub fn generate_chunks(heightmap: &Heightmap, options: &TerrainOptions) -> Vec<ModelData> {
+    let chunk_size = options.chunk_size.max(1);
+    let offset_x = (heightmap.width as f32 - 1.0) / 2.0;
+    let offset_z = (heightmap.depth as f32 - 1.0) / 2.0;
+    let position = |x: i64, z: i64| {
+        Vector3::new(
+            (x as f32 - offset_x) * options.horizontal_scale,
+            heightmap.height(x, z) * options.vertical_scale,
+            (z as f32 - offset_z) * options.horizontal_scale,
+        )
+    };
+    let normal = |x: i64, z: i64| {
+        let dx = position(x + 1, z) - position(x - 1, z);
+        let dz = position(x, z + 1) - position(x, z - 1);
+        dz.cross(dx).normalize()
+    };
+
+    let mut chunks = Vec::new();
+    for chunk_z in (0..heightmap.depth.saturating_sub(1)).step_by(chunk_size as usize) {
+        for chunk_x in (0..heightmap.width.saturating_sub(1)).step_by(chunk_size as usize) {
+            let columns = chunk_size.min(heightmap.width - 1 - chunk_x);
+            let rows = chunk_size.min(heightmap.depth - 1 - chunk_z);
+            let mut positions = Vec::new();
+            let mut uvs = Vec::new();
+            let mut normals = Vec::new();
+            for z in chunk_z..=chunk_z + rows {
+                for x in chunk_x..=chunk_x + columns {
+                    let p = position(x as i64, z as i64);
+                    positions.push(p.into());
+                    uvs.push([p.x / options.texture_scale, p.z / options.texture_scale]);
+                    normals.push(normal(x as i64, z as i64).into());
+                }
+            }
+            let mut indices = Vec::new();
+            for row in 0..rows {
+                for column in 0..columns {
+                    let top_left = row * (columns + 1) + column;
+                    let bottom_left = top_left + columns + 1;
+                    indices.extend_from_slice(&[
+                        top_left,
+                        bottom_left,
+                        bottom_left + 1,
+                        top_left,
+                        bottom_left + 1,
+                        top_left + 1,
+                    ]);
+                }
+            }
+            chunks.push(ModelData::new(positions, uvs, indices).with_normals(normals));
+        }
+    }
+    chunks
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+struct TerrainUniform {
+    /// Per layer: minimum and maximum height, minimum and maximum slope.
+    layers: [[f32; 4]; MAX_LAYERS],
+    /// Layer blend width, vertical scale, number of layers.
+    params: [f32; 4],
+}
+
+/// The GPU side of a terrain: its chunks and the textures and parameters of its layers.
+pub struct Terrain {
+    chunks: Vec<Model>,
+    /// The CPU side of `chunks`, kept for picking.
+    chunk_data: Vec<ModelData>,
+    layers: Vec<Texture>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Terrain {
+    pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries: Vec<_> = (0..MAX_LAYERS as u32)
+            .map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                },
+                count: None,
+            })
+            .collect();
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: MAX_LAYERS as u32,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        });
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: MAX_LAYERS as u32 + 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        });
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some("terrain_bind_group_layout"),
+        })
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        heightmap: &Heightmap,
+        options: &TerrainOptions,
+        layers: Vec<TerrainLayer>,
+    ) -> Result<Self> {
+        let chunk_data = generate_chunks(heightmap, options);
+        let chunks = chunk_data
+            .iter()
+            .map(|chunk| Model::new(device, chunk))
+            .collect::<Result<Vec<_>>>()?;
+
+        if layers.len() > MAX_LAYERS {
+            warn!(
+                "terrain has {} layers, ignoring all but the first {}",
+                layers.len(),
+                MAX_LAYERS
+            );
+        }
+        let mut uniform = TerrainUniform {
+            layers: [[0.0; 4]; MAX_LAYERS],
+            params: [options.layer_blend, options.vertical_scale, 0.0, 0.0],
+        };
+        let mut textures = Vec::new();
+        for (i, layer) in layers.into_iter().take(MAX_LAYERS).enumerate() {
+            uniform.layers[i] = [
+                layer.heights.start,
+                layer.heights.end,
+                layer.slopes.start,
+                layer.slopes.end,
+            ];
+            uniform.params[2] += 1.0;
+            textures.push(layer.texture);
+        }
+        let white = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([255; 4]),
+        ));
+        while textures.len() < MAX_LAYERS {
+            textures.push(Texture::from_image(
+                device,
+                queue,
+                &white,
+                Some("terrain placeholder layer"),
+            )?);
+        }
+
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Terrain Buffer"),
+            contents: bytemuck::cast_slice(&[uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let mut entries: Vec<_> = textures
+            .iter()
+            .enumerate()
+            .map(|(binding, texture)| wgpu::BindGroupEntry {
+                binding: binding as u32,
+                resource: wgpu::BindingResource::TextureView(texture.view()),
+            })
+            .collect();
+        entries.push(wgpu::BindGroupEntry {
+            binding: MAX_LAYERS as u32,
+            resource: wgpu::BindingResource::Sampler(&sampler),
+        });
+        entries.push(wgpu::BindGroupEntry {
+            binding: MAX_LAYERS as u32 + 1,
+            resource: uniform_buffer.as_entire_binding(),
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &entries,
+            label: Some("terrain_bind_group"),
+        });
+
+        Ok(Self {
+            chunks,
+            chunk_data,
+            layers: textures,
+            bind_group,
+        })
+    }
+
+    pub fn chunks(&self) -> &[Model] {
+        &self.chunks
+    }
+
+    pub fn chunk_data(&self) -> &[ModelData] {
+        &self.chunk_data
+    }
+
+    pub fn layers(&self) -> &[Texture] {
+        &self.layers
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heightmap(width: u32, depth: u32, height: impl Fn(u32, u32) -> u8) -> Heightmap {
+        let img = image::GrayImage::from_fn(width, depth, |x, z| image::Luma([height(x, z)]));
+        Heightmap::from_image(&image::DynamicImage::ImageLuma8(img))
+    }
+
+    #[test]
+    fn samples_heights_clamped_to_edges() {
+        let map = heightmap(3, 2, |x, z| if (x, z) == (2, 1) { 255 } else { 0 });
+        assert_eq!((map.width(), map.depth()), (3, 2));
+        assert_eq!(map.height(0, 0), 0.0);
+        assert_eq!(map.height(2, 1), 1.0);
+        assert_eq!(map.height(5, 9), 1.0);
+        assert_eq!(map.height(-1, -1), 0.0);
+        let gray = heightmap(1, 1, |_, _| 51);
+        assert!((gray.height(0, 0) - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn chunks_cover_the_map() {
+        let options = TerrainOptions {
+            horizontal_scale: 2.0,
+            chunk_size: 4,
+            ..TerrainOptions::default()
+        };
+        // 9 by 6 quads, in chunks of 4, 4 and 1 by 4 and 2 quads.
+        let chunks = generate_chunks(&heightmap(10, 7, |x, z| (x * z) as u8), &options);
+        let quads: Vec<usize> = chunks
+            .iter()
+            .map(|chunk| {
+                assert!(chunk.validate().is_ok());
+                assert_eq!(chunk.normals().len(), chunk.positions().len());
+                chunk.indices().len() / 6
+            })
+            .collect();
+        assert_eq!(quads, [16, 16, 4, 8, 8, 2]);
+        let first = chunks[0].aabb();
+        let last = chunks[5].aabb();
+        assert_eq!([first.min.x, first.min.z], [-9.0, -6.0]);
+        assert_eq!([last.max.x, last.max.z], [9.0, 6.0]);
+        // Neighbouring chunks share their border vertices.
+        assert_eq!(chunks[0].aabb().max.x, chunks[1].aabb().min.x);
+    }
+
+    #[test]
+    fn normals_follow_the_slope() {
+        let options = TerrainOptions {
+            vertical_scale: 255.0,
+            ..TerrainOptions::default()
+        };
+        // Rises by one unit per unit along x.
+        let chunks = generate_chunks(&heightmap(5, 5, |x, _| x as u8), &options);
+        assert_eq!(chunks.len(), 1);
+        let chunk = &chunks[0];
+        let expected = Vector3::new(-1.0, 1.0, 0.0).normalize();
+        for (position, normal) in chunk.positions().iter().zip(chunk.normals()) {
+            let on_edge = position[0].abs() == 2.0;
+            let normal = Vector3::from(*normal);
+            // At the edges the clamped neighbour halves the slope.
+            if !on_edge {
+                assert!((normal - expected).magnitude() < 1e-5, "{:?}", normal);
+            }
+            assert!(normal.y > 0.0 && normal.x < 0.0);
+        }
+        for triangle in chunk.indices().chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vector3::from(chunk.positions()[triangle[i] as usize]));
+            assert!((b - a).cross(c - a).dot(expected) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// Vertex Shader
+
+struct VertexOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) uv: vec2<f32>,
+  @location(1) world_position: vec3<f32>,
+  @location(2) normal: vec3<f32>
+};
+
+struct Vertex {
+ @location(0) position: vec3<f32>,
+ @location(1) uv: vec2<f32>,
+ @location(2) normal: vec3<f32>
+};
+
+@vertex
+fn vs_main(vertex: Vertex)
+  -> VertexOutput {
+  var out: VertexOutput;
+
+  out.clip_position = camera.view_proj * vec4<f32>(vertex.position, 1.0);
+  out.uv = vertex.uv;
+  out.world_position = vertex.position;
+  out.normal = vertex.normal;
+  return out;
+}
+
+// Fragment Shader
+
+struct TerrainUniform {
+  // min height, max height, min slope, max slope
+  layers: array<vec4<f32>, 4>,
+  // blend width, vertical scale, layer count
+  params: vec4<f32>
+};
+
+@group(0) @binding(0)
+var t_layer0: texture_2d<f32>;
+@group(0) @binding(1)
+var t_layer1: texture_2d<f32>;
+@group(0) @binding(2)
+var t_layer2: texture_2d<f32>;
+@group(0) @binding(3)
+var t_layer3: texture_2d<f32>;
+@group(0) @binding(4)
+var s_layer: sampler;
+@group(0) @binding(5)
+var<uniform> terrain: TerrainUniform;
+
+// 1 inside of `range`, fading to 0 over `blend` outside of it.
+fn band(value: f32, range: vec2<f32>, blend: f32) -> f32 {
+  return smoothstep(range.x - blend, range.x, value) * (1.0 - smoothstep(range.y, range.y + blend, value));
+}
+
+fn layer_weight(index: i32, height: f32, slope: f32) -> f32 {
+  if (f32(index) >= terrain.params.z) {
+    return 0.0;
+  }
+  let layer = terrain.layers[index];
+  let blend = max(terrain.params.x, 0.0001);
+  return band(height, layer.xy, blend) * band(slope, layer.zw, blend);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let normal = normalize(in.normal);
+  let height = in.world_position.y / max(terrain.params.y, 0.0001);
+  let slope = 1.0 - normal.y;
+
+  var weights = vec4<f32>(
+    layer_weight(0, height, slope),
+    layer_weight(1, height, slope),
+    layer_weight(2, height, slope),
+    layer_weight(3, height, slope)
+  );
+  let total = weights.x + weights.y + weights.z + weights.w;
+  if (total <= 0.0) {
+    weights = vec4<f32>(1.0, 0.0, 0.0, 0.0);
+  } else {
+    weights = weights / total;
+  }
+
+  let color = textureSample(t_layer0, s_layer, in.uv) * weights.x
+    + textureSample(t_layer1, s_layer, in.uv) * weights.y
+    + textureSample(t_layer2, s_layer, in.uv) * weights.z
+    + textureSample(t_layer3, s_layer, in.uv) * weights.w;
+
+  // The layers have no other maps, so the ground is taken to be a rough dielectric.
+  var surface: Surface;
+  surface.normal = normal;
+  surface.to_eye = normalize(camera.position.xyz - in.world_position);
+  surface.diffuse_color = color.rgb;
+  surface.f0 = vec3<f32>(0.04);
+  surface.roughness = 0.9;
+  surface.alpha = surface.roughness * surface.roughness;
+  let luminance = shade(surface, in.world_position, normal);
+  return vec4<f32>(luminance, 1.0);
+}
@@ -1,19 +1,8 @@
-use anyhow::Result;
+use crate::error::{Error, Result};
 use image::GenericImageView;
-use std::error::Error;
-use std::fmt::{Display, Formatter};
-
-#[derive(Debug)]
-struct TextureError(String);
-
-impl Display for TextureError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str("Texture error: ")?;
-        f.write_str(&self.0)
-    }
-}
-
-impl Error for TextureError {}
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
 
 pub struct Texture {
     texture: wgpu::Texture,
@@ -22,6 +11,9 @@ pub struct Texture {
 }
 
 impl Texture {
+    /// Depth with a stencil, which marks the pixels of selected objects for outlining.
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
+
     pub fn texture(&self) -> &wgpu::Texture {
         &self.texture
     }
@@ -38,19 +30,66 @@ impl Texture {
         bytes: &[u8],
         label: &str,
     ) -> Result<Self> {
-        let img = image::load_from_memory(bytes)?;
+        let img = image::load_from_memory(bytes).map_err(|source| Error::ImageDecode {
+            name: label.to_string(),
+            source,
+        })?;
         Self::from_image(device, queue, &img, Some(label))
     }
 
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: &Path) -> Result<Self> {
+        Self::load_with_format(device, queue, path, wgpu::TextureFormat::Rgba8UnormSrgb)
+    }
+
+    /// Loads the image at `path` into a texture of `format`, which should be
+    /// [`wgpu::TextureFormat::Rgba8Unorm`] for images holding data rather than colors, such as
+    /// normal maps.
+    pub fn load_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        path: &Path,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let file = File::open(path).map_err(|err| Error::io(path, err))?;
+        let image_format =
+            image::ImageFormat::from_path(path).map_err(|source| Error::ImageDecode {
+                name: path.display().to_string(),
+                source,
+            })?;
+        let img = image::load(BufReader::new(file), image_format).map_err(|source| {
+            Error::ImageDecode {
+                name: path.display().to_string(),
+                source,
+            }
+        })?;
+        let label = path.file_name().and_then(|name| name.to_str());
+        Self::from_image_with_format(device, queue, &img, label, format)
+    }
+
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
     ) -> Result<Self> {
-        let rgba = img.as_rgba8().ok_or(TextureError(
-            "could not get image pixels as rgba8".to_string(),
-        ))?;
+        Self::from_image_with_format(
+            device,
+            queue,
+            img,
+            label,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+        )
+    }
+
+    /// Uploads `img` into a texture of `format`, an 8 bit RGBA format.
+    pub fn from_image_with_format(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+    ) -> Result<Self> {
+        let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -65,7 +104,7 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            format,
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
@@ -76,7 +115,7 @@ impl Texture {
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            rgba,
+            &rgba,
             wgpu::ImageDataLayout {
                 offset: 0,
                 bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
@@ -101,4 +140,50 @@ impl Texture {
             sampler,
         })
     }
+
+    /// Creates a depth texture with `sample_count` samples per pixel, for render passes drawing
+    /// with that many.
+    pub fn create_depth_texture(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        // Multisampled textures can't be sampled with `sampler`, so they are only attachments.
+        let usage = if sample_count == 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
 }
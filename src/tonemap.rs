@@ -0,0 +1,66 @@
+//! Mapping the HDR colors the scene is rendered with into the range of the frame.
+
+/// The format the scene is rendered into before tonemapping.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// The curve compressing HDR colors into the displayable range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemap {
+    /// Clips colors above 1.
+    None,
+    /// `x / (1 + x)`, which keeps colors but washes out bright ones.
+    Reinhard,
+    /// The filmic curve of the Academy Color Encoding System, with more contrast.
+    Aces,
+}
+
+impl Tonemap {
+    fn id(self) -> u32 {
+        match self {
+            Tonemap::None => 0,
+            Tonemap::Reinhard => 1,
+            Tonemap::Aces => 2,
+        }
+    }
+}
+
+/// How the HDR scene is turned into the colors of the frame, after it is exposed by
+/// [`Lights::ev100`](crate::light::Lights::ev100).
+#[derive(Clone, Copy, Debug)]
+pub struct Tonemapping {
+    /// Applies to the whole scene, so curves other than [`Tonemap::None`] also change the colors
+    /// of unlit models, the sky and the grid.
+    pub operator: Tonemap,
+    /// Brightens mid tones above 1 and darkens them below, after `operator`. The frame is
+    /// encoded as sRGB in addition.
+    pub gamma: f32,
+}
+
+impl Default for Tonemapping {
+    fn default() -> Self {
+        Self {
+            operator: Tonemap::None,
+            gamma: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Zeroable, bytemuck::Pod)]
+pub(crate) struct TonemapUniform {
+    /// 1 / gamma, exposure.
+    params: [f32; 4],
+    /// Operator, whether the shader encodes sRGB.
+    mode: [u32; 4],
+}
+
+impl TonemapUniform {
+    /// The uniform of the tonemapping pass, multiplying the scene by `exposure` and encoding sRGB
+    /// in the shader if `encode_srgb`.
+    pub fn new(tonemapping: &Tonemapping, exposure: f32, encode_srgb: bool) -> Self {
+        Self {
+            params: [1.0 / tonemapping.gamma.max(0.01), exposure, 0.0, 0.0],
+            mode: [tonemapping.operator.id(), encode_srgb as u32, 0, 0],
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Maps the HDR scene into the displayable range of the frame.
+
+struct TonemapUniform {
+  // 1 / gamma, exposure
+  params: vec4<f32>,
+  // operator: 0 none, 1 Reinhard, 2 ACES; 1 if the target doesn't encode sRGB itself
+  mode: vec4<u32>
+};
+
+@group(1) @binding(0)
+var<uniform> tonemap: TonemapUniform;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve.
+fn aces(x: vec3<f32>) -> vec3<f32> {
+  let mapped = (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+  return clamp(mapped, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn reinhard(x: vec3<f32>) -> vec3<f32> {
+  return x / (1.0 + x);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let scene = textureSample(t_input, s_input, in.uv);
+  var color = max(scene.rgb * tonemap.params.y, vec3<f32>(0.0));
+  switch (tonemap.mode.x) {
+    case 1u: {
+      color = reinhard(color);
+    }
+    case 2u: {
+      color = aces(color);
+    }
+    default: {
+      color = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    }
+  }
+  color = pow(color, vec3<f32>(tonemap.params.x));
+  if (tonemap.mode.y != 0u) {
+    color = linear_to_srgb(color);
+  }
+  return vec4<f32>(color, 1.0);
+}
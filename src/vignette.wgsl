@@ -0,0 +1,19 @@
+// Darkens the corners of the image.
+
+struct VignetteUniform {
+  // intensity, radius, softness
+  params: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> vignette: VignetteUniform;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+  let color = textureSample(t_input, s_input, in.uv);
+  // 0 in the center, 1 in the corners.
+  let distance = length(in.uv - 0.5) * sqrt(2.0);
+  let radius = vignette.params.y;
+  let darkening = smoothstep(radius, radius + vignette.params.z, distance) * vignette.params.x;
+  return vec4<f32>(color.rgb * (1.0 - darkening), color.a);
+}